@@ -0,0 +1,85 @@
+use crate::subtitle::Cue;
+
+/// Overlap of two cues as a share of the shorter one, from 0.0 to 1.0.
+fn overlap_ratio(a: &Cue, b: &Cue) -> f64 {
+    let start = a.start.max(b.start).as_millis();
+    let end = a.end.min(b.end).as_millis();
+    let shorter = a.duration_ms().min(b.duration_ms());
+    if end <= start || shorter <= 0 {
+        return 0.0;
+    }
+    (end - start) as f64 / shorter as f64
+}
+
+/// Pairs source and target cues that overlap by at least `min_overlap` of the shorter cue,
+/// keeping only one-to-one pairs: a cue merged or split in the other file overlaps several
+/// cues there and is left out. Both lists must be sorted by start time.
+pub fn align_cues(source: &[Cue], target: &[Cue], min_overlap: f64) -> Vec<(usize, usize)> {
+    let only_match = |cue: &Cue, others: &[Cue]| -> Option<usize> {
+        // Only cues starting before this one ends can overlap it.
+        let first = others.partition_point(|other| other.end <= cue.start);
+        let mut matches = others[first..]
+            .iter()
+            .take_while(|other| other.start < cue.end)
+            .enumerate()
+            .filter(|(_, other)| overlap_ratio(cue, other) >= min_overlap)
+            .map(|(offset, _)| first + offset);
+        match (matches.next(), matches.next()) {
+            (Some(position), None) => Some(position),
+            _ => None,
+        }
+    };
+
+    source
+        .iter()
+        .enumerate()
+        .filter_map(|(source_position, cue)| {
+            let target_position = only_match(cue, target)?;
+            (only_match(&target[target_position], source) == Some(source_position))
+                .then_some((source_position, target_position))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subtitle::Timestamp;
+
+    fn cue(start: u64, end: u64) -> Cue {
+        Cue {
+            start: Timestamp(start),
+            end: Timestamp(end),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pairs_shifted_cues() {
+        let source = [cue(1_000, 3_000), cue(4_000, 6_000), cue(7_000, 9_000)];
+        let target = [cue(1_300, 3_200), cue(4_200, 6_300), cue(7_100, 8_800)];
+        assert_eq!(align_cues(&source, &target, 0.5), vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn skips_merged_and_split_cues() {
+        // Source cues 0 and 1 were merged into target cue 0; source cue 2 was split into
+        // target cues 1 and 2.
+        let source = [cue(1_000, 2_000), cue(2_000, 3_000), cue(4_000, 6_000), cue(7_000, 8_000)];
+        let target = [cue(1_000, 3_000), cue(4_000, 5_000), cue(5_000, 6_000), cue(7_000, 8_000)];
+        assert_eq!(align_cues(&source, &target, 0.5), vec![(3, 3)]);
+    }
+
+    #[test]
+    fn skips_cues_overlapping_too_little() {
+        let source = [cue(1_000, 3_000)];
+        let target = [cue(2_500, 4_500)];
+        assert!(align_cues(&source, &target, 0.5).is_empty());
+        assert_eq!(align_cues(&source, &target, 0.2), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn zero_length_cues_never_match() {
+        assert!(align_cues(&[cue(1_000, 1_000)], &[cue(1_000, 1_000)], 0.0).is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt,
+    sync::LazyLock,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::logger::Logger;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("CircuitBreaker"));
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TripReason {
+    /// Several files in a row failed on RESOURCE_EXHAUSTED even after their retries.
+    QuotaExhausted,
+    /// The API rejected the key or the request itself; no later file can succeed either.
+    FatalError,
+}
+
+impl fmt::Display for TripReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TripReason::QuotaExhausted => write!(f, "the API quota is exhausted"),
+            TripReason::FatalError => write!(f, "the API rejected the key or request"),
+        }
+    }
+}
+
+/// Run-wide state that stops a batch from starting new files once failures show that
+/// every further request would fail too.
+pub struct CircuitBreaker {
+    quota_failure_threshold: u32,
+    consecutive_quota_failures: u32,
+    tripped: Option<TripReason>,
+}
+
+impl CircuitBreaker {
+    pub fn new(quota_failure_threshold: u32) -> Self {
+        CircuitBreaker {
+            quota_failure_threshold: quota_failure_threshold.max(1),
+            consecutive_quota_failures: 0,
+            tripped: None,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_quota_failures = 0;
+    }
+
+    pub fn record_quota_failure(&mut self) {
+        self.consecutive_quota_failures += 1;
+        if self.consecutive_quota_failures >= self.quota_failure_threshold {
+            self.tripped = Some(TripReason::QuotaExhausted);
+        }
+    }
+
+    pub fn record_fatal_error(&mut self) {
+        self.tripped = Some(TripReason::FatalError);
+    }
+
+    pub fn tripped(&self) -> Option<TripReason> {
+        self.tripped
+    }
+
+    pub fn reset(&mut self) {
+        self.consecutive_quota_failures = 0;
+        self.tripped = None;
+    }
+}
+
+/// Parses a quota reset time written as `HH:MM` in UTC into seconds after midnight.
+pub fn parse_reset_time(value: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid quota reset time '{}'; expected HH:MM (UTC).", value);
+    let (hours, minutes) = value.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u64 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u64 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok((hours * 60 + minutes) * 60)
+}
+
+/// Seconds from `now` (Unix time) to the next time of day `reset_seconds` after midnight UTC;
+/// a full day when `now` is exactly at the reset.
+fn seconds_until_reset(now: u64, reset_seconds: u64) -> u64 {
+    let seconds_today = now % SECONDS_PER_DAY;
+    if reset_seconds > seconds_today {
+        reset_seconds - seconds_today
+    } else {
+        SECONDS_PER_DAY - seconds_today + reset_seconds
+    }
+}
+
+/// Sleeps until the next occurrence of `reset_time` (`HH:MM` UTC).
+pub fn wait_for_quota_reset(reset_time: &str) -> Result<(), String> {
+    let reset_seconds = parse_reset_time(reset_time)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| format!("System clock is before 1970: {}", e))?
+        .as_secs();
+    let wait_seconds = seconds_until_reset(now, reset_seconds);
+
+    LOGGER.bold_message_warning(
+        format!(
+            "Waiting {}h {:02}m until the quota resets at {} UTC...",
+            wait_seconds / 3600,
+            (wait_seconds / 60) % 60,
+            reset_time.trim()
+        )
+        .as_str(),
+    );
+    // A minute of slack so requests do not hit the old window right at the boundary.
+    thread::sleep(Duration::from_secs(wait_seconds + 60));
+    LOGGER.info("Quota reset time reached; resuming.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trips_after_consecutive_quota_failures() {
+        let mut breaker = CircuitBreaker::new(2);
+        breaker.record_quota_failure();
+        breaker.record_success();
+        breaker.record_quota_failure();
+        assert_eq!(breaker.tripped(), None);
+        breaker.record_quota_failure();
+        assert_eq!(breaker.tripped(), Some(TripReason::QuotaExhausted));
+
+        breaker.reset();
+        assert_eq!(breaker.tripped(), None);
+        breaker.record_quota_failure();
+        assert_eq!(breaker.tripped(), None);
+    }
+
+    #[test]
+    fn zero_threshold_trips_on_the_first_failure() {
+        let mut breaker = CircuitBreaker::new(0);
+        breaker.record_quota_failure();
+        assert_eq!(breaker.tripped(), Some(TripReason::QuotaExhausted));
+    }
+
+    #[test]
+    fn fatal_error_trips_at_once() {
+        let mut breaker = CircuitBreaker::new(5);
+        breaker.record_fatal_error();
+        assert_eq!(breaker.tripped(), Some(TripReason::FatalError));
+    }
+
+    #[test]
+    fn parses_reset_times() {
+        assert_eq!(parse_reset_time("00:00"), Ok(0));
+        assert_eq!(parse_reset_time(" 07:30 "), Ok(27_000));
+        assert_eq!(parse_reset_time("23:59"), Ok(SECONDS_PER_DAY - 60));
+        for malformed in ["", "7", "24:00", "12:60", "ab:cd", "12:30:00", "-1:00", "12-30"] {
+            assert!(parse_reset_time(malformed).is_err(), "accepted '{}'", malformed);
+        }
+    }
+
+    #[test]
+    fn waits_until_the_next_reset_across_midnight() {
+        let day = 20_000 * SECONDS_PER_DAY;
+        let eight = 8 * 3600;
+        assert_eq!(seconds_until_reset(day + 7 * 3600, eight), 3600);
+        assert_eq!(seconds_until_reset(day + 9 * 3600, eight), 23 * 3600);
+        assert_eq!(seconds_until_reset(day + eight, eight), SECONDS_PER_DAY);
+        assert_eq!(seconds_until_reset(day + SECONDS_PER_DAY - 60, 0), 60);
+        assert_eq!(seconds_until_reset(day + 60, SECONDS_PER_DAY - 60), SECONDS_PER_DAY - 120);
+    }
+}
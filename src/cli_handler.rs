@@ -1,10 +1,18 @@
 use std::{
-    io::{self, Write},
+    io::{self, IsTerminal, Write},
     path::PathBuf,
     sync::LazyLock,
 };
 
-use crate::logger::Logger;
+use crate::config;
+use crate::credentials::StoreKind;
+use crate::formats::SubtitleFormat;
+use crate::logger::{self, Logger};
+use crate::parser::BilingualOptions;
+use crate::timing::TimingAdjustment;
+use crate::translator::{Backend, GenerationSettings, TranslationOptions};
+use crate::utils::{self, LanguagePair};
+use crate::validator::ValidationOptions;
 
 static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("CLI_Handler"));
 
@@ -16,6 +24,11 @@ pub fn get_user_path_input() -> Result<String, String> {
 
     let mut terminal_input_buffer = String::new();
     match io::stdin().read_line(&mut terminal_input_buffer) {
+        Ok(0) => {
+            // Closed stdin (e.g. piped or detached runs) would otherwise re-prompt forever.
+            LOGGER.warning("No more input. Exiting...");
+            Err("exit".to_string())
+        }
         Ok(_) => {
             let terminal_input = terminal_input_buffer.trim();
 
@@ -65,11 +78,25 @@ pub fn get_user_path_input() -> Result<String, String> {
 pub fn get_api_key_input() -> Result<String, String> {
     LOGGER.bold("🔑 Please enter your Gemini API key to use the translation service:");
     LOGGER.info("You can obtain your API key from https://aistudio.google.com/app/apikey");
+    LOGGER.info("The key is not shown while you type.");
     LOGGER.log("Enter Gemini API Key: ");
     io::stdout().flush().unwrap();
 
     let mut api_key_buffer = String::new();
-    match io::stdin().read_line(&mut api_key_buffer) {
+    let read_result = if io::stdin().is_terminal() {
+        rpassword::read_password().map(|api_key| {
+            api_key_buffer = api_key;
+            // Hidden input returns on Enter; report it as a non-empty read like read_line does.
+            api_key_buffer.len() + 1
+        })
+    } else {
+        io::stdin().read_line(&mut api_key_buffer)
+    };
+    match read_result {
+        Ok(0) => {
+            LOGGER.warning("No more input. Exiting...");
+            Err("exit".to_string())
+        }
         Ok(_) => {
             let api_key = api_key_buffer.trim();
             if api_key.is_empty() {
@@ -80,6 +107,7 @@ pub fn get_api_key_input() -> Result<String, String> {
                 LOGGER.warning("Exiting program as per user request...");
                 return Err("exit".to_string());
             }
+            logger::register_secret(api_key);
             Ok(api_key.to_string())
         }
         Err(error) => {
@@ -97,6 +125,7 @@ pub fn get_max_line_length_input() -> usize {
     loop {
         let mut max_length_buffer = String::new();
         match io::stdin().read_line(&mut max_length_buffer) {
+            Ok(0) => return 55,
             Ok(_) => {
                 let max_length_str = max_length_buffer.trim();
                 if max_length_str.is_empty() {
@@ -139,3 +168,837 @@ pub fn get_max_line_length_input() -> usize {
         }
     }
 }
+
+pub fn get_repair_input() -> bool {
+    LOGGER.bold("🛠️  Repair structurally broken subtitle files before translating? (y/N)");
+    LOGGER.info("Renumbers and sorts cues, trims overlaps, merges duplicates, removes empty cues and normalizes timestamps. The original is kept in the backup folder.");
+    LOGGER.log("Repair sources (y/N): ");
+    io::stdout().flush().unwrap();
+
+    let mut choice_buffer = String::new();
+    match io::stdin().read_line(&mut choice_buffer) {
+        Ok(_) => {
+            let repair = matches!(choice_buffer.trim().to_lowercase().as_str(), "y" | "yes");
+            if repair {
+                LOGGER.success("Source subtitles will be repaired before translation.");
+            }
+            repair
+        }
+        Err(error) => {
+            LOGGER.error(&format!("Error reading input: {}. Sources will not be repaired.", error));
+            false
+        }
+    }
+}
+
+pub fn get_bilingual_input(max_line_length: usize) -> Option<BilingualOptions> {
+    LOGGER.bold("🌐 Also write bilingual subtitles with the original and Persian text in each cue?");
+    LOGGER.info("Enter 'en' to show English on top, 'fa' to show Persian on top, or leave empty to skip.");
+    LOGGER.log("Bilingual output (en/fa/empty): ");
+    io::stdout().flush().unwrap();
+
+    let mut order_buffer = String::new();
+    if let Err(error) = io::stdin().read_line(&mut order_buffer) {
+        LOGGER.error(&format!("Error reading input: {}. Bilingual output disabled.", error));
+        return None;
+    }
+    let original_first = match order_buffer.trim().to_lowercase().as_str() {
+        "en" | "english" => true,
+        "fa" | "persian" => false,
+        _ => return None,
+    };
+
+    LOGGER.log("Colours for the English and Persian lines, e.g. '#c0c0c0 #ffff00' (leave empty for none): ");
+    io::stdout().flush().unwrap();
+    let mut colors_buffer = String::new();
+    let mut colors = Vec::new();
+    if io::stdin().read_line(&mut colors_buffer).is_ok() {
+        colors = colors_buffer
+            .split_whitespace()
+            .filter(|color| is_valid_color(color))
+            .map(str::to_string)
+            .collect();
+    }
+    let mut colors = colors.into_iter();
+
+    LOGGER.log(format!("Max line length of the original text (default {}): ", max_line_length).as_str());
+    io::stdout().flush().unwrap();
+    let mut length_buffer = String::new();
+    let mut original_max_line_length = max_line_length;
+    if io::stdin().read_line(&mut length_buffer).is_ok() && !length_buffer.trim().is_empty() {
+        match length_buffer.trim().parse::<usize>() {
+            Ok(length) if length > 0 => original_max_line_length = length,
+            _ => LOGGER.warning(
+                format!("Invalid line length. Using {} for the original text.", max_line_length).as_str(),
+            ),
+        }
+    }
+
+    LOGGER.success("Bilingual subtitles will be written next to each translation.");
+    Some(BilingualOptions {
+        original_first,
+        original_color: colors.next(),
+        translation_color: colors.next(),
+        original_max_line_length,
+    })
+}
+
+/// Accepts `#rrggbb` style hex colours or plain colour names for `<font color>`.
+pub fn is_valid_color(color: &str) -> bool {
+    let name = color.strip_prefix('#').unwrap_or(color);
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+pub fn get_timing_adjustments_input() -> Vec<TimingAdjustment> {
+    LOGGER.bold("⏱️  Optionally enter timing adjustments to apply before translation (leave empty for none):");
+    LOGGER.info("Examples: 'shift:+1500' (ms), 'fps:25:23.976', 'stretch:00:00:10,000=00:00:11,200;01:30:00,000=01:30:04,800'. Separate several with spaces.");
+    LOGGER.log("Timing adjustments: ");
+    io::stdout().flush().unwrap();
+
+    loop {
+        let mut adjustments_buffer = String::new();
+        match io::stdin().read_line(&mut adjustments_buffer) {
+            Ok(_) => {
+                let specs: Vec<&str> = adjustments_buffer.split_whitespace().collect();
+                match specs
+                    .iter()
+                    .map(|spec| TimingAdjustment::parse(spec))
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(adjustments) => {
+                        for adjustment in &adjustments {
+                            LOGGER.success(format!("Timing adjustment added: {}.", adjustment).as_str());
+                        }
+                        return adjustments;
+                    }
+                    Err(e) => {
+                        LOGGER.warning(format!("{} Please try again or leave empty for none.", e).as_str());
+                        LOGGER.log("Timing adjustments: ");
+                        io::stdout().flush().unwrap();
+                    }
+                }
+            }
+            Err(error) => {
+                LOGGER.error(&format!(
+                    "Error reading input: {}. No timing adjustments will be applied.",
+                    error
+                ));
+                return Vec::new();
+            }
+        }
+    }
+}
+
+pub const USAGE: &str = "Usage: ai_translate [<command> [options]]
+
+Run without arguments for the interactive mode.
+
+Global options:
+  --config <file>             Use this config file instead of ./ai_translate.toml
+  --set <key>=<value>         Override a config key for this run; may be repeated
+
+Commands:
+  translate <file-or-folder>  Translate subtitles
+      --api-key-file <file>            Read API keys from a file, one per line
+      --source-lang <code>             Language of the source subtitles (config: source_language)
+      --target-lang <code>             Language to translate into (config: target_language)
+      --backend <name>                 Translation backend (config: backend)
+      --max-line-length <n>            Maximum line length of the output (config: max_line_length)
+      --timing <adjustment>            Retime before translating; may be repeated
+      --repair                         Repair broken sources before translating
+      --overwrite                      Translate again even if a translation exists
+      --bilingual <order>              Also write a bilingual file: original-first or translation-first
+      --original-color <color>         Colour of the original lines in the bilingual file
+      --translation-color <color>      Colour of the translated lines in the bilingual file
+      --original-max-line-length <n>   Line length of the original lines in the bilingual file
+      --model <name>                   Model to use (config: gemini.model)
+      --temperature <t>, --top-p <p>, --top-k <k>, --max-output-tokens <n>
+                                       Sampling parameters (config: gemini.<name>)
+      --thinking-budget <n>            Thinking tokens for thinking models; 0 off, -1 dynamic
+      --safety-threshold <level>       BLOCK_NONE, BLOCK_ONLY_HIGH, BLOCK_MEDIUM_AND_ABOVE,
+                                       BLOCK_LOW_AND_ABOVE or OFF for every harm category
+      --structured-output              Ask for JSON {id, text} items instead of numbered lines
+                                       (config: gemini.structured_output)
+      --context-cache                  Cache the instructions once per run instead of resending
+                                       them with every chunk; skipped while they are below the
+                                       model's cache minimum, which is usually the case
+                                       (config: gemini.context_cache)
+      --fallback-model <model>         Model (or backend:model) to retry failing chunks with;
+                                       may be repeated (config: fallback_models)
+      --context-lines <n>              Previous lines sent as read-only context (config: context_lines)
+      --rolling-summary                Keep a story summary for context; one extra request per chunk
+                                       (config: rolling_summary)
+      --glossary <file>                Project glossary; glossary.toml files in the input folders
+                                       add to and override it (config: glossary_file)
+      --no-memory                      Neither fill lines from nor add them to the translation
+                                       memory (config: translation_memory)
+      --no-dedup                       Send repeated lines of a file every time instead of once
+                                       (config: deduplicate_lines)
+      --memory-examples <n>            Show the model up to n earlier translations of similar lines
+                                       from the translation memory (config: memory_examples)
+      --chunk-tokens <n>               Estimated output tokens per chunk; 0 sizes chunks from the
+                                       model's output limit (config: chunk_output_tokens)
+      --count-tokens                   Calibrate chunk sizes with the countTokens endpoint first
+                                       (config: count_tokens)
+      --concurrency <n>                Chunks of a file in flight at once; see concurrent_requests in
+                                       the config for the context this costs (config:
+                                       concurrent_requests)
+      --rpm <n>, --tpm <n>             Requests and estimated tokens allowed per minute for the
+                                       whole run; 0 for no limit (config: requests_per_minute,
+                                       tokens_per_minute)
+      --report <file>                  Where to write the run report (default: next to the input)
+  glossary <file-or-folder>   Propose a glossary of recurring names and terms for review
+      --source-lang <code>, --target-lang <code>, --backend <name>, --model <name>
+      --api-key-file <file>
+      --min-count <n>                  Occurrences a term needs to be proposed (default: 3)
+      --max-terms <n>                  Most frequent terms to propose (default: 100)
+      --output <file>                  Where to write it (default: glossary.toml in the input
+                                       folder, which translate runs pick up); terms already in
+                                       the file are kept as they are
+      --list                           Only list the candidates, without asking the model
+  memory <action>             Manage the translation memory of earlier runs
+      stats | search <text> | export <file> | import <file>
+      import-pairs <source> <target> [--min-overlap <percent>]
+                                       Align a subtitle and its human translation (or two
+                                       folders of them) by timing and store the line pairs
+      prune [--older-than <days>] [--unused]
+                                       Remove entries; --source-lang/--target-lang restrict
+                                       search and prune to a language and give the languages
+                                       of import-pairs
+  scan <file-or-folder>       List the subtitles a translate run would pick up
+  status <file-or-folder>     Show which subtitles are translated and which are pending
+      --source-lang <code>, --target-lang <code>
+  validate <file-or-folder>   Check subtitles for structural problems
+      --max-line-length <n>, --max-cps <n>, --report <file>
+  convert <file-or-folder>    Convert between subtitle formats
+      --to <srt|vtt|ass|sbv|ttml>, --from <format>
+  fix <file-or-folder>        Repair broken SRT files in place
+  timing <file-or-folder> <adjustment>...
+                              Retime SRT files in place (e.g. shift:-1200 fps:25:23.976)
+  login [--backend <name>] [--api-key-file <file>] [--plain-file]
+                              Save API keys in the system keychain; --plain-file saves
+                              them unencrypted in credentials.toml instead
+  logout [--backend <name>]   Remove the saved API keys
+  config                      Print the effective configuration
+  help                        Show this message
+
+Configuration is read from the user config folder (ai_translate/ai_translate.toml),
+then ./ai_translate.toml, then AI_TRANSLATE_<KEY> environment variables, then --set.
+Model settings live in the [gemini] section; override them with --set gemini.<key>=<value>
+or AI_TRANSLATE_GEMINI_<KEY>.
+API keys are taken from --api-key-file, GEMINI_API_KEY, the system keychain
+or the credentials.toml file written by login --plain-file, in that order, and one is
+prompted for (hidden) when none is set. Several keys (e.g. from different projects) may
+be given as separate lines in a key file, written `label=key` to name them, or
+comma-separated in GEMINI_API_KEY; a key that hits its quota rests for key_cooldown_ms
+while the next one is used.
+A glossary is a TOML file of [[term]] tables with source, target and optionally
+case_sensitive and notes; terms found in a chunk are sent with it, and translated lines
+missing the required rendering are listed in the run report.";
+
+pub struct TranslateArgs {
+    pub path: String,
+    pub api_key_file: Option<PathBuf>,
+    pub max_line_length: usize,
+    pub translation: TranslationOptions,
+    pub timing_adjustments: Vec<TimingAdjustment>,
+    pub repair: bool,
+    pub overwrite: bool,
+    pub bilingual: Option<BilingualOptions>,
+    /// Where to write the run report; next to the input when not given.
+    pub report_path: Option<PathBuf>,
+    /// Project glossary applied below the folder glossaries.
+    pub glossary_file: Option<PathBuf>,
+    /// Fill and extend the translation memory.
+    pub use_memory: bool,
+}
+
+/// Parses `translate <file-or-folder> [options]`; see [`USAGE`] for the flags.
+pub fn parse_translate_args(args: &[String]) -> Result<TranslateArgs, String> {
+    let usage = "Usage: ai_translate translate <file-or-folder> [options] (see 'ai_translate help')";
+    let config = config::get();
+    let mut path = None;
+    let mut api_key_file = None;
+    let mut max_line_length = config.max_line_length;
+    let mut translation = TranslationOptions::from_config(config);
+    let mut timing_adjustments = Vec::new();
+    let mut repair = false;
+    let mut overwrite = false;
+    let mut bilingual_order = None;
+    let mut original_color = None;
+    let mut translation_color = None;
+    let mut original_max_line_length = None;
+    let mut report_path = None;
+    let mut glossary_file = (!config.glossary_file.is_empty()).then(|| PathBuf::from(&config.glossary_file));
+    let mut use_memory = config.translation_memory;
+    let mut generation_flags = Vec::new();
+    let mut fallback_models = Vec::new();
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--api-key-file" => api_key_file = Some(PathBuf::from(next_value(&mut args_iter, arg)?)),
+            "--source-lang" => {
+                translation.languages.source = parse_language(next_value(&mut args_iter, arg)?)?
+            }
+            "--target-lang" => {
+                translation.languages.target = parse_language(next_value(&mut args_iter, arg)?)?
+            }
+            "--backend" => {
+                let value = next_value(&mut args_iter, arg)?;
+                translation.backend = Backend::from_name(value)
+                    .ok_or_else(|| format!("Unsupported backend '{}'. Available: gemini.", value))?;
+            }
+            "--max-line-length" => max_line_length = parse_line_length(next_value(&mut args_iter, arg)?, arg)?,
+            "--original-max-line-length" => {
+                original_max_line_length = Some(parse_line_length(next_value(&mut args_iter, arg)?, arg)?)
+            }
+            "--timing" => timing_adjustments.push(TimingAdjustment::parse(next_value(&mut args_iter, arg)?)?),
+            "--model" | "--temperature" | "--top-p" | "--top-k" | "--max-output-tokens"
+            | "--thinking-budget" | "--safety-threshold" => {
+                generation_flags.push((arg.as_str(), next_value(&mut args_iter, arg)?.as_str()))
+            }
+            "--fallback-model" => fallback_models.push(next_value(&mut args_iter, arg)?.clone()),
+            "--structured-output" | "--context-cache" => generation_flags.push((arg.as_str(), "true")),
+            "--context-lines" => {
+                let value = next_value(&mut args_iter, arg)?;
+                translation.context_lines = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}; expected a number of lines.", arg))?;
+            }
+            "--rolling-summary" => translation.rolling_summary = true,
+            "--memory-examples" => {
+                let value = next_value(&mut args_iter, arg)?;
+                translation.memory_examples = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}; expected a number of examples.", arg))?;
+            }
+            "--report" => report_path = Some(PathBuf::from(next_value(&mut args_iter, arg)?)),
+            "--glossary" => glossary_file = Some(PathBuf::from(next_value(&mut args_iter, arg)?)),
+            "--no-memory" => use_memory = false,
+            "--no-dedup" => translation.deduplicate_lines = false,
+            "--chunk-tokens" => {
+                let value = next_value(&mut args_iter, arg)?;
+                translation.chunk_output_tokens = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}; expected a number of tokens.", arg))?;
+            }
+            "--count-tokens" => translation.count_tokens = true,
+            "--concurrency" => {
+                let value = next_value(&mut args_iter, arg)?;
+                translation.concurrent_requests = value
+                    .parse()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| format!("Invalid value for {}; expected a number of requests of at least 1.", arg))?;
+            }
+            "--rpm" | "--tpm" => {
+                let value = next_value(&mut args_iter, arg)?;
+                let limit = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}; expected a number per minute.", arg))?;
+                if arg == "--rpm" {
+                    translation.requests_per_minute = limit;
+                } else {
+                    translation.tokens_per_minute = limit;
+                }
+            }
+            "--repair" => repair = true,
+            "--overwrite" => overwrite = true,
+            "--bilingual" => {
+                bilingual_order = Some(match next_value(&mut args_iter, arg)?.as_str() {
+                    "original-first" => true,
+                    "translation-first" => false,
+                    other => {
+                        return Err(format!(
+                            "Invalid value '{}' for --bilingual; use original-first or translation-first.",
+                            other
+                        ))
+                    }
+                })
+            }
+            "--original-color" | "--translation-color" => {
+                let color = next_value(&mut args_iter, arg)?;
+                if !is_valid_color(color) {
+                    return Err(format!("Invalid colour '{}' for {}.", color, arg));
+                }
+                if arg == "--original-color" {
+                    original_color = Some(color.clone());
+                } else {
+                    translation_color = Some(color.clone());
+                }
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option '{}'. {}", other, usage))
+            }
+            other if path.is_none() => path = Some(other.to_string()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let path = path.ok_or_else(|| usage.to_string())?;
+    if !PathBuf::from(&path).exists() {
+        return Err(format!("The specified path '{}' does not exist.", path));
+    }
+    if let Some(file) = glossary_file.as_ref().filter(|file| !file.is_file()) {
+        return Err(format!("The glossary file '{}' does not exist.", file.display()));
+    }
+    if translation.languages.source.eq_ignore_ascii_case(&translation.languages.target) {
+        return Err("Source and target languages must differ.".to_string());
+    }
+    // Flags apply on top of the chosen backend's section, whatever order they came in.
+    translation.generation = config.generation_settings(translation.backend).clone();
+    for (flag, value) in generation_flags {
+        apply_generation_flag(&mut translation.generation, flag, value)?;
+    }
+    let problems = translation.generation.problems(&translation.backend.to_string());
+    if !problems.is_empty() {
+        return Err(format!("Invalid generation settings: {}.", problems.join("; ")));
+    }
+    if fallback_models.is_empty() {
+        fallback_models = config.fallback_models.clone();
+    }
+    translation
+        .set_fallbacks(&fallback_models, config)
+        .map_err(|e| format!("Invalid --fallback-model: {}.", e))?;
+    let bilingual = match bilingual_order {
+        Some(original_first) => Some(BilingualOptions {
+            original_first,
+            original_color,
+            translation_color,
+            original_max_line_length: original_max_line_length.unwrap_or(max_line_length),
+        }),
+        None if original_color.is_some()
+            || translation_color.is_some()
+            || original_max_line_length.is_some() =>
+        {
+            return Err("Bilingual colour and line length options require --bilingual.".to_string())
+        }
+        None => None,
+    };
+
+    Ok(TranslateArgs {
+        path,
+        api_key_file,
+        max_line_length,
+        translation,
+        timing_adjustments,
+        repair,
+        overwrite,
+        bilingual,
+        report_path,
+        glossary_file,
+        use_memory,
+    })
+}
+
+fn apply_generation_flag(settings: &mut GenerationSettings, flag: &str, value: &str) -> Result<(), String> {
+    let invalid = || format!("Invalid value '{}' for {}.", value, flag);
+    match flag {
+        "--model" => settings.model = value.to_string(),
+        "--temperature" => settings.temperature = value.parse().map_err(|_| invalid())?,
+        "--top-p" => settings.top_p = Some(value.parse().map_err(|_| invalid())?),
+        "--top-k" => settings.top_k = Some(value.parse().map_err(|_| invalid())?),
+        "--max-output-tokens" => settings.max_output_tokens = value.parse().map_err(|_| invalid())?,
+        "--thinking-budget" => settings.thinking_budget = Some(value.parse().map_err(|_| invalid())?),
+        "--safety-threshold" => settings.safety_threshold = Some(value.to_ascii_uppercase()),
+        "--structured-output" => settings.structured_output = true,
+        "--context-cache" => settings.context_cache = true,
+        _ => return Err(format!("Unknown option '{}'.", flag)),
+    }
+    Ok(())
+}
+
+pub struct ScanArgs {
+    pub path: String,
+    pub languages: LanguagePair,
+}
+
+/// Parses `scan` and `status`: `<command> <file-or-folder> [--source-lang CODE] [--target-lang CODE]`.
+pub fn parse_scan_args(command: &str, args: &[String]) -> Result<ScanArgs, String> {
+    let usage = format!(
+        "Usage: ai_translate {} <file-or-folder> [--source-lang <code>] [--target-lang <code>]",
+        command
+    );
+    let mut path = None;
+    let mut languages = TranslationOptions::from_config(config::get()).languages;
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--source-lang" => languages.source = parse_language(next_value(&mut args_iter, arg)?)?,
+            "--target-lang" => languages.target = parse_language(next_value(&mut args_iter, arg)?)?,
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option '{}'. {}", other, usage))
+            }
+            other if path.is_none() => path = Some(other.to_string()),
+            _ => return Err(usage),
+        }
+    }
+
+    let path = path.ok_or_else(|| usage.clone())?;
+    if !PathBuf::from(&path).exists() {
+        return Err(format!("The specified path '{}' does not exist.", path));
+    }
+    Ok(ScanArgs { path, languages })
+}
+
+pub struct GlossaryArgs {
+    pub path: String,
+    pub api_key_file: Option<PathBuf>,
+    pub translation: TranslationOptions,
+    /// Occurrences across all files a term needs to be proposed.
+    pub min_count: usize,
+    pub max_terms: usize,
+    /// Where to write the glossary; `glossary.toml` in the input folder when not given.
+    pub output: Option<PathBuf>,
+    /// Only list the candidates; no request is made and nothing is written.
+    pub list_only: bool,
+}
+
+/// Parses `glossary <file-or-folder> [options]`; see [`USAGE`] for the flags.
+pub fn parse_glossary_args(args: &[String]) -> Result<GlossaryArgs, String> {
+    let usage = "Usage: ai_translate glossary <file-or-folder> [options] (see 'ai_translate help')";
+    let config = config::get();
+    let mut path = None;
+    let mut glossary_args = GlossaryArgs {
+        path: String::new(),
+        api_key_file: None,
+        translation: TranslationOptions::from_config(config),
+        min_count: 3,
+        max_terms: 100,
+        output: None,
+        list_only: false,
+    };
+    let mut model = None;
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--api-key-file" => glossary_args.api_key_file = Some(PathBuf::from(next_value(&mut args_iter, arg)?)),
+            "--source-lang" => {
+                glossary_args.translation.languages.source = parse_language(next_value(&mut args_iter, arg)?)?
+            }
+            "--target-lang" => {
+                glossary_args.translation.languages.target = parse_language(next_value(&mut args_iter, arg)?)?
+            }
+            "--backend" => {
+                let value = next_value(&mut args_iter, arg)?;
+                glossary_args.translation.backend = Backend::from_name(value)
+                    .ok_or_else(|| format!("Unsupported backend '{}'. Available: gemini.", value))?;
+            }
+            "--model" => model = Some(next_value(&mut args_iter, arg)?.as_str()),
+            "--min-count" | "--max-terms" => {
+                let value = next_value(&mut args_iter, arg)?
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&value| value > 0)
+                    .ok_or_else(|| format!("Invalid value for {}; expected a positive number.", arg))?;
+                if arg == "--min-count" {
+                    glossary_args.min_count = value;
+                } else {
+                    glossary_args.max_terms = value;
+                }
+            }
+            "--output" => glossary_args.output = Some(PathBuf::from(next_value(&mut args_iter, arg)?)),
+            "--list" => glossary_args.list_only = true,
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option '{}'. {}", other, usage))
+            }
+            other if path.is_none() => path = Some(other.to_string()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    glossary_args.path = path.ok_or_else(|| usage.to_string())?;
+    if !PathBuf::from(&glossary_args.path).exists() {
+        return Err(format!("The specified path '{}' does not exist.", glossary_args.path));
+    }
+    let translation = &mut glossary_args.translation;
+    if translation.languages.source.eq_ignore_ascii_case(&translation.languages.target) {
+        return Err("Source and target languages must differ.".to_string());
+    }
+    translation.generation = config.generation_settings(translation.backend).clone();
+    if let Some(model) = model {
+        apply_generation_flag(&mut translation.generation, "--model", model)?;
+    }
+    Ok(glossary_args)
+}
+
+pub enum MemoryAction {
+    Stats,
+    Search(String),
+    Export(PathBuf),
+    Import(PathBuf),
+    /// Align two subtitle files (or folders of them) by timing and store the line pairs.
+    ImportPairs {
+        source: PathBuf,
+        target: PathBuf,
+        /// Share of the shorter cue two cues must overlap to be paired.
+        min_overlap: f64,
+    },
+    Prune {
+        /// Remove entries not used for this many days.
+        older_than_days: Option<u64>,
+        /// Remove entries that never filled a line.
+        unused: bool,
+    },
+}
+
+pub struct MemoryArgs {
+    pub action: MemoryAction,
+    /// Restrict `search` and `prune` to one language pair side; for `import-pairs`, the
+    /// languages of the two files.
+    pub source_language: Option<String>,
+    pub target_language: Option<String>,
+}
+
+/// Parses `memory <stats|search|export|import|prune> [...]`; see [`USAGE`].
+pub fn parse_memory_args(args: &[String]) -> Result<MemoryArgs, String> {
+    let usage = "Usage: ai_translate memory <stats | search <text> | export <file> | import <file> | import-pairs <source> <target> [--min-overlap <percent>] | prune [--older-than <days>] [--unused]> [--source-lang <code>] [--target-lang <code>]";
+    let mut args_iter = args.iter();
+    let subcommand = args_iter.next().ok_or_else(|| usage.to_string())?;
+    let mut operands = Vec::new();
+    let mut min_overlap = 0.5;
+    let mut source_language = None;
+    let mut target_language = None;
+    let mut older_than_days = None;
+    let mut unused = false;
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--source-lang" => source_language = Some(parse_language(next_value(&mut args_iter, arg)?)?),
+            "--target-lang" => target_language = Some(parse_language(next_value(&mut args_iter, arg)?)?),
+            "--older-than" if subcommand == "prune" => {
+                older_than_days = Some(
+                    next_value(&mut args_iter, arg)?
+                        .parse::<u64>()
+                        .map_err(|_| format!("Invalid value for {}; expected a number of days.", arg))?,
+                )
+            }
+            "--unused" if subcommand == "prune" => unused = true,
+            "--min-overlap" if subcommand == "import-pairs" => {
+                min_overlap = next_value(&mut args_iter, arg)?
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|percent| *percent > 0.0 && *percent <= 100.0)
+                    .ok_or_else(|| format!("Invalid value for {}; expected a percentage from 1 to 100.", arg))?
+                    / 100.0
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option '{}'. {}", other, usage))
+            }
+            other => operands.push(other.to_string()),
+        }
+    }
+
+    let action = match (subcommand.as_str(), operands.as_slice()) {
+        ("stats", []) => MemoryAction::Stats,
+        ("search", [text]) => MemoryAction::Search(text.clone()),
+        ("export", [file]) => MemoryAction::Export(PathBuf::from(file)),
+        ("import", [file]) => {
+            let file = PathBuf::from(file);
+            if !file.is_file() {
+                return Err(format!("The file '{}' does not exist.", file.display()));
+            }
+            MemoryAction::Import(file)
+        }
+        ("import-pairs", [source, target]) => {
+            let (source, target) = (PathBuf::from(source), PathBuf::from(target));
+            if source.is_dir() != target.is_dir() || !source.exists() || !target.exists() {
+                return Err("import-pairs needs two existing files or two existing folders.".to_string());
+            }
+            MemoryAction::ImportPairs {
+                source,
+                target,
+                min_overlap,
+            }
+        }
+        ("prune", []) => {
+            if older_than_days.is_none() && !unused && source_language.is_none() && target_language.is_none() {
+                return Err("Tell prune what to remove: --older-than <days>, --unused, --source-lang or --target-lang.".to_string());
+            }
+            MemoryAction::Prune { older_than_days, unused }
+        }
+        _ => return Err(usage.to_string()),
+    };
+    Ok(MemoryArgs {
+        action,
+        source_language,
+        target_language,
+    })
+}
+
+pub struct LoginArgs {
+    pub backend: Backend,
+    pub api_key_file: Option<PathBuf>,
+    pub store: StoreKind,
+}
+
+/// Parses `login [--backend NAME] [--api-key-file FILE] [--plain-file]` and `logout [--backend NAME]`.
+pub fn parse_login_args(command: &str, args: &[String]) -> Result<LoginArgs, String> {
+    let usage = if command == "login" {
+        "Usage: ai_translate login [--backend <name>] [--api-key-file <file>] [--plain-file]"
+    } else {
+        "Usage: ai_translate logout [--backend <name>]"
+    };
+    let mut login_args = LoginArgs {
+        backend: config::get().backend(),
+        api_key_file: None,
+        store: StoreKind::Keychain,
+    };
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--backend" => {
+                let value = next_value(&mut args_iter, arg)?;
+                login_args.backend = Backend::from_name(value)
+                    .ok_or_else(|| format!("Unsupported backend '{}'. Available: gemini.", value))?;
+            }
+            "--api-key-file" if command == "login" => {
+                login_args.api_key_file = Some(PathBuf::from(next_value(&mut args_iter, arg)?))
+            }
+            "--plain-file" if command == "login" => login_args.store = StoreKind::PlainFile,
+            _ => return Err(usage.to_string()),
+        }
+    }
+    Ok(login_args)
+}
+
+fn parse_language(code: &str) -> Result<String, String> {
+    if utils::is_valid_language_code(code) {
+        Ok(code.to_ascii_lowercase())
+    } else {
+        Err(format!("Invalid language code '{}'; expected something like 'en' or 'pt-br'.", code))
+    }
+}
+
+fn parse_line_length(value: &str, flag: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(length) if length > 0 => Ok(length),
+        _ => Err(format!("Invalid value for {}; expected a positive integer.", flag)),
+    }
+}
+
+/// Parses `timing <file-or-folder> <adjustment>...` used to retime sources without translating.
+pub fn parse_timing_args(args: &[String]) -> Result<(String, Vec<TimingAdjustment>), String> {
+    let usage = "Usage: ai_translate timing <file-or-folder> <adjustment>... (e.g. shift:-1200 fps:25:23.976)";
+    let path = args.first().ok_or_else(|| usage.to_string())?;
+    if !PathBuf::from(path).exists() {
+        return Err(format!("The specified path '{}' does not exist.", path));
+    }
+    if args.len() < 2 {
+        return Err(usage.to_string());
+    }
+    let adjustments = args[1..]
+        .iter()
+        .map(|spec| TimingAdjustment::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((path.clone(), adjustments))
+}
+
+/// Parses `fix <file-or-folder>`.
+pub fn parse_fix_args(args: &[String]) -> Result<String, String> {
+    match args {
+        [path] if PathBuf::from(path).exists() => Ok(path.clone()),
+        [path] => Err(format!("The specified path '{}' does not exist.", path)),
+        _ => Err("Usage: ai_translate fix <file-or-folder>".to_string()),
+    }
+}
+
+pub struct ConvertArgs {
+    pub path: String,
+    pub to: SubtitleFormat,
+    pub from: Option<SubtitleFormat>,
+}
+
+/// Parses `convert <file-or-folder> --to <format> [--from <format>]`.
+pub fn parse_convert_args(args: &[String]) -> Result<ConvertArgs, String> {
+    let usage = "Usage: ai_translate convert <file-or-folder> --to <srt|vtt|ass|sbv|ttml> [--from <format>]";
+    let mut path = None;
+    let mut to = None;
+    let mut from = None;
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--to" | "--from" => {
+                let value = next_value(&mut args_iter, arg)?;
+                let format = SubtitleFormat::from_name(value)
+                    .ok_or_else(|| format!("Unsupported subtitle format '{}'.", value))?;
+                if arg == "--to" {
+                    to = Some(format);
+                } else {
+                    from = Some(format);
+                }
+            }
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option '{}'. {}", other, usage))
+            }
+            other if path.is_none() => path = Some(other.to_string()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let path = path.ok_or_else(|| usage.to_string())?;
+    if !PathBuf::from(&path).exists() {
+        return Err(format!("The specified path '{}' does not exist.", path));
+    }
+    Ok(ConvertArgs {
+        path,
+        to: to.ok_or_else(|| usage.to_string())?,
+        from,
+    })
+}
+
+pub struct ValidateArgs {
+    pub path: String,
+    pub options: ValidationOptions,
+    pub report_path: PathBuf,
+}
+
+/// Parses `validate <file-or-folder> [--max-line-length N] [--max-cps N] [--report FILE]`.
+pub fn parse_validate_args(args: &[String]) -> Result<ValidateArgs, String> {
+    let usage = "Usage: ai_translate validate <file-or-folder> [--max-line-length N] [--max-cps N] [--report FILE]";
+    let mut path = None;
+    let mut options = ValidationOptions::default();
+    let mut report_path = PathBuf::from("validation_report.json");
+
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--max-line-length" => {
+                options.max_line_length = next_value(&mut args_iter, arg)?
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}.", arg))?;
+            }
+            "--max-cps" => {
+                options.max_cps = next_value(&mut args_iter, arg)?
+                    .parse()
+                    .map_err(|_| format!("Invalid value for {}.", arg))?;
+            }
+            "--report" => report_path = PathBuf::from(next_value(&mut args_iter, arg)?),
+            other if other.starts_with("--") => {
+                return Err(format!("Unknown option '{}'. {}", other, usage))
+            }
+            other if path.is_none() => path = Some(other.to_string()),
+            _ => return Err(usage.to_string()),
+        }
+    }
+
+    let path = path.ok_or_else(|| usage.to_string())?;
+    if !PathBuf::from(&path).exists() {
+        return Err(format!("The specified path '{}' does not exist.", path));
+    }
+    Ok(ValidateArgs {
+        path,
+        options,
+        report_path,
+    })
+}
+
+fn next_value<'a>(args_iter: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<&'a String, String> {
+    args_iter
+        .next()
+        .ok_or_else(|| format!("Missing value for {}.", flag))
+}
@@ -0,0 +1,571 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::circuit_breaker;
+use crate::logger::Logger;
+use crate::translator::{self, Backend, GenerationSettings};
+use crate::utils;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Config"));
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// File name looked up in the user config directory and in the current (project) folder.
+pub const CONFIG_FILE_NAME: &str = "ai_translate.toml";
+/// Prefix of the environment variables that override config keys, e.g. `AI_TRANSLATE_RETRY_DELAY_MS`.
+pub const ENV_PREFIX: &str = "AI_TRANSLATE_";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub max_retry_error: u8,
+    /// Most lines sent in one chunk; chunks are also kept within their estimated token budget.
+    pub subtitle_line_chunks: usize,
+    /// Estimated output tokens per chunk; 0 sizes chunks from the model's output limit.
+    pub chunk_output_tokens: usize,
+    /// Calibrate the token estimate with the `countTokens` endpoint before the first chunk
+    /// instead of waiting for the usage reported by the first responses.
+    pub count_tokens: bool,
+    /// Chunks of a file in flight at once; the next chunk starts as soon as one finishes. A
+    /// chunk gets the context (previous lines and summary) of the chunks recorded before it
+    /// started, so with more than one it can miss the chunks just before it; files are still
+    /// translated one at a time.
+    pub concurrent_requests: usize,
+    /// Requests and estimated tokens allowed per minute across the whole run; 0 for no limit.
+    pub requests_per_minute: usize,
+    pub tokens_per_minute: usize,
+    pub retry_delay_ms: u64,
+    pub max_retry_delay_ms: u64,
+    /// How long a rate-limited key rests before it is used again, unless the API says otherwise.
+    pub key_cooldown_ms: u64,
+    /// Consecutive files failing on quota errors before the run stops.
+    pub circuit_breaker_threshold: u32,
+    /// `HH:MM` (UTC) at which the daily quota resets; when set, a run stopped by quota
+    /// errors waits for it and resumes instead of exiting.
+    pub quota_reset_time: String,
+    /// Folder for translations next to each source; empty for `subtitle_<target language>`.
+    pub translate_target_dir: String,
+    pub original_sub_target_dir: String,
+    pub convert_target_dir: String,
+    pub source_language: String,
+    pub target_language: String,
+    pub max_line_length: usize,
+    pub backend: String,
+    /// Models tried in order when a chunk keeps failing, as `model` (same backend) or
+    /// `backend:model`.
+    pub fallback_models: Vec<String>,
+    /// Failed attempts on one model before a chunk moves to the next model in the chain.
+    pub fallback_after_attempts: u8,
+    /// Source lines of the previous chunk (with their translations) sent as read-only context.
+    pub context_lines: usize,
+    /// Keep a short story summary, updated with one extra request per chunk, and send it as context.
+    pub rolling_summary: bool,
+    /// Project glossary applied to every file, below the `glossary.toml` files found in the
+    /// input folders; empty for none.
+    pub glossary_file: String,
+    /// Fill lines translated in earlier runs from the translation memory and add new ones to it.
+    pub translation_memory: bool,
+    /// Translation memory location; empty for `translation_memory.json` in the user data folder.
+    pub translation_memory_file: String,
+    /// Also fill a line stored only after other lines, when every stored translation of it is
+    /// the same. Off by default, since the line before can change the meaning ("Yes." after
+    /// different questions).
+    pub memory_any_context: bool,
+    /// Similar earlier translations from the translation memory shown with each chunk; 0 for none.
+    pub memory_examples: usize,
+    /// Send each distinct line of a file once and copy its translation to the repeats.
+    pub deduplicate_lines: bool,
+    /// Retry a blocked response once with every safety threshold set to BLOCK_NONE before
+    /// splitting the chunk to isolate the offending lines.
+    pub relax_safety_on_block: bool,
+    /// Model and generation parameters used with the Gemini backend (`[gemini]` section).
+    pub gemini: GenerationSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_retry_error: 10,
+            subtitle_line_chunks: 150,
+            chunk_output_tokens: 0,
+            count_tokens: false,
+            concurrent_requests: 1,
+            requests_per_minute: 0,
+            tokens_per_minute: 0,
+            retry_delay_ms: 5000,
+            max_retry_delay_ms: 120_000,
+            key_cooldown_ms: 60_000,
+            circuit_breaker_threshold: 2,
+            quota_reset_time: String::new(),
+            translate_target_dir: String::new(),
+            original_sub_target_dir: "subtitle_en".to_string(),
+            convert_target_dir: "subtitle_converted".to_string(),
+            source_language: "en".to_string(),
+            target_language: "fa".to_string(),
+            max_line_length: 55,
+            backend: "gemini".to_string(),
+            fallback_models: Vec::new(),
+            fallback_after_attempts: 3,
+            relax_safety_on_block: true,
+            context_lines: 10,
+            rolling_summary: false,
+            glossary_file: String::new(),
+            translation_memory: true,
+            translation_memory_file: String::new(),
+            memory_any_context: false,
+            memory_examples: 0,
+            deduplicate_lines: true,
+            gemini: GenerationSettings::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn backend(&self) -> Backend {
+        Backend::from_name(&self.backend).unwrap_or_default()
+    }
+
+    pub fn languages(&self) -> utils::LanguagePair {
+        utils::LanguagePair {
+            source: self.source_language.clone(),
+            target: self.target_language.clone(),
+        }
+    }
+
+    /// Name of the folder translations into `languages.target` are written to.
+    pub fn translation_dir(&self, languages: &utils::LanguagePair) -> String {
+        if self.translate_target_dir.is_empty() {
+            format!("subtitle_{}", languages.target)
+        } else {
+            self.translate_target_dir.clone()
+        }
+    }
+
+    /// The `[<backend>]` section holding the model and generation parameters for `backend`.
+    pub fn generation_settings(&self, backend: Backend) -> &GenerationSettings {
+        match backend {
+            Backend::Gemini => &self.gemini,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if self.max_retry_error == 0 {
+            problems.push("max_retry_error must be at least 1".to_string());
+        }
+        if self.subtitle_line_chunks == 0 {
+            problems.push("subtitle_line_chunks must be at least 1".to_string());
+        }
+        if self.concurrent_requests == 0 {
+            problems.push("concurrent_requests must be at least 1".to_string());
+        }
+        if self.max_retry_delay_ms < self.retry_delay_ms {
+            problems.push("max_retry_delay_ms must not be below retry_delay_ms".to_string());
+        }
+        if self.circuit_breaker_threshold == 0 {
+            problems.push("circuit_breaker_threshold must be at least 1".to_string());
+        }
+        if !self.quota_reset_time.is_empty()
+            && circuit_breaker::parse_reset_time(&self.quota_reset_time).is_err()
+        {
+            problems.push(format!(
+                "quota_reset_time must be HH:MM (UTC), got '{}'",
+                self.quota_reset_time
+            ));
+        }
+        if self.max_line_length == 0 {
+            problems.push("max_line_length must be at least 1".to_string());
+        }
+        let translation_dir = self.translation_dir(&self.languages());
+        let dirs = [
+            ("translate_target_dir", &translation_dir),
+            ("original_sub_target_dir", &self.original_sub_target_dir),
+            ("convert_target_dir", &self.convert_target_dir),
+        ];
+        for (key, dir) in dirs {
+            if dir.trim().is_empty() || dir.contains(['/', '\\']) || dir == "." || dir == ".." {
+                problems.push(format!("{} must be a plain folder name, got '{}'", key, dir));
+            }
+        }
+        if dirs[0].1 == dirs[1].1 || dirs[0].1 == dirs[2].1 || dirs[1].1 == dirs[2].1 {
+            problems.push("the translation, backup and conversion folders must all differ".to_string());
+        }
+        for (key, code) in [
+            ("source_language", &self.source_language),
+            ("target_language", &self.target_language),
+        ] {
+            if !utils::is_valid_language_code(code) {
+                problems.push(format!("{} '{}' is not a valid language code", key, code));
+            }
+        }
+        if Backend::from_name(&self.backend).is_none() {
+            problems.push(format!("unsupported backend '{}'", self.backend));
+        }
+        for entry in &self.fallback_models {
+            if let Err(e) = translator::parse_fallback_entry(entry) {
+                problems.push(e);
+            }
+        }
+        if self.fallback_after_attempts == 0 {
+            problems.push("fallback_after_attempts must be at least 1".to_string());
+        }
+        problems.extend(self.gemini.problems("gemini"));
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid configuration: {}.", problems.join("; ")))
+        }
+    }
+}
+
+/// Where the effective configuration came from, lowest priority first.
+pub struct LoadedConfig {
+    pub config: Config,
+    pub sources: Vec<String>,
+}
+
+/// The per-user folder holding the user config file and the plain-text credentials file.
+pub fn user_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("ai_translate"))
+}
+
+/// Config flags accepted before or after any command.
+#[derive(Default)]
+pub struct ConfigOverrides {
+    pub config_file: Option<PathBuf>,
+    pub settings: Vec<(String, String)>,
+}
+
+/// Removes `--config <file>` and `--set <key>=<value>` from `args`, leaving the command's own arguments.
+pub fn extract_overrides(args: &mut Vec<String>) -> Result<ConfigOverrides, String> {
+    let mut overrides = ConfigOverrides::default();
+    let mut remaining = Vec::with_capacity(args.len());
+    let mut args_iter = args.drain(..);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--config" => {
+                let path = args_iter.next().ok_or("Missing value for --config.")?;
+                overrides.config_file = Some(PathBuf::from(path));
+            }
+            "--set" => {
+                let setting = args_iter.next().ok_or("Missing value for --set.")?;
+                let (key, value) = setting
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid --set '{}'; expected key=value.", setting))?;
+                overrides.settings.push((key.trim().to_string(), value.trim().to_string()));
+            }
+            _ => remaining.push(arg),
+        }
+    }
+    drop(args_iter);
+    *args = remaining;
+    Ok(overrides)
+}
+
+/// Builds the effective configuration: defaults, then the user config file, the project
+/// config file (or `--config`), environment variables and finally `--set` flags.
+pub fn load(overrides: &ConfigOverrides) -> Result<LoadedConfig, String> {
+    let mut files = Vec::new();
+    if let Some(user_dir) = user_config_dir() {
+        files.push(user_dir.join(CONFIG_FILE_NAME));
+    }
+    match &overrides.config_file {
+        Some(path) if !path.is_file() => {
+            return Err(format!("Config file '{}' does not exist.", path.display()))
+        }
+        Some(path) => files.push(path.clone()),
+        None => files.push(PathBuf::from(CONFIG_FILE_NAME)),
+    }
+    build(&files, env::vars().collect(), &overrides.settings)
+}
+
+/// Layers the existing `files`, the `AI_TRANSLATE_*` entries of `vars` and `settings` over
+/// the defaults, in that order, and validates the result.
+fn build(
+    files: &[PathBuf],
+    vars: Vec<(String, String)>,
+    settings: &[(String, String)],
+) -> Result<LoadedConfig, String> {
+    let mut table = toml::Table::try_from(Config::default())
+        .map_err(|e| format!("Failed to build default configuration: {}", e))?;
+    let mut sources = vec!["built-in defaults".to_string()];
+
+    for path in files.iter().filter(|path| path.is_file()) {
+        merge_file(&mut table, path)?;
+        sources.push(path.display().to_string());
+    }
+
+    let mut env_settings = Vec::new();
+    for (key, value) in &table {
+        if value.is_table() {
+            // Section keys may be unset in the defaults, so look for any variable with the
+            // section's prefix, e.g. `AI_TRANSLATE_GEMINI_MODEL` for `gemini.model`.
+            let section_prefix = format!("{}{}_", ENV_PREFIX, key.to_ascii_uppercase());
+            for (var_name, var_value) in &vars {
+                if let Some(rest) = var_name.strip_prefix(&section_prefix) {
+                    let dotted = format!("{}.{}", key, rest.to_ascii_lowercase());
+                    env_settings.push((dotted, var_value.clone(), var_name.clone()));
+                }
+            }
+        } else {
+            let var_name = format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase());
+            if let Some((_, var_value)) = vars.iter().find(|(name, _)| *name == var_name) {
+                env_settings.push((key.clone(), var_value.clone(), var_name));
+            }
+        }
+    }
+    for (key, value, var_name) in env_settings {
+        set_value(&mut table, &key, &value)
+            .map_err(|e| format!("{} (from environment variable {})", e, var_name))?;
+        sources.push(format!("environment variable {}", var_name));
+    }
+
+    for (key, value) in settings {
+        set_value(&mut table, key, value).map_err(|e| format!("{} (from --set)", e))?;
+        sources.push(format!("--set {}", key));
+    }
+
+    let config: Config = table
+        .try_into()
+        .map_err(|e| format!("Invalid configuration: {}", e.to_string().trim().replace('\n', " ")))?;
+    config.validate()?;
+    Ok(LoadedConfig { config, sources })
+}
+
+fn merge_file(table: &mut toml::Table, path: &Path) -> Result<(), String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file '{}': {}", path.display(), e))?;
+    let file_table: toml::Table = content
+        .parse()
+        .map_err(|e| format!("Failed to parse config file '{}': {}", path.display(), e))?;
+    for (key, value) in file_table {
+        match (table.get_mut(&key), value) {
+            (None, _) => {
+                return Err(format!("Unknown key '{}' in config file '{}'.", key, path.display()))
+            }
+            // Sections merge key by key so a file can change one setting and keep the rest.
+            (Some(toml::Value::Table(section)), toml::Value::Table(file_section)) => {
+                section.extend(file_section);
+            }
+            (Some(existing), value) => *existing = value,
+        }
+    }
+    LOGGER.debug(format!("Loaded config file '{}'.", path.display()).as_str());
+    Ok(())
+}
+
+/// Sets a key from a plain string, converting it to the type of the existing value.
+/// Section keys are written `section.key`; a section key that is unset by default gets
+/// the type its text looks like, and the final deserialization rejects unknown names.
+fn set_value(table: &mut toml::Table, key: &str, raw: &str) -> Result<(), String> {
+    let (table, leaf) = match key.split_once('.') {
+        Some((section, leaf)) => match table.get_mut(section) {
+            Some(toml::Value::Table(section_table)) => (section_table, leaf),
+            _ => return Err(format!("Unknown config section '{}'", section)),
+        },
+        None => (table, key),
+    };
+    let Some(current) = table.get(leaf) else {
+        if key.contains('.') {
+            table.insert(leaf.to_string(), infer_value(raw));
+            return Ok(());
+        }
+        return Err(format!("Unknown config key '{}'", key));
+    };
+    let value = match current {
+        toml::Value::Integer(_) => raw
+            .parse::<i64>()
+            .map(toml::Value::Integer)
+            .map_err(|_| format!("Config key '{}' expects an integer, got '{}'", key, raw))?,
+        toml::Value::Float(_) => raw
+            .parse::<f64>()
+            .map(toml::Value::Float)
+            .map_err(|_| format!("Config key '{}' expects a number, got '{}'", key, raw))?,
+        toml::Value::Boolean(_) => raw
+            .parse::<bool>()
+            .map(toml::Value::Boolean)
+            .map_err(|_| format!("Config key '{}' expects true or false, got '{}'", key, raw))?,
+        // Lists are written comma-separated, e.g. `--set fallback_models=a,b`.
+        toml::Value::Array(_) => toml::Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        ),
+        toml::Value::Table(_) => {
+            return Err(format!("Config key '{}' is a section; set its keys as '{}.<key>'", key, key))
+        }
+        _ => toml::Value::String(raw.to_string()),
+    };
+    table.insert(leaf.to_string(), value);
+    Ok(())
+}
+
+fn infer_value(raw: &str) -> toml::Value {
+    if let Ok(integer) = raw.parse::<i64>() {
+        toml::Value::Integer(integer)
+    } else if let Ok(float) = raw.parse::<f64>() {
+        toml::Value::Float(float)
+    } else if let Ok(boolean) = raw.parse::<bool>() {
+        toml::Value::Boolean(boolean)
+    } else {
+        toml::Value::String(raw.to_string())
+    }
+}
+
+pub fn init(config: Config) {
+    if CONFIG.set(config).is_err() {
+        LOGGER.warning("Configuration was already initialized; keeping the first one.");
+    }
+}
+
+/// The effective configuration; built-in defaults when `init` has not been called.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+/// Prints the effective configuration as TOML, preceded by the layers it was built from.
+pub fn print_effective(loaded: &LoadedConfig) -> Result<(), String> {
+    let rendered = toml::to_string_pretty(&loaded.config)
+        .map_err(|e| format!("Failed to render configuration: {}", e))?;
+    let mut output = String::from("# Effective configuration, built from (lowest priority first):\n");
+    for source in &loaded.sources {
+        output.push_str(&format!("#   {}\n", source));
+    }
+    output.push_str(&rendered);
+    LOGGER.log(&output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `content` to a fresh file under the system temp folder.
+    fn write_temp(name: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ai_translate_config_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let user = write_temp(
+            "user.toml",
+            "context_lines = 1\nmax_line_length = 41\nretry_delay_ms = 101\nkey_cooldown_ms = 1001\n",
+        );
+        let project = write_temp("project.toml", "max_line_length = 42\nretry_delay_ms = 102\nkey_cooldown_ms = 1002\n");
+        let env_vars = vars(&[
+            ("AI_TRANSLATE_RETRY_DELAY_MS", "103"),
+            ("AI_TRANSLATE_KEY_COOLDOWN_MS", "1003"),
+            ("UNRELATED_RETRY_DELAY_MS", "1"),
+        ]);
+        let settings = vec![("key_cooldown_ms".to_string(), "1004".to_string())];
+
+        let loaded = build(&[user.clone(), project.clone()], env_vars, &settings).unwrap();
+        assert_eq!(loaded.config.context_lines, 1);
+        assert_eq!(loaded.config.max_line_length, 42);
+        assert_eq!(loaded.config.retry_delay_ms, 103);
+        assert_eq!(loaded.config.key_cooldown_ms, 1004);
+        assert_eq!(loaded.config.subtitle_line_chunks, Config::default().subtitle_line_chunks);
+        assert_eq!(
+            loaded.sources,
+            vec![
+                "built-in defaults".to_string(),
+                user.display().to_string(),
+                project.display().to_string(),
+                "environment variable AI_TRANSLATE_KEY_COOLDOWN_MS".to_string(),
+                "environment variable AI_TRANSLATE_RETRY_DELAY_MS".to_string(),
+                "--set key_cooldown_ms".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_files_are_skipped() {
+        let missing = env::temp_dir().join("ai_translate_config_test_missing.toml");
+        let loaded = build(&[missing], Vec::new(), &[]).unwrap();
+        assert_eq!(loaded.sources, vec!["built-in defaults".to_string()]);
+    }
+
+    #[test]
+    fn environment_values_take_the_type_of_the_key() {
+        let env_vars = vars(&[
+            ("AI_TRANSLATE_ROLLING_SUMMARY", "true"),
+            ("AI_TRANSLATE_FALLBACK_MODELS", "a, gemini:b"),
+            ("AI_TRANSLATE_GEMINI_MODEL", "some-model"),
+            ("AI_TRANSLATE_GEMINI_TEMPERATURE", "0.5"),
+        ]);
+        let config = build(&[], env_vars, &[]).unwrap().config;
+        assert!(config.rolling_summary);
+        assert_eq!(config.fallback_models, vec!["a".to_string(), "gemini:b".to_string()]);
+        assert_eq!(config.gemini.model, "some-model");
+        assert_eq!(config.gemini.temperature, 0.5);
+
+        let error = build(&[], vars(&[("AI_TRANSLATE_CONTEXT_LINES", "many")]), &[]).err().unwrap();
+        assert!(error.contains("expects an integer"), "{}", error);
+        assert!(error.contains("AI_TRANSLATE_CONTEXT_LINES"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let file = write_temp("unknown.toml", "no_such_key = 1\n");
+        let error = build(&[file], Vec::new(), &[]).err().unwrap();
+        assert!(error.contains("Unknown key 'no_such_key'"), "{}", error);
+
+        let file = write_temp("unknown_section_key.toml", "[gemini]\nno_such_key = 1\n");
+        let error = build(&[file], Vec::new(), &[]).err().unwrap();
+        assert!(error.contains("no_such_key"), "{}", error);
+
+        let settings = vec![("no_such_key".to_string(), "1".to_string())];
+        let error = build(&[], Vec::new(), &settings).err().unwrap();
+        assert!(error.contains("Unknown config key 'no_such_key'"), "{}", error);
+
+        let error = build(&[], vars(&[("AI_TRANSLATE_GEMINI_NO_SUCH_KEY", "1")]), &[]).err().unwrap();
+        assert!(error.contains("no_such_key"), "{}", error);
+    }
+
+    type Change = fn(&mut Config);
+
+    #[test]
+    fn validation_reports_each_problem() {
+        assert!(Config::default().validate().is_ok());
+
+        let cases: Vec<(Change, &str)> = vec![
+            (|c| c.max_retry_error = 0, "max_retry_error must be at least 1"),
+            (|c| c.subtitle_line_chunks = 0, "subtitle_line_chunks must be at least 1"),
+            (|c| c.concurrent_requests = 0, "concurrent_requests must be at least 1"),
+            (|c| c.max_retry_delay_ms = c.retry_delay_ms - 1, "max_retry_delay_ms must not be below"),
+            (|c| c.circuit_breaker_threshold = 0, "circuit_breaker_threshold must be at least 1"),
+            (|c| c.quota_reset_time = "25:00".to_string(), "quota_reset_time must be HH:MM"),
+            (|c| c.max_line_length = 0, "max_line_length must be at least 1"),
+            (|c| c.convert_target_dir = "a/b".to_string(), "convert_target_dir must be a plain folder name"),
+            (|c| c.original_sub_target_dir = "..".to_string(), "original_sub_target_dir must be a plain folder name"),
+            (|c| c.translate_target_dir = "subtitle_converted".to_string(), "folders must all differ"),
+            (|c| c.target_language = "farsi!".to_string(), "target_language 'farsi!' is not a valid"),
+            (|c| c.backend = "nope".to_string(), "unsupported backend 'nope'"),
+            (|c| c.fallback_models = vec!["gemini:".to_string()], "has no model name"),
+            (|c| c.fallback_after_attempts = 0, "fallback_after_attempts must be at least 1"),
+            (|c| c.gemini.temperature = 3.0, "gemini.temperature must be between 0 and 2"),
+        ];
+        for (change, expected) in cases {
+            let mut config = Config::default();
+            change(&mut config);
+            let error = config.validate().err().unwrap_or_default();
+            assert!(error.contains(expected), "expected '{}' in '{}'", expected, error);
+        }
+    }
+}
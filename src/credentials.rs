@@ -0,0 +1,343 @@
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::key_pool::{self, KeyEntry};
+use crate::logger::{self, Logger};
+use crate::translator::Backend;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Credentials"));
+
+const CREDENTIALS_FILE_NAME: &str = "credentials.toml";
+/// Service name the keys are saved under in the system keychain, one entry per backend.
+const KEYCHAIN_SERVICE: &str = "ai_translate";
+
+/// Where `login` saves keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreKind {
+    /// The system keychain (macOS Keychain, Windows Credential Manager, Secret Service).
+    Keychain,
+    /// `credentials.toml` in the user config folder, for systems without a keychain. The keys
+    /// are stored unencrypted; the file is only readable by its owner on unix.
+    PlainFile,
+}
+
+/// API keys saved by `login --plain-file`, a key list per backend.
+#[derive(Serialize, Deserialize, Default)]
+struct CredentialFile {
+    #[serde(default)]
+    keys: BTreeMap<String, String>,
+}
+
+/// Where a resolved API key came from; logged instead of the key itself.
+#[derive(Debug, PartialEq)]
+pub enum KeySource {
+    File(PathBuf),
+    Environment(&'static str),
+    Keychain,
+    PlainFile(PathBuf),
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::File(path) => write!(f, "key file '{}'", path.display()),
+            KeySource::Environment(name) => write!(f, "environment variable {}", name),
+            KeySource::Keychain => write!(f, "the system keychain"),
+            KeySource::PlainFile(path) => write!(f, "plain-text credentials file '{}'", path.display()),
+        }
+    }
+}
+
+pub fn credential_file_path() -> Result<PathBuf, String> {
+    config::user_config_dir()
+        .map(|dir| dir.join(CREDENTIALS_FILE_NAME))
+        .ok_or_else(|| "Could not determine the user config folder for the credentials file.".to_string())
+}
+
+fn keychain_entry(backend: Backend) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, &backend.to_string())
+        .map_err(|e| format!("System keychain unavailable: {}", e))
+}
+
+fn load_file(path: &Path) -> Result<CredentialFile, String> {
+    if !path.is_file() {
+        return Ok(CredentialFile::default());
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read credentials file '{}': {}", path.display(), e))?;
+    toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse credentials file '{}': {}", path.display(), e))
+}
+
+fn save_file(path: &Path, store: &CredentialFile) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Error creating folder '{}': {}", parent.display(), e))?;
+    }
+    let content = toml::to_string(store)
+        .map_err(|e| format!("Failed to serialize credentials: {}", e))?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // Tighten an existing file too; `mode` only applies when the file is created.
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+                .map_err(|e| format!("Error securing '{}': {}", path.display(), e))?;
+        }
+    }
+    #[cfg(not(unix))]
+    LOGGER.warning(
+        format!(
+            "'{}' is not protected by file permissions on this system; anyone with access to your user folder can read the keys.",
+            path.display()
+        )
+        .as_str(),
+    );
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(|e| format!("Error writing credentials file '{}': {}", path.display(), e))
+}
+
+/// Saves the keys for `backend` and returns where they went.
+pub fn save_keys(backend: Backend, entries: &[KeyEntry], kind: StoreKind) -> Result<KeySource, String> {
+    let key_list = key_pool::format_key_list(entries);
+    match kind {
+        StoreKind::Keychain => {
+            keychain_entry(backend)?.set_password(&key_list).map_err(|e| {
+                format!(
+                    "Could not save the keys in the system keychain: {}. Use 'login --plain-file' to save them in an unencrypted file instead.",
+                    e
+                )
+            })?;
+            Ok(KeySource::Keychain)
+        }
+        StoreKind::PlainFile => {
+            let path = credential_file_path()?;
+            let mut store = load_file(&path)?;
+            store.keys.insert(backend.to_string(), key_list);
+            save_file(&path, &store)?;
+            Ok(KeySource::PlainFile(path))
+        }
+    }
+}
+
+/// Removes the saved keys for `backend` from the keychain and the credentials file; returns
+/// false when neither had any.
+pub fn remove_key(backend: Backend) -> Result<bool, String> {
+    let removed_from_keychain = match keychain_entry(backend).map(|entry| entry.delete_credential()) {
+        Ok(Ok(())) => true,
+        Ok(Err(keyring::Error::NoEntry)) => false,
+        // No usable keychain on this system, so nothing can have been saved there.
+        Ok(Err(e @ (keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)))) => {
+            LOGGER.debug(format!("System keychain unavailable: {}", e).as_str());
+            false
+        }
+        Ok(Err(e)) => {
+            LOGGER.warning(format!("Could not remove the keys from the system keychain: {}", e).as_str());
+            false
+        }
+        Err(e) => {
+            LOGGER.debug(&e);
+            false
+        }
+    };
+
+    let path = credential_file_path()?;
+    let mut store = load_file(&path)?;
+    if store.keys.remove(&backend.to_string()).is_none() {
+        return Ok(removed_from_keychain);
+    }
+    save_file(&path, &store)?;
+    Ok(true)
+}
+
+/// Reads a key file holding one key per line, optionally as `label=key`.
+pub fn read_key_file(path: &Path) -> Result<Vec<KeyEntry>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read API key file '{}': {}", path.display(), e))?;
+    let entries = key_pool::parse_key_list(&content);
+    if entries.is_empty() {
+        return Err(format!("API key file '{}' is empty.", path.display()));
+    }
+    Ok(entries)
+}
+
+/// Finds the API keys for `backend`, checking in order: `--api-key-file`, the backend's
+/// environment variable (comma-separated for several keys), the system keychain and the
+/// plain-text credentials file. Returns `None` when no source has a key, so the caller can fall back to prompting.
+pub fn resolve_api_keys(
+    backend: Backend,
+    key_file: Option<&Path>,
+) -> Result<Option<(Vec<KeyEntry>, KeySource)>, String> {
+    let var_name = backend.api_key_env_var();
+    let resolved = find_api_keys(
+        backend,
+        key_file,
+        env::var(var_name).ok().map(|value| (var_name, value)),
+        || keychain_entry(backend).and_then(|entry| entry.get_password().map_err(|e| e.to_string())),
+        credential_file_path().ok(),
+    )?;
+    if let Some((entries, _)) = &resolved {
+        for entry in entries {
+            logger::register_secret(&entry.key);
+        }
+    }
+    Ok(resolved)
+}
+
+/// The lookup behind `resolve_api_keys`, with each source passed in; the keychain is only
+/// read when the sources before it have no key.
+fn find_api_keys(
+    backend: Backend,
+    key_file: Option<&Path>,
+    environment: Option<(&'static str, String)>,
+    read_keychain: impl FnOnce() -> Result<String, String>,
+    plain_file: Option<PathBuf>,
+) -> Result<Option<(Vec<KeyEntry>, KeySource)>, String> {
+    if let Some(path) = key_file {
+        return Ok(Some((read_key_file(path)?, KeySource::File(path.to_path_buf()))));
+    }
+
+    if let Some((var_name, value)) = environment {
+        let entries = key_pool::parse_key_list(&value);
+        if !entries.is_empty() {
+            return Ok(Some((entries, KeySource::Environment(var_name))));
+        }
+    }
+
+    match read_keychain() {
+        Ok(value) => {
+            let entries = key_pool::parse_key_list(&value);
+            if !entries.is_empty() {
+                return Ok(Some((entries, KeySource::Keychain)));
+            }
+        }
+        // No keychain on this system (e.g. Linux without a Secret Service) or nothing saved.
+        Err(e) => LOGGER.debug(format!("No keys read from the system keychain: {}", e).as_str()),
+    }
+
+    let Some(path) = plain_file else {
+        return Ok(None);
+    };
+    let store = load_file(&path)?;
+    Ok(store
+        .keys
+        .get(&backend.to_string())
+        .map(|value| key_pool::parse_key_list(value))
+        .filter(|entries| !entries.is_empty())
+        .map(|entries| (entries, KeySource::PlainFile(path))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ai_translate_credentials_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    fn plain_file_with(name: &str, keys: &str) -> PathBuf {
+        let path = temp_path(name);
+        let mut store = CredentialFile::default();
+        store.keys.insert(Backend::Gemini.to_string(), keys.to_string());
+        save_file(&path, &store).unwrap();
+        path
+    }
+
+    fn keys_of(resolved: &Option<(Vec<KeyEntry>, KeySource)>) -> Vec<String> {
+        resolved
+            .as_ref()
+            .map(|(entries, _)| entries.iter().map(|entry| entry.key.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn sources_are_tried_in_order() {
+        let key_file = temp_path("keys.txt");
+        fs::write(&key_file, "file-key\n").unwrap();
+        let plain_file = plain_file_with("order.toml", "plain-key");
+        let environment = || Some(("GEMINI_API_KEY", "env-a, env-b".to_string()));
+        let keychain = || Ok("keychain-key".to_string());
+        let no_keychain = || Err("no keychain".to_string());
+
+        let resolved = find_api_keys(Backend::Gemini, Some(&key_file), environment(), keychain, Some(plain_file.clone())).unwrap();
+        assert_eq!(keys_of(&resolved), vec!["file-key"]);
+        assert_eq!(resolved.unwrap().1, KeySource::File(key_file.clone()));
+
+        let resolved = find_api_keys(Backend::Gemini, None, environment(), keychain, Some(plain_file.clone())).unwrap();
+        assert_eq!(keys_of(&resolved), vec!["env-a", "env-b"]);
+        assert_eq!(resolved.unwrap().1, KeySource::Environment("GEMINI_API_KEY"));
+
+        let empty_environment = Some(("GEMINI_API_KEY", " , ".to_string()));
+        let resolved = find_api_keys(Backend::Gemini, None, empty_environment, keychain, Some(plain_file.clone())).unwrap();
+        assert_eq!(keys_of(&resolved), vec!["keychain-key"]);
+        assert_eq!(resolved.unwrap().1, KeySource::Keychain);
+
+        let resolved = find_api_keys(Backend::Gemini, None, None, no_keychain, Some(plain_file.clone())).unwrap();
+        assert_eq!(keys_of(&resolved), vec!["plain-key"]);
+        assert_eq!(resolved.unwrap().1, KeySource::PlainFile(plain_file));
+
+        let resolved = find_api_keys(Backend::Gemini, None, None, no_keychain, Some(temp_path("missing.toml"))).unwrap();
+        assert!(resolved.is_none());
+    }
+
+    #[test]
+    fn keychain_is_not_read_when_an_earlier_source_has_a_key() {
+        let environment = Some(("GEMINI_API_KEY", "env-key".to_string()));
+        let keychain = || -> Result<String, String> { panic!("keychain read") };
+        let resolved = find_api_keys(Backend::Gemini, None, environment, keychain, None).unwrap();
+        assert_eq!(keys_of(&resolved), vec!["env-key"]);
+    }
+
+    #[test]
+    fn empty_key_file_is_an_error() {
+        let key_file = temp_path("empty.txt");
+        fs::write(&key_file, "# no keys yet\n\n").unwrap();
+        let error = find_api_keys(Backend::Gemini, Some(&key_file), None, || Ok(String::new()), None).err().unwrap();
+        assert!(error.contains("is empty"), "{}", error);
+    }
+
+    #[test]
+    fn plain_file_keeps_other_backends_and_round_trips() {
+        let path = temp_path("round_trip.toml");
+        let mut store = CredentialFile::default();
+        store.keys.insert("other".to_string(), "other-key".to_string());
+        store.keys.insert(Backend::Gemini.to_string(), "work=a\nb".to_string());
+        save_file(&path, &store).unwrap();
+
+        let loaded = load_file(&path).unwrap();
+        assert_eq!(loaded.keys, store.keys);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn plain_file_is_only_readable_by_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("permissions.toml");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        save_file(&path, &CredentialFile::default()).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        let new_path = temp_path("new_permissions.toml");
+        let _ = fs::remove_file(&new_path);
+        save_file(&new_path, &CredentialFile::default()).unwrap();
+        assert_eq!(fs::metadata(&new_path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+}
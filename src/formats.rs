@@ -0,0 +1,1273 @@
+use std::{collections::HashMap, fmt, path::Path, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::parser;
+use crate::subtitle::{self, Cue, Timestamp};
+
+static FONT_COLOR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)color\s*=\s*["']?([#\w]+)"#).unwrap());
+/// ASS override blocks (`{\i1}`) some SRT files carry; braces without a backslash are text.
+static SRT_OVERRIDE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\{\\[^}]*\}").unwrap());
+static VTT_TAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)([a-zA-Z0-9:.]+)([^>]*)>").unwrap());
+/// Override blocks, or a `\{`/`\}` escaped brace that belongs to the text.
+static ASS_OVERRIDE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\[{}]|\{([^}]*)\}").unwrap());
+static TTML_ATTRIBUTE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"([\w:.-]+)\s*=\s*"([^"]*)""#).unwrap());
+// TTML elements may carry a namespace prefix (`<tt:p>`), so every element pattern allows one.
+static TTML_ROOT_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(?:[\w.-]+:)?tt(\s[^>]*)?>").unwrap());
+static TTML_REGION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(?:[\w.-]+:)?region(\s[^>]*)?>").unwrap());
+static TTML_PARAGRAPH_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<(?:[\w.-]+:)?p(\s[^>]*)?>(.*?)</(?:[\w.-]+:)?p>").unwrap()
+});
+static TTML_OFFSET_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([\d.]+)(h|ms|m|s|f|t)$").unwrap());
+static TTML_TOKEN_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(/?)([\w:]+)([^>]*?)(/?)>|[^<]+").unwrap());
+static WHITESPACE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").unwrap());
+
+/// Subtitle file formats the converter can read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    Vtt,
+    Ass,
+    Sbv,
+    Ttml,
+}
+
+pub const SUPPORTED_EXTENSIONS: [&str; 7] = ["srt", "vtt", "ass", "ssa", "sbv", "ttml", "dfxp"];
+
+impl SubtitleFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "srt" => Some(SubtitleFormat::Srt),
+            "vtt" | "webvtt" => Some(SubtitleFormat::Vtt),
+            "ass" | "ssa" => Some(SubtitleFormat::Ass),
+            "sbv" => Some(SubtitleFormat::Sbv),
+            "ttml" | "dfxp" | "xml" => Some(SubtitleFormat::Ttml),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(SubtitleFormat::from_name)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Vtt => "vtt",
+            SubtitleFormat::Ass => "ass",
+            SubtitleFormat::Sbv => "sbv",
+            SubtitleFormat::Ttml => "ttml",
+        }
+    }
+
+    fn supports_styling(&self) -> bool {
+        !matches!(self, SubtitleFormat::Sbv)
+    }
+
+    fn supports_positioning(&self) -> bool {
+        !matches!(self, SubtitleFormat::Sbv)
+    }
+}
+
+impl fmt::Display for SubtitleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension().to_uppercase())
+    }
+}
+
+/// Parses any supported format into cues whose text uses SRT-style inline tags
+/// (`<i>`, `<b>`, `<u>`, `<font color>`) as the common styling representation, and returns
+/// warnings about features that could not be kept.
+pub fn parse(content: &str, format: SubtitleFormat) -> Result<(Vec<Cue>, Vec<String>), String> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut losses = Losses::default();
+    let mut cues = match format {
+        SubtitleFormat::Srt => parse_srt(&content, &mut losses)?,
+        SubtitleFormat::Vtt => parse_vtt(&content)?,
+        SubtitleFormat::Ass => parse_ass(&content)?,
+        SubtitleFormat::Sbv => parse_sbv(&content)?,
+        SubtitleFormat::Ttml => parse_ttml(&content)?,
+    };
+    for (position, cue) in cues.iter_mut().enumerate() {
+        cue.index = position + 1;
+    }
+    Ok((cues, losses.into_messages()))
+}
+
+/// Renders cues in the target format and returns warnings about features it cannot keep.
+pub fn render(cues: &[Cue], format: SubtitleFormat) -> (String, Vec<String>) {
+    let mut losses = Losses::default();
+
+    if !format.supports_styling() {
+        let styled = cues
+            .iter()
+            .filter(|cue| tokenize(&cue.text).iter().any(|t| !matches!(t, Markup::Text(_))))
+            .count();
+        losses.add(styled, format!("Styling is not supported by {} and was dropped", format));
+    }
+    if !format.supports_positioning() {
+        let positioned = cues.iter().filter(|cue| is_repositioned(cue)).count();
+        losses.add(positioned, format!("Positioning is not supported by {} and was dropped", format));
+    }
+
+    let content = match format {
+        SubtitleFormat::Srt => render_srt(cues),
+        SubtitleFormat::Vtt => render_vtt(cues, &mut losses),
+        SubtitleFormat::Ass => render_ass(cues, &mut losses),
+        SubtitleFormat::Sbv => render_sbv(cues),
+        SubtitleFormat::Ttml => render_ttml(cues, &mut losses),
+    };
+    (content, losses.into_messages())
+}
+
+fn is_repositioned(cue: &Cue) -> bool {
+    cue.alignment.is_some_and(|alignment| alignment != 2)
+}
+
+/// Counts cues affected by each kind of lost feature so warnings are reported once per file.
+#[derive(Default)]
+struct Losses {
+    counts: Vec<(String, usize)>,
+}
+
+impl Losses {
+    fn add(&mut self, cues: usize, message: String) {
+        if cues == 0 {
+            return;
+        }
+        match self.counts.iter_mut().find(|(existing, _)| *existing == message) {
+            Some((_, count)) => *count += cues,
+            None => self.counts.push((message, cues)),
+        }
+    }
+
+    fn into_messages(self) -> Vec<String> {
+        self.counts
+            .into_iter()
+            .map(|(message, count)| format!("{} in {} cue(s).", message, count))
+            .collect()
+    }
+}
+
+// region Inline markup
+
+#[derive(Debug, Clone, PartialEq)]
+enum Markup {
+    Text(String),
+    Open(Style),
+    Close(Style),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Style {
+    Italic,
+    Bold,
+    Underline,
+    Color(String),
+}
+
+impl Style {
+    fn same_kind(&self, other: &Style) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// Splits SRT-style text into balanced markup tokens. Anything in `<...>` that is not a
+/// recognised tag, such as `x < 5 and y > 3`, is kept as text.
+fn tokenize(text: &str) -> Vec<Markup> {
+    let mut tokens = Vec::new();
+    let mut text_buffer = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        let Some(length) = rest[start..].find('>') else {
+            break;
+        };
+        let raw = &rest[start..start + length + 1];
+        if raw[1..].contains('<') {
+            // A lone `<`; a tag can only start at the next one.
+            text_buffer.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        }
+        text_buffer.push_str(&rest[..start]);
+        rest = &rest[start + length + 1..];
+
+        let tag = raw[1..raw.len() - 1].trim().to_lowercase();
+        let (is_close, name) = match tag.strip_prefix('/') {
+            Some(name) => (true, name.trim()),
+            None => (false, tag.as_str()),
+        };
+        let style = match name.split_whitespace().next().unwrap_or_default() {
+            "i" => Style::Italic,
+            "b" => Style::Bold,
+            "u" => Style::Underline,
+            "font" => Style::Color(
+                FONT_COLOR_RE
+                    .captures(raw)
+                    .map(|c| c[1].to_string())
+                    .unwrap_or_default(),
+            ),
+            _ => {
+                text_buffer.push_str(raw);
+                continue;
+            }
+        };
+        if !text_buffer.is_empty() {
+            tokens.push(Markup::Text(std::mem::take(&mut text_buffer)));
+        }
+        tokens.push(if is_close { Markup::Close(style) } else { Markup::Open(style) });
+    }
+    text_buffer.push_str(rest);
+    if !text_buffer.is_empty() {
+        tokens.push(Markup::Text(text_buffer));
+    }
+
+    balance(tokens)
+}
+
+/// Drops stray closing tags, closes tags left open, and drops `<font>` without a colour.
+fn balance(tokens: Vec<Markup>) -> Vec<Markup> {
+    let mut balanced = Vec::with_capacity(tokens.len());
+    let mut open: Vec<Style> = Vec::new();
+
+    for token in tokens {
+        match token {
+            Markup::Open(Style::Color(color)) if color.is_empty() => {
+                open.push(Style::Color(String::new()));
+            }
+            Markup::Open(style) => {
+                open.push(style.clone());
+                balanced.push(Markup::Open(style));
+            }
+            Markup::Close(style) => {
+                let Some(position) = open.iter().rposition(|o| o.same_kind(&style)) else {
+                    continue;
+                };
+                // Close inner tags first so every format receives properly nested markup.
+                while open.len() > position {
+                    let inner = open.pop().unwrap();
+                    if inner != Style::Color(String::new()) {
+                        balanced.push(Markup::Close(inner));
+                    }
+                }
+            }
+            text => balanced.push(text),
+        }
+    }
+    while let Some(inner) = open.pop() {
+        if inner != Style::Color(String::new()) {
+            balanced.push(Markup::Close(inner));
+        }
+    }
+    balanced
+}
+
+const NAMED_COLORS: [(&str, &str); 8] = [
+    ("white", "#FFFFFF"),
+    ("lime", "#00FF00"),
+    ("cyan", "#00FFFF"),
+    ("red", "#FF0000"),
+    ("yellow", "#FFFF00"),
+    ("magenta", "#FF00FF"),
+    ("blue", "#0000FF"),
+    ("black", "#000000"),
+];
+
+/// Normalizes a colour to `#RRGGBB`, resolving the basic named colours.
+fn color_to_hex(color: &str) -> Option<String> {
+    let lower = color.to_lowercase();
+    if let Some((_, hex)) = NAMED_COLORS.iter().find(|(name, _)| *name == lower) {
+        return Some(hex.to_string());
+    }
+    let hex = lower.strip_prefix('#')?;
+    match hex.len() {
+        6 if hex.chars().all(|c| c.is_ascii_hexdigit()) => Some(format!("#{}", hex.to_uppercase())),
+        3 if hex.chars().all(|c| c.is_ascii_hexdigit()) => Some(format!(
+            "#{}",
+            hex.chars().flat_map(|c| [c, c]).collect::<String>().to_uppercase()
+        )),
+        _ => None,
+    }
+}
+
+fn color_to_name(color: &str) -> Option<&'static str> {
+    let hex = color_to_hex(color)?;
+    NAMED_COLORS
+        .iter()
+        .find(|(_, named_hex)| *named_hex == hex)
+        .map(|(name, _)| *name)
+}
+
+fn open_tag(style: &Style) -> String {
+    match style {
+        Style::Italic => "<i>".to_string(),
+        Style::Bold => "<b>".to_string(),
+        Style::Underline => "<u>".to_string(),
+        Style::Color(color) => format!("<font color=\"{}\">", color),
+    }
+}
+
+fn close_tag(style: &Style) -> &'static str {
+    match style {
+        Style::Italic => "</i>",
+        Style::Bold => "</b>",
+        Style::Underline => "</u>",
+        Style::Color(_) => "</font>",
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn encode_entities(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Numpad alignment from a vertical row (1 bottom, 4 middle, 7 top) and a horizontal keyword.
+fn numpad_alignment(row_base: u8, horizontal: Option<&str>) -> u8 {
+    let column = match horizontal {
+        Some("start" | "left") => 0,
+        Some("end" | "right") => 2,
+        _ => 1,
+    };
+    row_base + column
+}
+
+// endregion
+
+// region Timestamps
+
+fn format_clock(timestamp: Timestamp, separator: char, pad_hours: bool, centiseconds: bool) -> String {
+    let total = timestamp.as_millis();
+    let hours = total / 3_600_000;
+    let hours = if pad_hours {
+        format!("{:02}", hours)
+    } else {
+        hours.to_string()
+    };
+    let fraction = if centiseconds {
+        format!("{:02}", (total % 1000) / 10)
+    } else {
+        format!("{:03}", total % 1000)
+    };
+    format!(
+        "{}:{:02}:{:02}{}{}",
+        hours,
+        (total / 60_000) % 60,
+        (total / 1000) % 60,
+        separator,
+        fraction
+    )
+}
+
+// endregion
+
+// region SRT
+
+/// Maps the ASS override blocks some SRT files carry onto tags; overrides with no tag
+/// equivalent are dropped and reported.
+fn parse_srt(content: &str, losses: &mut Losses) -> Result<Vec<Cue>, String> {
+    let mut cues = parser::parse_srt(content);
+    let mut unsupported_overrides = 0;
+
+    for cue in &mut cues {
+        if !SRT_OVERRIDE_RE.is_match(&cue.text) {
+            continue;
+        }
+        let mut markup = String::new();
+        let mut open = Vec::new();
+        let mut supported = true;
+        let mut last = 0;
+        for block in SRT_OVERRIDE_RE.find_iter(&cue.text) {
+            markup.push_str(&cue.text[last..block.start()]);
+            last = block.end();
+            let tags = &block.as_str()[1..block.as_str().len() - 1];
+            supported &= apply_override_tags(tags, &mut markup, &mut open, &mut cue.alignment);
+        }
+        markup.push_str(&cue.text[last..]);
+        cue.text = rebalance(&markup);
+        if !supported {
+            unsupported_overrides += 1;
+        }
+    }
+
+    losses.add(unsupported_overrides, "Unsupported override tags were dropped".to_string());
+    Ok(cues)
+}
+
+fn render_srt(cues: &[Cue]) -> String {
+    let cues: Vec<Cue> = cues
+        .iter()
+        .map(|cue| {
+            let text = render_tokens(&tokenize(&cue.text), |token| match token {
+                Markup::Text(text) => text.clone(),
+                Markup::Open(style) => open_tag(style),
+                Markup::Close(style) => close_tag(style).to_string(),
+            });
+            let text = match cue.alignment {
+                Some(alignment) if is_repositioned(cue) => format!("{{\\an{}}}{}", alignment, text),
+                _ => text,
+            };
+            Cue { text, ..cue.clone() }
+        })
+        .collect();
+    subtitle::cues_to_srt(&cues)
+}
+
+fn render_tokens(tokens: &[Markup], map: impl FnMut(&Markup) -> String) -> String {
+    tokens.iter().map(map).collect()
+}
+
+/// Re-renders tags through the tokenizer so interleaved toggles still nest correctly.
+fn rebalance(markup: &str) -> String {
+    render_tokens(&tokenize(markup), |token| match token {
+        Markup::Text(text) => text.clone(),
+        Markup::Open(style) => open_tag(style),
+        Markup::Close(style) => close_tag(style).to_string(),
+    })
+}
+
+// endregion
+
+// region WebVTT
+
+fn parse_vtt(content: &str) -> Result<Vec<Cue>, String> {
+    let mut cues = Vec::new();
+
+    for (block_number, block) in parser::split_blocks(content).into_iter().enumerate() {
+        let first = block[0].trim_start();
+        if block_number == 0 && first.starts_with("WEBVTT") {
+            continue;
+        }
+        if first.starts_with("NOTE") || first.starts_with("STYLE") || first.starts_with("REGION") {
+            continue;
+        }
+
+        let timing_position = block
+            .iter()
+            .position(|line| line.contains("-->"))
+            .ok_or_else(|| format!("VTT block {} has no timing line.", block_number + 1))?;
+        let timing_line = block[timing_position];
+        let (start, rest) = timing_line.split_once("-->").unwrap();
+        let mut rest_parts = rest.split_whitespace();
+        let end = rest_parts.next().unwrap_or_default();
+
+        let mut row_base = None;
+        let mut horizontal = None;
+        for setting in rest_parts {
+            match setting.split_once(':') {
+                Some(("line", value)) => {
+                    let value = value.split(',').next().unwrap_or_default();
+                    row_base = Some(match value.strip_suffix('%') {
+                        Some(percent) => match percent.parse::<f64>().unwrap_or(100.0) {
+                            p if p < 33.0 => 7,
+                            p if p < 66.0 => 4,
+                            _ => 1,
+                        },
+                        None if value.starts_with('-') => 1,
+                        None => 7,
+                    });
+                }
+                Some(("align", value)) => horizontal = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        let alignment = match (row_base, horizontal.as_deref()) {
+            (None, None) => None,
+            (row, horizontal) => Some(numpad_alignment(row.unwrap_or(1), horizontal)),
+        };
+
+        let text = block[timing_position + 1..].join("\n");
+        cues.push(Cue {
+            index: cues.len() + 1,
+            start: Timestamp::parse(start)?,
+            end: Timestamp::parse(end)?,
+            text: vtt_text_to_markup(&text),
+            alignment,
+            timing_suffix: String::new(),
+        });
+    }
+
+    Ok(cues)
+}
+
+fn vtt_text_to_markup(text: &str) -> String {
+    let mut markup = String::new();
+    let mut class_stack: Vec<bool> = Vec::new(); // whether each open <c> became a <font>
+    let mut last = 0;
+
+    for captures in VTT_TAG_RE.captures_iter(text) {
+        let whole = captures.get(0).unwrap();
+        markup.push_str(&decode_entities(&text[last..whole.start()]));
+        last = whole.end();
+
+        let is_close = &captures[1] == "/";
+        let mut name_parts = captures[2].split('.');
+        let name = name_parts.next().unwrap_or_default().to_lowercase();
+        match (name.as_str(), is_close) {
+            ("i" | "b" | "u", _) => markup.push_str(&whole.as_str().to_lowercase()),
+            ("c", false) => {
+                let color = name_parts.find_map(|class| {
+                    color_to_hex(class).filter(|_| NAMED_COLORS.iter().any(|(n, _)| *n == class))
+                });
+                class_stack.push(color.is_some());
+                if let Some(color) = color {
+                    markup.push_str(&format!("<font color=\"{}\">", color));
+                }
+            }
+            ("c", true) if class_stack.pop().unwrap_or(false) => markup.push_str("</font>"),
+            _ => {} // voice, language, ruby and timestamp tags have no SRT equivalent
+        }
+    }
+    markup.push_str(&decode_entities(&text[last..]));
+    markup
+}
+
+fn render_vtt(cues: &[Cue], losses: &mut Losses) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    let mut unsupported_colors = 0;
+
+    for cue in cues {
+        let mut settings = String::new();
+        if let Some(alignment) = cue.alignment.filter(|_| is_repositioned(cue)) {
+            match alignment {
+                7..=9 => settings.push_str(" line:0"),
+                4..=6 => settings.push_str(" line:50%"),
+                _ => {}
+            }
+            match alignment % 3 {
+                1 => settings.push_str(" align:start"),
+                0 => settings.push_str(" align:end"),
+                _ => {}
+            }
+        }
+
+        let mut lost_color = false;
+        let text = render_tokens(&tokenize(&cue.text), |token| match token {
+            Markup::Text(text) => encode_entities(text),
+            Markup::Open(Style::Color(color)) => match color_to_name(color) {
+                Some(name) => format!("<c.{}>", name),
+                None => {
+                    lost_color = true;
+                    "<c>".to_string()
+                }
+            },
+            Markup::Close(Style::Color(_)) => "</c>".to_string(),
+            Markup::Open(style) => open_tag(style),
+            Markup::Close(style) => close_tag(style).to_string(),
+        });
+        if lost_color {
+            unsupported_colors += 1;
+        }
+
+        vtt.push_str(&format!(
+            "{}\n{} --> {}{}\n{}\n\n",
+            cue.index,
+            format_clock(cue.start, '.', true, false),
+            format_clock(cue.end, '.', true, false),
+            settings,
+            text
+        ));
+    }
+
+    losses.add(
+        unsupported_colors,
+        "Colours outside the WebVTT default palette were dropped".to_string(),
+    );
+    vtt
+}
+
+// endregion
+
+// region ASS / SSA
+
+fn parse_ass(content: &str) -> Result<Vec<Cue>, String> {
+    let mut section = String::new();
+    let mut style_format: Vec<String> = Vec::new();
+    let mut event_format: Vec<String> = Vec::new();
+    let mut style_alignments: HashMap<String, u8> = HashMap::new();
+    let mut cues = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            section = line.to_lowercase();
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let fields = |value: &str| -> Vec<String> {
+            value.split(',').map(|f| f.trim().to_lowercase()).collect()
+        };
+
+        match (section.as_str(), key.trim()) {
+            ("[v4+ styles]" | "[v4 styles]", "Format") => style_format = fields(value),
+            ("[v4+ styles]" | "[v4 styles]", "Style") => {
+                let values: Vec<&str> = value.split(',').map(str::trim).collect();
+                let field = |name: &str| {
+                    style_format
+                        .iter()
+                        .position(|f| f == name)
+                        .and_then(|i| values.get(i).copied())
+                };
+                if let (Some(name), Some(alignment)) = (field("name"), field("alignment")) {
+                    if let Ok(alignment) = alignment.parse::<u8>() {
+                        let alignment = if section == "[v4 styles]" {
+                            legacy_ssa_alignment(alignment)
+                        } else {
+                            alignment
+                        };
+                        style_alignments.insert(name.to_string(), alignment);
+                    }
+                }
+            }
+            ("[events]", "Format") => event_format = fields(value),
+            ("[events]", "Dialogue") => {
+                if event_format.is_empty() {
+                    return Err("ASS [Events] section has no Format line.".to_string());
+                }
+                let values: Vec<&str> = value.splitn(event_format.len(), ',').collect();
+                let field = |name: &str| {
+                    event_format
+                        .iter()
+                        .position(|f| f == name)
+                        .and_then(|i| values.get(i).copied())
+                        .unwrap_or_default()
+                };
+
+                let (text, override_alignment) = ass_text_to_markup(field("text"));
+                let style_alignment = style_alignments.get(field("style").trim()).copied();
+                cues.push(Cue {
+                    index: cues.len() + 1,
+                    start: Timestamp::parse(field("start"))?,
+                    end: Timestamp::parse(field("end"))?,
+                    text,
+                    alignment: override_alignment.or(style_alignment),
+                    timing_suffix: String::new(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    cues.sort_by_key(|cue| cue.start);
+    Ok(cues)
+}
+
+/// SSA v4 numbers alignments 1-3 (bottom), 9-11 (middle) and 5-7 (top).
+fn legacy_ssa_alignment(alignment: u8) -> u8 {
+    match alignment {
+        5..=7 => alignment + 2,
+        9..=11 => alignment - 5,
+        other => other,
+    }
+}
+
+/// Converts ASS override blocks into SRT-style tags and returns any `\an` position.
+fn ass_text_to_markup(text: &str) -> (String, Option<u8>) {
+    let mut markup = String::new();
+    let mut alignment = None;
+    let mut open: Vec<Style> = Vec::new();
+    let mut last = 0;
+
+    // `\` is not an escape in ASS, so a literal `\N` is written with a word joiner after
+    // the backslash.
+    let plain = |segment: &str| {
+        segment
+            .replace("\\N", "\n")
+            .replace("\\n", "\n")
+            .replace("\\h", "\u{a0}")
+            .replace("\\\u{2060}", "\\")
+    };
+
+    for block in ASS_OVERRIDE_RE.captures_iter(text) {
+        let whole = block.get(0).unwrap();
+        markup.push_str(&plain(&text[last..whole.start()]));
+        last = whole.end();
+
+        match block.get(1) {
+            Some(tags) => {
+                apply_override_tags(tags.as_str(), &mut markup, &mut open, &mut alignment);
+            }
+            None => markup.push_str(&whole.as_str()[1..]),
+        }
+    }
+    markup.push_str(&plain(&text[last..]));
+
+    (rebalance(&markup), alignment)
+}
+
+/// Appends the SRT-style tags for one override block's `\tag` list, tracking open styles
+/// and the `\an` position. Returns false when a tag had no equivalent and was dropped.
+fn apply_override_tags(
+    tags: &str,
+    markup: &mut String,
+    open: &mut Vec<Style>,
+    alignment: &mut Option<u8>,
+) -> bool {
+    let is_number = |value: &str| !value.is_empty() && value.chars().all(|c| c.is_ascii_digit());
+    let mut supported = true;
+
+    for tag in tags.split('\\').skip(1).map(str::trim) {
+        let toggle = match tag.split_at(tag.chars().next().map_or(0, char::len_utf8)) {
+            ("i", value) if is_number(value) => Some((Style::Italic, value != "0")),
+            ("b", value) if is_number(value) => Some((Style::Bold, value != "0")),
+            ("u", value) if is_number(value) => Some((Style::Underline, value != "0")),
+            _ => None,
+        };
+        if let Some((style, enabled)) = toggle {
+            let is_open = open.contains(&style);
+            if enabled && !is_open {
+                markup.push_str(&open_tag(&style));
+                open.push(style);
+            } else if !enabled && is_open {
+                markup.push_str(close_tag(&style));
+                open.retain(|o| *o != style);
+            }
+            continue;
+        }
+
+        if let Some(value) = tag.strip_prefix("an").filter(|v| is_number(v)) {
+            *alignment = value.parse().ok();
+        } else if let Some(value) = tag
+            .strip_prefix("1c")
+            .or_else(|| tag.strip_prefix('c'))
+            .filter(|v| v.is_empty() || v.starts_with('&'))
+        {
+            if let Some(position) = open.iter().position(|o| matches!(o, Style::Color(_))) {
+                markup.push_str("</font>");
+                open.remove(position);
+            }
+            if let Some(color) = ass_color_to_hex(value) {
+                let style = Style::Color(color);
+                markup.push_str(&open_tag(&style));
+                open.push(style);
+            }
+        } else if tag.starts_with('r') {
+            while let Some(style) = open.pop() {
+                markup.push_str(close_tag(&style));
+            }
+        } else {
+            supported = false;
+        }
+    }
+    supported
+}
+
+/// ASS colours are written `&HBBGGRR&` (optionally with a leading alpha byte).
+fn ass_color_to_hex(value: &str) -> Option<String> {
+    let digits = value
+        .trim_start_matches('&')
+        .trim_start_matches(['H', 'h'])
+        .trim_end_matches('&');
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let padded = format!("{:0>6}", digits);
+    let bgr = &padded[padded.len() - 6..];
+    Some(format!("#{}{}{}", &bgr[4..6], &bgr[2..4], &bgr[0..2]).to_uppercase())
+}
+
+fn hex_to_ass_color(hex: &str) -> String {
+    let rgb = hex.trim_start_matches('#');
+    format!("&H{}{}{}&", &rgb[4..6], &rgb[2..4], &rgb[0..2])
+}
+
+/// Escapes braces and keeps a literal `\N`, `\n` or `\h` from being read as a line break
+/// or hard space.
+fn escape_ass_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' if matches!(chars.peek(), Some('N' | 'n' | 'h')) => escaped.push_str("\\\u{2060}"),
+            '\n' => escaped.push_str("\\N"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_ass(cues: &[Cue], losses: &mut Losses) -> String {
+    let mut ass = String::from(
+        "[Script Info]\n\
+         ScriptType: v4.00+\n\
+         PlayResX: 1920\n\
+         PlayResY: 1080\n\
+         WrapStyle: 0\n\
+         \n\
+         [V4+ Styles]\n\
+         Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+         Style: Default,Arial,64,&H00FFFFFF,&H000000FF,&H00000000,&H64000000,0,0,0,0,100,100,0,0,1,3,1,2,60,60,50,1\n\
+         \n\
+         [Events]\n\
+         Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+    let mut unsupported_colors = 0;
+
+    for cue in cues {
+        let mut lost_color = false;
+        let text = render_tokens(&tokenize(&cue.text), |token| match token {
+            Markup::Text(text) => escape_ass_text(text),
+            Markup::Open(Style::Italic) => "{\\i1}".to_string(),
+            Markup::Close(Style::Italic) => "{\\i0}".to_string(),
+            Markup::Open(Style::Bold) => "{\\b1}".to_string(),
+            Markup::Close(Style::Bold) => "{\\b0}".to_string(),
+            Markup::Open(Style::Underline) => "{\\u1}".to_string(),
+            Markup::Close(Style::Underline) => "{\\u0}".to_string(),
+            Markup::Open(Style::Color(color)) => match color_to_hex(color) {
+                Some(hex) => format!("{{\\c{}}}", hex_to_ass_color(&hex)),
+                None => {
+                    lost_color = true;
+                    String::new()
+                }
+            },
+            Markup::Close(Style::Color(_)) => "{\\c}".to_string(),
+        });
+        if lost_color {
+            unsupported_colors += 1;
+        }
+
+        let position = match cue.alignment {
+            Some(alignment) if is_repositioned(cue) => format!("{{\\an{}}}", alignment),
+            _ => String::new(),
+        };
+        ass.push_str(&format!(
+            "Dialogue: 0,{},{},Default,,0,0,0,,{}{}\n",
+            format_clock(cue.start, '.', false, true),
+            format_clock(cue.end, '.', false, true),
+            position,
+            text
+        ));
+    }
+
+    if cues.iter().any(|cue| cue.start.as_millis() % 10 != 0 || cue.end.as_millis() % 10 != 0) {
+        losses.add(
+            cues.len(),
+            "ASS stores centiseconds, so timestamps were rounded down to 10 ms".to_string(),
+        );
+    }
+    losses.add(unsupported_colors, "Unrecognized colours were dropped".to_string());
+    ass
+}
+
+// endregion
+
+// region SBV
+
+fn parse_sbv(content: &str) -> Result<Vec<Cue>, String> {
+    parser::split_blocks(content)
+        .into_iter()
+        .enumerate()
+        .map(|(block_number, block)| {
+            let (start, end) = block[0].split_once(',').ok_or_else(|| {
+                format!("SBV block {} has an invalid timing line '{}'.", block_number + 1, block[0])
+            })?;
+            Ok(Cue {
+                index: block_number + 1,
+                start: Timestamp::parse(start)?,
+                end: Timestamp::parse(end)?,
+                text: block[1..].join("\n"),
+                alignment: None,
+                timing_suffix: String::new(),
+            })
+        })
+        .collect()
+}
+
+fn render_sbv(cues: &[Cue]) -> String {
+    cues.iter()
+        .map(|cue| {
+            format!(
+                "{},{}\n{}\n\n",
+                format_clock(cue.start, '.', false, false),
+                format_clock(cue.end, '.', false, false),
+                subtitle::strip_markup(&cue.text)
+            )
+        })
+        .collect()
+}
+
+// endregion
+
+// region TTML
+
+fn parse_ttml(content: &str) -> Result<Vec<Cue>, String> {
+    let attributes = |tag: &str| -> HashMap<String, String> {
+        TTML_ATTRIBUTE_RE
+            .captures_iter(tag)
+            .map(|c| {
+                // Drop namespace prefixes such as `tts:` so lookups do not depend on them.
+                let name = c[1].rsplit(':').next().unwrap_or_default().to_string();
+                (name, decode_entities(&c[2]))
+            })
+            .collect()
+    };
+
+    let root_attributes = TTML_ROOT_RE
+        .captures(content)
+        .map(|c| attributes(c.get(1).map_or("", |m| m.as_str())))
+        .ok_or_else(|| "No <tt> root element found; not a TTML document.".to_string())?;
+    let tick_rate = root_attributes
+        .get("tickRate")
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(1.0);
+    let frame_rate = root_attributes
+        .get("frameRate")
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(30.0);
+
+    let mut regions: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for captures in TTML_REGION_RE.captures_iter(content) {
+        let region = attributes(captures.get(1).map_or("", |m| m.as_str()));
+        if let Some(id) = region.get("id") {
+            regions.insert(id.clone(), region);
+        }
+    }
+
+    let mut cues = Vec::new();
+    for captures in TTML_PARAGRAPH_RE.captures_iter(content) {
+        let paragraph = attributes(captures.get(1).map_or("", |m| m.as_str()));
+        let begin = paragraph
+            .get("begin")
+            .ok_or_else(|| "TTML paragraph without a 'begin' attribute.".to_string())?;
+        let start = parse_ttml_time(begin, tick_rate, frame_rate)?;
+        let end = match (paragraph.get("end"), paragraph.get("dur")) {
+            (Some(end), _) => parse_ttml_time(end, tick_rate, frame_rate)?,
+            (None, Some(duration)) => Timestamp(
+                start.as_millis() + parse_ttml_time(duration, tick_rate, frame_rate)?.as_millis(),
+            ),
+            (None, None) => return Err(format!("TTML paragraph at {} has no end time.", start)),
+        };
+
+        let region = paragraph.get("region").and_then(|id| regions.get(id));
+        let lookup = |name: &str| {
+            paragraph
+                .get(name)
+                .or_else(|| region.and_then(|r| r.get(name)))
+                .map(String::as_str)
+        };
+        let row_base = match lookup("displayAlign") {
+            Some("before") => Some(7),
+            Some("center") => Some(4),
+            Some("after") => Some(1),
+            _ => None,
+        };
+        let horizontal = lookup("textAlign");
+        let alignment = match (row_base, horizontal) {
+            (None, None) => None,
+            (row, horizontal) => Some(numpad_alignment(row.unwrap_or(1), horizontal)),
+        };
+
+        let mut text = ttml_styles_to_markup(&paragraph).0;
+        text.push_str(&ttml_content_to_markup(&captures[2], &attributes));
+        text.push_str(&ttml_styles_to_markup(&paragraph).1);
+
+        cues.push(Cue {
+            index: cues.len() + 1,
+            start,
+            end,
+            text: text
+                .lines()
+                .map(str::trim)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            alignment,
+            timing_suffix: String::new(),
+        });
+    }
+
+    if cues.is_empty() {
+        return Err("No timed <p> elements found in the TTML document.".to_string());
+    }
+    cues.sort_by_key(|cue| cue.start);
+    Ok(cues)
+}
+
+/// Accepts clock times (`00:00:01.500`, `00:00:01:12` with frames) and offsets (`1.5s`, `300ms`, `15000000t`).
+fn parse_ttml_time(value: &str, tick_rate: f64, frame_rate: f64) -> Result<Timestamp, String> {
+    let value = value.trim();
+    if let Some(captures) = TTML_OFFSET_RE.captures(value) {
+        let amount: f64 = captures[1]
+            .parse()
+            .map_err(|_| format!("Invalid TTML time '{}'.", value))?;
+        let millis = match &captures[2] {
+            "h" => amount * 3_600_000.0,
+            "m" => amount * 60_000.0,
+            "s" => amount * 1000.0,
+            "ms" => amount,
+            "f" => amount / frame_rate * 1000.0,
+            _ => amount / tick_rate * 1000.0,
+        };
+        return Ok(Timestamp::from_millis(millis.round() as i64));
+    }
+
+    let fields: Vec<&str> = value.split(':').collect();
+    if fields.len() == 4 {
+        // HH:MM:SS:FF
+        let clock = Timestamp::parse(&fields[..3].join(":"))?;
+        let frames: f64 = fields[3]
+            .parse()
+            .map_err(|_| format!("Invalid TTML time '{}'.", value))?;
+        return Ok(Timestamp(
+            clock.as_millis() + (frames / frame_rate * 1000.0).round() as u64,
+        ));
+    }
+    Timestamp::parse(value)
+}
+
+/// Opening and closing SRT-style tags for the `tts:` styling attributes of one element.
+fn ttml_styles_to_markup(attributes: &HashMap<String, String>) -> (String, String) {
+    let mut styles = Vec::new();
+    if attributes.get("fontStyle").is_some_and(|v| v == "italic") {
+        styles.push(Style::Italic);
+    }
+    if attributes.get("fontWeight").is_some_and(|v| v == "bold") {
+        styles.push(Style::Bold);
+    }
+    if attributes.get("textDecoration").is_some_and(|v| v == "underline") {
+        styles.push(Style::Underline);
+    }
+    if let Some(color) = attributes.get("color").and_then(|c| color_to_hex(c)) {
+        styles.push(Style::Color(color));
+    }
+    (
+        styles.iter().map(open_tag).collect(),
+        styles.iter().rev().map(close_tag).collect(),
+    )
+}
+
+fn ttml_content_to_markup(
+    content: &str,
+    attributes: &dyn Fn(&str) -> HashMap<String, String>,
+) -> String {
+    let mut markup = String::new();
+    let mut closers: Vec<String> = Vec::new();
+
+    for token in TTML_TOKEN_RE.captures_iter(content) {
+        let Some(name) = token.get(2) else {
+            let text = WHITESPACE_RE.replace_all(&token[0], " ");
+            markup.push_str(&decode_entities(&text));
+            continue;
+        };
+        let local_name = name.as_str().rsplit(':').next().unwrap_or_default();
+        let is_close = &token[1] == "/";
+        let self_closing = &token[4] == "/";
+        match (local_name, is_close) {
+            ("br", _) => markup.push('\n'),
+            ("span", false) if !self_closing => {
+                let (open, close) = ttml_styles_to_markup(&attributes(&token[3]));
+                markup.push_str(&open);
+                closers.push(close);
+            }
+            ("span", true) => markup.push_str(&closers.pop().unwrap_or_default()),
+            _ => {}
+        }
+    }
+    markup
+}
+
+fn render_ttml(cues: &[Cue], losses: &mut Losses) -> String {
+    let mut ttml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <tt xmlns=\"http://www.w3.org/ns/ttml\" xmlns:tts=\"http://www.w3.org/ns/ttml#styling\" xml:lang=\"\">\n\
+         \x20 <head>\n\
+         \x20   <layout>\n\
+         \x20     <region xml:id=\"top\" tts:origin=\"10% 5%\" tts:extent=\"80% 30%\" tts:displayAlign=\"before\" tts:textAlign=\"center\"/>\n\
+         \x20     <region xml:id=\"middle\" tts:origin=\"10% 35%\" tts:extent=\"80% 30%\" tts:displayAlign=\"center\" tts:textAlign=\"center\"/>\n\
+         \x20     <region xml:id=\"bottom\" tts:origin=\"10% 65%\" tts:extent=\"80% 30%\" tts:displayAlign=\"after\" tts:textAlign=\"center\"/>\n\
+         \x20   </layout>\n\
+         \x20 </head>\n\
+         \x20 <body>\n\
+         \x20   <div>\n",
+    );
+    let mut unsupported_colors = 0;
+
+    for cue in cues {
+        let alignment = cue.alignment.unwrap_or(2);
+        let region = match alignment {
+            7..=9 => "top",
+            4..=6 => "middle",
+            _ => "bottom",
+        };
+        let text_align = match alignment % 3 {
+            1 => " tts:textAlign=\"start\"",
+            0 => " tts:textAlign=\"end\"",
+            _ => "",
+        };
+
+        let mut lost_color = false;
+        let text = render_tokens(&tokenize(&cue.text), |token| match token {
+            Markup::Text(text) => encode_entities(text).replace('\n', "<br/>"),
+            Markup::Open(Style::Italic) => "<span tts:fontStyle=\"italic\">".to_string(),
+            Markup::Open(Style::Bold) => "<span tts:fontWeight=\"bold\">".to_string(),
+            Markup::Open(Style::Underline) => "<span tts:textDecoration=\"underline\">".to_string(),
+            Markup::Open(Style::Color(color)) => match color_to_hex(color) {
+                Some(hex) => format!("<span tts:color=\"{}\">", hex),
+                None => {
+                    lost_color = true;
+                    "<span>".to_string()
+                }
+            },
+            Markup::Close(_) => "</span>".to_string(),
+        });
+        if lost_color {
+            unsupported_colors += 1;
+        }
+
+        ttml.push_str(&format!(
+            "      <p begin=\"{}\" end=\"{}\" region=\"{}\"{}>{}</p>\n",
+            format_clock(cue.start, '.', true, false),
+            format_clock(cue.end, '.', true, false),
+            region,
+            text_align,
+            text
+        ));
+    }
+
+    ttml.push_str("    </div>\n  </body>\n</tt>\n");
+    losses.add(unsupported_colors, "Unrecognized colours were dropped".to_string());
+    ttml
+}
+
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_cues() -> Vec<Cue> {
+        vec![
+            Cue {
+                index: 1,
+                start: Timestamp(1_000),
+                end: Timestamp(2_500),
+                text: "<i>Hello</i> there\nsecond line".to_string(),
+                alignment: None,
+                timing_suffix: String::new(),
+            },
+            Cue {
+                index: 2,
+                start: Timestamp(3_000),
+                end: Timestamp(4_200),
+                text: "<b>Up</b> <font color=\"#FFFF00\">top</font>".to_string(),
+                alignment: Some(8),
+                timing_suffix: String::new(),
+            },
+        ]
+    }
+
+    fn round_trip(format: SubtitleFormat) -> Vec<Cue> {
+        let (content, _) = render(&sample_cues(), format);
+        parse(&content, format).unwrap().0
+    }
+
+    fn assert_timing(cues: &[Cue]) {
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start, cues[0].end), (Timestamp(1_000), Timestamp(2_500)));
+        assert_eq!((cues[1].start, cues[1].end), (Timestamp(3_000), Timestamp(4_200)));
+    }
+
+    #[test]
+    fn styled_formats_round_trip() {
+        for format in [SubtitleFormat::Srt, SubtitleFormat::Vtt, SubtitleFormat::Ass, SubtitleFormat::Ttml] {
+            let cues = round_trip(format);
+            assert_timing(&cues);
+            let expected = sample_cues();
+            assert_eq!(cues[0].text, expected[0].text, "{}", format);
+            assert_eq!(cues[1].text, expected[1].text, "{}", format);
+            assert_eq!(cues[1].alignment, Some(8), "{}", format);
+        }
+    }
+
+    #[test]
+    fn sbv_round_trip_keeps_plain_text() {
+        let cues = round_trip(SubtitleFormat::Sbv);
+        assert_timing(&cues);
+        assert_eq!(cues[0].text, "Hello there\nsecond line");
+        assert_eq!(cues[1].text, "Up top");
+    }
+
+    #[test]
+    fn keeps_text_that_only_looks_like_markup() {
+        let text = "{laughs} x < 5 and y > 3 <i>ok</i>";
+        let rendered = render_tokens(&tokenize(text), |token| match token {
+            Markup::Text(text) => text.clone(),
+            Markup::Open(style) => open_tag(style),
+            Markup::Close(style) => close_tag(style).to_string(),
+        });
+        assert_eq!(rendered, text);
+    }
+
+    #[test]
+    fn srt_maps_override_blocks_but_keeps_braced_text() {
+        let content = "1\n00:00:01,000 --> 00:00:02,000\n{\\an8}{\\i1}{laughs}{\\i0} {\\b1}no{\\b0}\n\n\
+                       2\n00:00:03,000 --> 00:00:04,000\n{\\pos(10,20)\\u1}moved\n";
+        let (cues, losses) = parse(content, SubtitleFormat::Srt).unwrap();
+        assert_eq!(cues[0].text, "<i>{laughs}</i> <b>no</b>");
+        assert_eq!(cues[0].alignment, Some(8));
+        assert_eq!(cues[1].text, "<u>moved</u>");
+        assert_eq!(losses, vec!["Unsupported override tags were dropped in 1 cue(s).".to_string()]);
+    }
+
+    #[test]
+    fn ass_round_trip_keeps_braces_and_backslashes() {
+        let cue = Cue {
+            index: 1,
+            start: Timestamp(1_000),
+            end: Timestamp(2_000),
+            text: "<i>{sighs}</i> C:\\New\nnext {\\i1} }{".to_string(),
+            ..Default::default()
+        };
+        let (content, _) = render(std::slice::from_ref(&cue), SubtitleFormat::Ass);
+        let (cues, _) = parse(&content, SubtitleFormat::Ass).unwrap();
+        assert_eq!(cues[0].text, cue.text);
+    }
+
+    #[test]
+    fn ass_override_with_multibyte_tag_does_not_panic() {
+        let (text, alignment) = ass_text_to_markup("{\\é1\\an7}Héllo");
+        assert_eq!(text, "Héllo");
+        assert_eq!(alignment, Some(7));
+    }
+
+    #[test]
+    fn ttml_accepts_namespace_prefixes() {
+        let content = "<tt:tt xmlns:tt=\"http://www.w3.org/ns/ttml\"><tt:body><tt:div>\
+            <tt:p begin=\"00:00:01.000\" end=\"00:00:02.000\">Hi<tt:br/>there</tt:p>\
+            </tt:div></tt:body></tt:tt>";
+        let (cues, _) = parse(content, SubtitleFormat::Ttml).unwrap();
+        assert_eq!(cues.len(), 1);
+        assert_eq!(cues[0].text, "Hi\nthere");
+    }
+
+    #[test]
+    fn ttml_without_cues_is_an_error() {
+        assert!(parse("<tt><body><div></div></body></tt>", SubtitleFormat::Ttml).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::logger::Logger;
+use crate::translator;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Glossary"));
+
+/// Glossary file picked up from the input folder and every folder between it and a subtitle.
+pub const GLOSSARY_FILE_NAME: &str = "glossary.toml";
+
+/// A required rendering of a source term.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct GlossaryEntry {
+    pub source: String,
+    pub target: String,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    #[serde(skip)]
+    pattern: Option<Regex>,
+}
+
+impl GlossaryEntry {
+    pub fn new(source: String, target: String, notes: String) -> Result<Self, String> {
+        let mut entry = GlossaryEntry {
+            source,
+            target,
+            case_sensitive: false,
+            notes,
+            pattern: None,
+        };
+        entry.compile()?;
+        Ok(entry)
+    }
+
+    fn compile(&mut self) -> Result<(), String> {
+        let term = self.source.trim();
+        let flags = if self.case_sensitive { "" } else { "(?i)" };
+        // Word boundaries only where the term itself starts or ends with a word character,
+        // so terms like "Dr." still match.
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        let start = if term.starts_with(is_word_char) { r"\b" } else { "" };
+        let end = if term.ends_with(is_word_char) { r"\b" } else { "" };
+        let pattern = format!("{}{}{}{}", flags, start, regex::escape(term), end);
+        self.pattern = Some(
+            Regex::new(&pattern).map_err(|e| format!("Invalid glossary term '{}': {}", self.source, e))?,
+        );
+        Ok(())
+    }
+
+    fn appears_in(&self, text: &str) -> bool {
+        self.pattern.as_ref().is_some_and(|pattern| pattern.is_match(text))
+    }
+
+    fn rendered_in(&self, text: &str) -> bool {
+        if self.case_sensitive {
+            text.contains(self.target.trim())
+        } else {
+            text.to_lowercase().contains(&self.target.trim().to_lowercase())
+        }
+    }
+
+    /// Entries for the same term replace each other when glossaries are merged.
+    fn merge_key(&self) -> String {
+        if self.case_sensitive {
+            self.source.trim().to_string()
+        } else {
+            self.source.trim().to_lowercase()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct GlossaryFile {
+    #[serde(default, rename = "term")]
+    terms: Vec<GlossaryEntry>,
+}
+
+/// A translated line that does not use the required rendering of a glossary term.
+#[derive(Serialize, Debug, Clone)]
+pub struct GlossaryViolation {
+    /// 1-based cue and line numbers within the cue.
+    pub cue: usize,
+    pub line: usize,
+    pub term: String,
+    pub expected: String,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct Glossary {
+    entries: Vec<GlossaryEntry>,
+    sources: Vec<PathBuf>,
+}
+
+impl Glossary {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Files the glossary was merged from, lowest priority first.
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Builds the glossary for `subtitle_path`: the project glossary (if any), then a
+    /// `glossary.toml` in `input_root` and in every folder down to the subtitle's own, the
+    /// nearest folder winning when a term is defined twice.
+    pub fn load_for(subtitle_path: &Path, input_root: &Path, project_file: Option<&Path>) -> Result<Self, String> {
+        if let Some(project_file) = project_file.filter(|file| !file.is_file()) {
+            return Err(format!("Glossary file '{}' does not exist.", project_file.display()));
+        }
+        let mut files: Vec<PathBuf> = project_file.map(Path::to_path_buf).into_iter().collect();
+        if let Some(subtitle_dir) = subtitle_path.parent() {
+            let mut folders: Vec<&Path> = subtitle_dir
+                .ancestors()
+                .take_while(|folder| folder.starts_with(input_root))
+                .collect();
+            if folders.is_empty() {
+                folders.push(subtitle_dir);
+            }
+            files.extend(folders.iter().rev().map(|folder| folder.join(GLOSSARY_FILE_NAME)));
+        }
+
+        let mut glossary = Glossary::default();
+        for file in files.iter().filter(|file| file.is_file()) {
+            glossary.merge(read_file(file)?);
+            glossary.sources.push(file.clone());
+        }
+        Ok(glossary)
+    }
+
+    /// Reads a single glossary file.
+    pub fn load_file(path: &Path) -> Result<Self, String> {
+        Ok(Glossary {
+            entries: read_file(path)?,
+            sources: vec![path.to_path_buf()],
+        })
+    }
+
+    pub fn contains(&self, term: &str) -> bool {
+        self.entries.iter().any(|entry| match entry.case_sensitive {
+            true => entry.source.trim() == term.trim(),
+            false => entry.source.trim().to_lowercase() == term.trim().to_lowercase(),
+        })
+    }
+
+    /// Adds entries, replacing existing entries for the same terms.
+    pub fn merge(&mut self, entries: Vec<GlossaryEntry>) {
+        for entry in entries {
+            let key = entry.merge_key();
+            match self.entries.iter_mut().find(|existing| existing.merge_key() == key) {
+                Some(existing) => *existing = entry,
+                None => self.entries.push(entry),
+            }
+        }
+    }
+
+    /// Prompt block listing the terms that occur in the `<id>_<text>` lines of `chunk_text`,
+    /// or `None` when none do.
+    pub fn render_for(&self, chunk_text: &str) -> Option<String> {
+        // The id prefix ends in `_`, a word character, so terms are matched in the text alone.
+        let texts: Vec<&str> = chunk_text
+            .lines()
+            .map(|line| line.split_once('_').map_or(line, |(_, text)| text))
+            .collect();
+        let lines: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|entry| texts.iter().any(|text| entry.appears_in(text)))
+            .map(|entry| match entry.notes.is_empty() {
+                true => format!("{} => {}", entry.source, entry.target),
+                false => format!("{} => {} ({})", entry.source, entry.target, entry.notes),
+            })
+            .collect();
+        if lines.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "GLOSSARY (always render these terms exactly this way):\n{}\nEND OF GLOSSARY\n\n",
+            lines.join("\n")
+        ))
+    }
+
+    /// Compares `<id>_<text>` source and translated lines and reports every translated line
+    /// missing the required form of a glossary term found in its source line.
+    pub fn check(&self, source_text: &str, translated_text: &str) -> Vec<GlossaryViolation> {
+        if self.entries.is_empty() {
+            return Vec::new();
+        }
+        let sources: HashMap<&str, &str> = source_text
+            .lines()
+            .filter_map(|line| line.split_once('_'))
+            .collect();
+        let mut violations = Vec::new();
+        for (id, translated) in translated_text.lines().filter_map(|line| line.split_once('_')) {
+            let Some(source) = sources.get(id) else {
+                continue;
+            };
+            let (cue, line) = translator::parse_line_id(id);
+            for entry in self.entries.iter().filter(|entry| entry.appears_in(source)) {
+                if !entry.rendered_in(translated) {
+                    violations.push(GlossaryViolation {
+                        cue,
+                        line,
+                        term: entry.source.clone(),
+                        expected: entry.target.clone(),
+                    });
+                }
+            }
+        }
+        violations
+    }
+
+    /// Writes the glossary in the format `load_for` reads.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let file = GlossaryFile {
+            terms: self.entries.clone(),
+        };
+        let content = toml::to_string_pretty(&file).map_err(|e| format!("Failed to serialize glossary: {}", e))?;
+        fs::write(path, content).map_err(|e| format!("Failed to write glossary '{}': {}", path.display(), e))
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<GlossaryEntry>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read glossary '{}': {}", path.display(), e))?;
+    let mut file: GlossaryFile = toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse glossary '{}': {}", path.display(), e.to_string().trim().replace('\n', " ")))?;
+    file.terms.retain(|entry| !entry.source.trim().is_empty());
+    for entry in &mut file.terms {
+        entry.compile()?;
+    }
+    LOGGER.debug(format!("Loaded {} glossary term(s) from '{}'.", file.terms.len(), path.display()).as_str());
+    Ok(file.terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(source: &str, target: &str) -> GlossaryEntry {
+        GlossaryEntry::new(source.to_string(), target.to_string(), String::new()).unwrap()
+    }
+
+    fn glossary(entries: Vec<GlossaryEntry>) -> Glossary {
+        let mut glossary = Glossary::default();
+        glossary.merge(entries);
+        glossary
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ai_translate_glossary_test_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn terms_match_whole_words_only() {
+        let captain = entry("Captain Hook", "Kapitän Hook");
+        assert!(captain.appears_in("Here comes captain hook!"));
+        assert!(!captain.appears_in("Captain Hooked"));
+
+        let doctor = entry("Dr.", "Doktor");
+        assert!(doctor.appears_in("Ask Dr. Smith."));
+        assert!(doctor.appears_in("Ask Dr."));
+        assert!(!doctor.appears_in("Ask Drew."));
+
+        let mut exact = entry("May", "Mai");
+        exact.case_sensitive = true;
+        exact.compile().unwrap();
+        assert!(exact.appears_in("See you in May"));
+        assert!(!exact.appears_in("You may go"));
+    }
+
+    #[test]
+    fn render_lists_only_terms_in_the_chunk() {
+        let mut noted = entry("Hook", "Hook");
+        noted.notes = "a name, keep it".to_string();
+        let glossary = glossary(vec![entry("Neverland", "Nimmerland"), noted]);
+
+        let block = glossary.render_for("0.0_HOOK is here").unwrap();
+        assert_eq!(
+            block,
+            "GLOSSARY (always render these terms exactly this way):\nHook => Hook (a name, keep it)\nEND OF GLOSSARY\n\n"
+        );
+        assert!(glossary.render_for("0.0_Nothing to see").is_none());
+    }
+
+    #[test]
+    fn check_reports_missing_renderings_with_cue_and_line() {
+        let glossary = glossary(vec![entry("Neverland", "Nimmerland")]);
+        let source = "0.0_Welcome to Neverland\n2.1_Back to neverland\n3.0_Hello";
+        let translated = "0.0_Willkommen im NIMMERLAND\n2.1_Zurück ins Niemandsland\n3.0_Hallo";
+
+        let violations = glossary.check(source, translated);
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].cue, violations[0].line), (3, 2));
+        assert_eq!(violations[0].term, "Neverland");
+        assert_eq!(violations[0].expected, "Nimmerland");
+    }
+
+    #[test]
+    fn merge_replaces_the_same_term() {
+        let mut glossary = glossary(vec![entry("Hook", "Haken"), entry("Smee", "Smee")]);
+        glossary.merge(vec![entry("hook", "Hook")]);
+        assert_eq!(glossary.len(), 2);
+        assert_eq!(glossary.render_for("0.0_Hook").unwrap().lines().nth(1), Some("hook => Hook"));
+    }
+
+    #[test]
+    fn nearest_folder_glossary_wins() {
+        let root = temp_dir("nearest");
+        let season = root.join("Season 1");
+        fs::create_dir_all(&season).unwrap();
+        let project = root.join("project.toml");
+        fs::write(&project, "[[term]]\nsource = \"Hook\"\ntarget = \"project\"\n\n[[term]]\nsource = \"Smee\"\ntarget = \"Smee\"\n").unwrap();
+        fs::write(root.join(GLOSSARY_FILE_NAME), "[[term]]\nsource = \"Hook\"\ntarget = \"root\"\n").unwrap();
+        fs::write(season.join(GLOSSARY_FILE_NAME), "[[term]]\nsource = \"hook\"\ntarget = \"season\"\n").unwrap();
+
+        let glossary = Glossary::load_for(&season.join("E01.srt"), &root, Some(&project)).unwrap();
+        assert_eq!(glossary.len(), 2);
+        assert!(glossary.render_for("0.0_Hook and Smee").unwrap().contains("hook => season"));
+        assert_eq!(
+            glossary.sources(),
+            &[project, root.join(GLOSSARY_FILE_NAME), season.join(GLOSSARY_FILE_NAME)]
+        );
+
+        // Folders above the input root are not searched.
+        let glossary = Glossary::load_for(&season.join("E01.srt"), &season, None).unwrap();
+        assert_eq!(glossary.sources(), &[season.join(GLOSSARY_FILE_NAME)]);
+    }
+
+    #[test]
+    fn missing_project_glossary_is_an_error() {
+        let root = temp_dir("missing");
+        assert!(Glossary::load_for(&root.join("E01.srt"), &root, Some(&root.join("none.toml"))).is_err());
+    }
+}
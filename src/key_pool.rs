@@ -0,0 +1,309 @@
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use crate::logger::Logger;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("KeyPool"));
+
+/// One API key as written in a key file, environment variable or saved by `login`.
+#[derive(Debug, Clone)]
+pub struct KeyEntry {
+    pub label: Option<String>,
+    pub key: String,
+}
+
+/// Parses keys separated by newlines or commas; each may be written as `label=key`.
+/// Blank entries and lines starting with `#` are ignored.
+pub fn parse_key_list(text: &str) -> Vec<KeyEntry> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((label, key)) if !label.trim().is_empty() => KeyEntry {
+                label: Some(label.trim().to_string()),
+                key: key.trim().to_string(),
+            },
+            _ => KeyEntry {
+                label: None,
+                key: entry.to_string(),
+            },
+        })
+        .filter(|entry| !entry.key.is_empty())
+        .collect()
+}
+
+/// Writes entries back in the format `parse_key_list` reads.
+pub fn format_key_list(entries: &[KeyEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| match &entry.label {
+            Some(label) => format!("{}={}", label, entry.key),
+            None => entry.key.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+struct PooledKey {
+    label: String,
+    key: String,
+    requests: usize,
+    successes: usize,
+    rate_limited: usize,
+    total_tokens: usize,
+    cooldown_until: Option<Instant>,
+}
+
+impl PooledKey {
+    fn is_cooling_down(&self, now: Instant) -> bool {
+        self.cooldown_until.is_some_and(|until| until > now)
+    }
+}
+
+/// The API keys available to a run. Requests use the current key until it is rate limited,
+/// then move on to the next key that is not cooling down.
+pub struct KeyPool {
+    keys: Vec<PooledKey>,
+    current: usize,
+}
+
+impl PooledKey {
+    fn new(position: usize, entry: KeyEntry) -> Self {
+        PooledKey {
+            // Only the last characters of a key are shown, never the whole key.
+            label: entry.label.unwrap_or_else(|| {
+                let suffix: String = entry
+                    .key
+                    .chars()
+                    .rev()
+                    .take(4)
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .rev()
+                    .collect();
+                format!("key {} (…{})", position + 1, suffix)
+            }),
+            key: entry.key,
+            requests: 0,
+            successes: 0,
+            rate_limited: 0,
+            total_tokens: 0,
+            cooldown_until: None,
+        }
+    }
+}
+
+impl KeyPool {
+    /// Builds a pool from `entries`, which must hold at least one key.
+    pub fn new(entries: Vec<KeyEntry>) -> Result<Self, String> {
+        if entries.is_empty() {
+            return Err("No API keys were given.".to_string());
+        }
+        let keys = entries
+            .into_iter()
+            .enumerate()
+            .map(|(position, entry)| PooledKey::new(position, entry))
+            .collect();
+        Ok(KeyPool { keys, current: 0 })
+    }
+
+    pub fn single(key: String) -> Self {
+        KeyPool {
+            keys: vec![PooledKey::new(0, KeyEntry { label: None, key })],
+            current: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn current_key(&self) -> &str {
+        &self.keys[self.current].key
+    }
+
+    pub fn current_label(&self) -> &str {
+        &self.keys[self.current].label
+    }
+
+    /// Position of the current key, used to credit a request to the key it was sent with
+    /// even when another request has rotated the pool since.
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    pub fn record_request(&mut self, index: usize) {
+        self.keys[index].requests += 1;
+    }
+
+    pub fn record_success(&mut self, index: usize) {
+        self.keys[index].successes += 1;
+    }
+
+    pub fn record_tokens(&mut self, index: usize, total_tokens: usize) {
+        self.keys[index].total_tokens += total_tokens;
+    }
+
+    /// Puts the key at `index` on cooldown after a quota or rate-limit error.
+    pub fn record_rate_limited(&mut self, index: usize, cooldown: Duration) {
+        let key = &mut self.keys[index];
+        key.rate_limited += 1;
+        key.cooldown_until = Some(Instant::now() + cooldown);
+    }
+
+    /// Switches to the next key that is not cooling down. Returns false, and selects the key
+    /// whose cooldown ends first, when every key is cooling down.
+    pub fn rotate(&mut self) -> bool {
+        let now = Instant::now();
+        let count = self.keys.len();
+        if let Some(next) = (1..count)
+            .map(|offset| (self.current + offset) % count)
+            .find(|&index| !self.keys[index].is_cooling_down(now))
+        {
+            self.current = next;
+            return true;
+        }
+        if let Some((soonest, _)) = self
+            .keys
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, key)| key.cooldown_until)
+        {
+            self.current = soonest;
+        }
+        false
+    }
+
+    /// Time until the current key's cooldown ends, if it is cooling down.
+    pub fn current_cooldown(&self) -> Option<Duration> {
+        self.keys[self.current]
+            .cooldown_until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+    }
+
+    pub fn log_usage(&self) {
+        if self.keys.iter().all(|key| key.requests == 0) {
+            return;
+        }
+        LOGGER.bold("API key usage for this run:");
+        for key in &self.keys {
+            LOGGER.info(
+                format!(
+                    "{}: {} request(s), {} successful, {} rate limited, {} token(s)",
+                    key.label, key.requests, key.successes, key.rate_limited, key.total_tokens
+                )
+                .as_str(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(entries: &[KeyEntry]) -> Vec<(Option<&str>, &str)> {
+        entries.iter().map(|entry| (entry.label.as_deref(), entry.key.as_str())).collect()
+    }
+
+    #[test]
+    fn parses_labels_commas_and_comments() {
+        let entries = parse_key_list("# keys\nwork = AIzaA\n\nAIzaB, home=AIzaC ,\n=AIzaD\nempty=\n");
+        assert_eq!(
+            pairs(&entries),
+            vec![
+                (Some("work"), "AIzaA"),
+                (None, "AIzaB"),
+                (Some("home"), "AIzaC"),
+                (None, "=AIzaD"),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatted_list_parses_back_to_the_same_entries() {
+        let entries = vec![
+            KeyEntry { label: Some("work".to_string()), key: "AIzaA".to_string() },
+            KeyEntry { label: None, key: "AIzaB".to_string() },
+        ];
+        let formatted = format_key_list(&entries);
+        assert_eq!(formatted, "work=AIzaA\nAIzaB");
+        assert_eq!(pairs(&parse_key_list(&formatted)), pairs(&entries));
+    }
+
+    fn pool(count: usize) -> KeyPool {
+        let entries = (0..count)
+            .map(|n| KeyEntry { label: None, key: format!("AIzaKey{}", n) })
+            .collect();
+        KeyPool::new(entries).unwrap()
+    }
+
+    const LONG: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn empty_pool_is_an_error() {
+        assert!(KeyPool::new(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn labels_show_only_the_end_of_a_key() {
+        let pool = KeyPool::single("AIzaSecretEnding".to_string());
+        assert_eq!(pool.current_label(), "key 1 (…ding)");
+    }
+
+    #[test]
+    fn rotation_skips_keys_that_are_cooling_down() {
+        let mut pool = pool(3);
+        pool.record_rate_limited(0, LONG);
+        pool.record_rate_limited(1, LONG);
+        assert!(pool.rotate());
+        assert_eq!(pool.current_index(), 2);
+        assert_eq!(pool.current_key(), "AIzaKey2");
+
+        // A cooldown that has ended no longer counts.
+        pool.record_rate_limited(0, Duration::ZERO);
+        assert!(pool.rotate());
+        assert_eq!(pool.current_index(), 0);
+    }
+
+    #[test]
+    fn rotation_falls_back_to_the_soonest_cooldown() {
+        let mut pool = pool(3);
+        pool.record_rate_limited(0, LONG * 3);
+        pool.record_rate_limited(1, LONG);
+        pool.record_rate_limited(2, LONG * 2);
+        assert!(!pool.rotate());
+        assert_eq!(pool.current_index(), 1);
+        assert!(pool.current_cooldown().is_some_and(|left| left <= LONG));
+    }
+
+    #[test]
+    fn single_key_pool_has_nothing_to_rotate_to() {
+        let mut pool = pool(1);
+        pool.record_rate_limited(0, LONG);
+        assert!(!pool.rotate());
+        assert_eq!(pool.current_index(), 0);
+    }
+
+    #[test]
+    fn usage_is_credited_to_the_key_a_request_used() {
+        let mut pool = pool(2);
+        let sent_with = pool.current_index();
+        pool.record_request(sent_with);
+        // Another request rotates the pool before this one finishes.
+        pool.record_rate_limited(sent_with, LONG);
+        assert!(pool.rotate());
+        pool.record_success(sent_with);
+        pool.record_tokens(sent_with, 120);
+
+        let first = &pool.keys[0];
+        assert_eq!((first.requests, first.successes, first.rate_limited, first.total_tokens), (1, 1, 1, 120));
+        let second = &pool.keys[1];
+        assert_eq!((second.requests, second.successes, second.rate_limited, second.total_tokens), (0, 0, 0, 0));
+    }
+}
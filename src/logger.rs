@@ -1,5 +1,9 @@
 #![allow(dead_code)]
 
+use std::sync::{LazyLock, RwLock};
+
+use regex::Regex;
+
 // ANSI escape codes for colors and styles
 const RESET: &str = "\x1b[0m";
 const RED: &str = "\x1b[31m";
@@ -17,6 +21,52 @@ const SUCCESS_ICON: &str = "✅";
 const DEBUG_ICON: &str = "🐞";
 const GEAR_ICON: &str = "⚙️";
 
+/// Values registered at runtime (API keys) that must never reach the terminal or a log.
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Key-like text caught even when it was never registered: Google API keys, `key=` query
+/// parameters and credential headers.
+static SECRET_PATTERNS: LazyLock<[Regex; 3]> = LazyLock::new(|| {
+    [
+        Regex::new(r"AIza[0-9A-Za-z_\-]{35}").unwrap(),
+        Regex::new(r#"(?i)([?&](?:key|api_key|access_token)=)[^&\s'"]+"#).unwrap(),
+        Regex::new(r#"(?i)((?:x-goog-api-key|authorization)\s*[:=]\s*(?:bearer\s+)?)[^\s,;'"]+"#).unwrap(),
+    ]
+});
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Masks `secret` in every message logged from now on.
+pub fn register_secret(secret: &str) {
+    let secret = secret.trim();
+    // Very short values would mask ordinary words; real keys are far longer.
+    if secret.len() < 8 {
+        return;
+    }
+    if let Ok(mut secrets) = SECRETS.write() {
+        if !secrets.iter().any(|known| known == secret) {
+            secrets.push(secret.to_string());
+        }
+    }
+}
+
+pub fn redact(message: &str) -> String {
+    let mut redacted = message.to_string();
+    if let Ok(secrets) = SECRETS.read() {
+        for secret in secrets.iter() {
+            redacted = redacted.replace(secret.as_str(), REDACTED);
+        }
+    }
+    let [google_key, query_key, header_key] = &*SECRET_PATTERNS;
+    redacted = google_key.replace_all(&redacted, REDACTED).into_owned();
+    redacted = query_key
+        .replace_all(&redacted, format!("${{1}}{}", REDACTED))
+        .into_owned();
+    header_key
+        .replace_all(&redacted, format!("${{1}}{}", REDACTED))
+        .into_owned()
+}
+
 pub struct Logger {
     module_name: &'static str,
 }
@@ -39,8 +89,9 @@ impl Logger {
     }
 
     pub fn info(&self, message: &str) {
-        print!(
-            "{}: {}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}",
             self.format_prefix(BLUE, INFO_ICON, "INFO"),
             BLUE,
             message,
@@ -49,8 +100,9 @@ impl Logger {
     }
 
     pub fn warning(&self, message: &str) {
-        print!(
-            "{}: {}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}",
             self.format_prefix(YELLOW, WARNING_ICON, "WARNING"),
             YELLOW,
             message,
@@ -59,8 +111,9 @@ impl Logger {
     }
 
     pub fn error(&self, message: &str) {
-        print!(
-            "{}: {}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}",
             self.format_prefix(RED, ERROR_ICON, "ERROR"),
             RED,
             message,
@@ -69,8 +122,9 @@ impl Logger {
     }
 
     pub fn success(&self, message: &str) {
-        print!(
-            "{}: {}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}",
             self.format_prefix(GREEN, SUCCESS_ICON, "SUCCESS"),
             GREEN,
             message,
@@ -79,14 +133,17 @@ impl Logger {
     }
 
     pub fn log(&self, message: &str) {
+        let message = redact(message);
         // For general, unstyled messages, or specific formatting needs
         print!("{}", message);
     }
 
     pub fn debug(&self, message: &str) {
+        // Checked first so debug messages cost nothing to redact in release builds.
         if cfg!(debug_assertions) {
-            print!(
-                "{}: {}{}{}\n",
+            let message = redact(message);
+            println!(
+                "{}: {}{}{}",
                 self.format_prefix(DIM, DEBUG_ICON, "DEBUG"),
                 DIM,
                 message,
@@ -96,12 +153,14 @@ impl Logger {
     }
 
     pub fn bold(&self, message: &str) {
-        print!("{}{}{}\n", BOLD, message, RESET);
+        let message = redact(message);
+        println!("{}{}{}", BOLD, message, RESET);
     }
 
     pub fn bold_message_info(&self, message: &str) {
-        print!(
-            "{}: {}{}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}{}",
             self.format_prefix(BLUE, INFO_ICON, "INFO"),
             BLUE,
             BOLD,
@@ -111,8 +170,9 @@ impl Logger {
     }
 
     pub fn bold_message_warning(&self, message: &str) {
-        print!(
-            "{}: {}{}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}{}",
             self.format_prefix(YELLOW, WARNING_ICON, "WARNING"),
             YELLOW,
             BOLD,
@@ -122,8 +182,9 @@ impl Logger {
     }
 
     pub fn bold_message_error(&self, message: &str) {
-        print!(
-            "{}: {}{}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}{}",
             self.format_prefix(RED, ERROR_ICON, "ERROR"),
             RED,
             BOLD,
@@ -133,8 +194,9 @@ impl Logger {
     }
 
     pub fn bold_message_success(&self, message: &str) {
-        print!(
-            "{}: {}{}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}{}",
             self.format_prefix(GREEN, SUCCESS_ICON, "SUCCESS"),
             GREEN,
             BOLD,
@@ -144,8 +206,9 @@ impl Logger {
     }
 
     pub fn process(&self, message: &str) {
-        print!(
-            "{}: {}{}{}\n",
+        let message = redact(message);
+        println!(
+            "{}: {}{}{}",
             self.format_prefix(BLUE, GEAR_ICON, "PROCESS"), // Using blue and gear for general processes
             BLUE,
             message,
@@ -156,3 +219,38 @@ impl Logger {
 
 // ✅
 // ℹ️
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_secrets_are_masked() {
+        register_secret("  my-own-secret-token  ");
+        assert_eq!(redact("using my-own-secret-token now"), "using [REDACTED] now");
+    }
+
+    #[test]
+    fn short_values_are_not_registered() {
+        register_secret("abc");
+        assert_eq!(redact("abc abcdef"), "abc abcdef");
+    }
+
+    #[test]
+    fn key_like_text_is_masked_without_registration() {
+        let google_key = format!("AIza{}", "x".repeat(35));
+        assert_eq!(redact(&format!("bad key {}.", google_key)), "bad key [REDACTED].");
+        assert_eq!(
+            redact("GET https://host/v1/models?alt=sse&key=abc123 failed"),
+            "GET https://host/v1/models?alt=sse&key=[REDACTED] failed"
+        );
+        assert_eq!(redact("x-goog-api-key: abc123"), "x-goog-api-key: [REDACTED]");
+        assert_eq!(redact("Authorization: Bearer abc.def"), "Authorization: Bearer [REDACTED]");
+    }
+
+    #[test]
+    fn ordinary_text_is_untouched() {
+        let message = "Translated 12 lines; the key to success is a keyboard and AIza short.";
+        assert_eq!(redact(message), message);
+    }
+}
@@ -1,11 +1,16 @@
 use std::{
-    path::PathBuf,
+    env,
+    path::{Path, PathBuf},
+    process,
     sync::LazyLock,
-    io::{self, Write},
+    io::{self, IsTerminal, Write},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 // Declare modules
 mod utils;
+mod config;
+mod credentials;
 mod logger;
 mod parser;
 mod queue;
@@ -13,11 +18,82 @@ mod scanner;
 mod translator;
 mod writer;
 mod cli_handler;
+mod subtitle;
+mod timing;
+mod validator;
+mod repair;
+mod formats;
+mod circuit_breaker;
+mod key_pool;
+mod run_report;
+mod prompt_cache;
+mod glossary;
+mod term_extractor;
+mod translation_memory;
+mod alignment;
+mod token_budget;
+mod rate_limiter;
 
 // Global logger for main operations
 static LOGGER: LazyLock<logger::Logger> = LazyLock::new(|| logger::Logger::new("Application"));
 
+/// Per-run choices that shape how each subtitle file is processed.
+struct PipelineOptions {
+    max_line_length: usize,
+    timing_adjustments: Vec<timing::TimingAdjustment>,
+    repair_sources: bool,
+    overwrite: bool,
+    bilingual: Option<parser::BilingualOptions>,
+    translation: translator::TranslationOptions,
+    /// Project glossary applied below the `glossary.toml` files of the input folders.
+    glossary_file: Option<PathBuf>,
+}
+
 fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let loaded_config = match config::extract_overrides(&mut args).and_then(|overrides| config::load(&overrides)) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            LOGGER.error(&e);
+            process::exit(2);
+        }
+    };
+    if args.first().map(String::as_str) == Some("config") {
+        process::exit(run_config_command(&args[1..], &loaded_config));
+    }
+    config::init(loaded_config.config);
+
+    let Some(command) = args.first() else {
+        run_interactive();
+        return;
+    };
+
+    let exit_code = match command.as_str() {
+        "translate" => run_translate_command(&args[1..]),
+        "glossary" => run_glossary_command(&args[1..]),
+        "memory" => run_memory_command(&args[1..]),
+        "scan" => run_scan_command(&args[1..]),
+        "status" => run_status_command(&args[1..]),
+        "validate" => run_validate_command(&args[1..]),
+        "convert" => run_convert_command(&args[1..]),
+        "fix" => run_fix_command(&args[1..]),
+        "timing" => run_timing_command(&args[1..]),
+        "login" => run_login_command(&args[1..]),
+        "logout" => run_logout_command(&args[1..]),
+        "help" | "--help" | "-h" => {
+            LOGGER.log(format!("{}\n", cli_handler::USAGE).as_str());
+            0
+        }
+        other => {
+            LOGGER.error(format!("Unknown command '{}'.", other).as_str());
+            LOGGER.log(format!("{}\n", cli_handler::USAGE).as_str());
+            2
+        }
+    };
+    process::exit(exit_code);
+}
+
+fn run_interactive() {
     print_welcome_message();
 
     let max_line_length = cli_handler::get_max_line_length_input();
@@ -30,22 +106,70 @@ fn main() {
     );
     LOGGER.log("\n");
 
-    let mut gemini_api_key = match get_api_key_loop() {
-        Some(key) => key,
-        None => {
-            LOGGER.bold("Program terminated by user.");
-            return;
+    let timing_adjustments = cli_handler::get_timing_adjustments_input();
+    LOGGER.log("\n");
+
+    let repair_sources = cli_handler::get_repair_input();
+    LOGGER.log("\n");
+
+    let bilingual = cli_handler::get_bilingual_input(max_line_length);
+    LOGGER.log("\n");
+
+    let pipeline_options = PipelineOptions {
+        max_line_length,
+        timing_adjustments,
+        repair_sources,
+        overwrite: false,
+        bilingual,
+        translation: translator::TranslationOptions::from_config(config::get()),
+        glossary_file: Some(PathBuf::from(&config::get().glossary_file))
+            .filter(|file| !file.as_os_str().is_empty()),
+    };
+
+    let stored_keys = match credentials::resolve_api_keys(pipeline_options.translation.backend, None) {
+        Ok(resolved) => resolved,
+        Err(e) => {
+            LOGGER.warning(format!("{} Falling back to the prompt.", e).as_str());
+            None
+        }
+    };
+    let key_pool = match stored_keys.map(|(entries, source)| (key_pool::KeyPool::new(entries), source)) {
+        Some((Ok(key_pool), source)) => {
+            LOGGER.success(
+                format!(
+                    "Using {} API key(s) from {}. Type 'c' after a folder to change it.\n",
+                    key_pool.len(),
+                    source
+                )
+                .as_str(),
+            );
+            key_pool
         }
+        _ => match get_api_key_loop() {
+            Some(key) => key_pool::KeyPool::single(key),
+            None => {
+                LOGGER.bold("Program terminated by user.");
+                return;
+            }
+        },
     };
 
+    let mut session = translator::TranslationSession::new(key_pool, &pipeline_options.translation);
+    session.memory = open_translation_memory(config::get().translation_memory);
     let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
 
-    loop {
-        match get_directory_path_loop() {
-            Some(dir_path) => {
-                process_directory(&dir_path, &mut subtitles_queue, &gemini_api_key, max_line_length);
-            }
-            None => break, // Exit main loop if user chose to exit during path input
+    // Exit main loop if user chose to exit during path input
+    while let Some(dir_path) = get_directory_path_loop() {
+        let mut report = run_report::RunReport::new(&dir_path, &pipeline_options.translation);
+        process_directory(
+            &dir_path,
+            &mut subtitles_queue,
+            &mut session,
+            &pipeline_options,
+            &mut report,
+        );
+        if let Err(e) = report.write(None) {
+            LOGGER.error(&e);
         }
 
         LOGGER.bold("Process another folder? (y/n) or (c) to change API key:");
@@ -59,7 +183,15 @@ fn main() {
             } else if choice == "c" {
                  LOGGER.info("User chose to change API key.");
                  match get_api_key_loop() {
-                    Some(key) => gemini_api_key = key,
+                    Some(key) => {
+                        session.finish();
+                        let memory = session.memory.take();
+                        session = translator::TranslationSession::new(
+                            key_pool::KeyPool::single(key),
+                            &pipeline_options.translation,
+                        );
+                        session.memory = memory;
+                    }
                     None => { // User chose to exit during API key input
                         LOGGER.info("User chose to exit during API key change.");
                         break;
@@ -76,6 +208,7 @@ fn main() {
         LOGGER.log("\n============================================\n");
         LOGGER.info("Ready for the next folder path...\n");
     }
+    session.finish();
     LOGGER.bold("Program finished successfully. Goodbye!");
 }
 
@@ -119,27 +252,58 @@ fn get_directory_path_loop() -> Option<String> {
     }
 }
 
+/// What happened to the files of one `process_directory` call.
+struct DirectoryOutcome {
+    failed_files: usize,
+    /// Files left untranslated because the circuit breaker stopped the run.
+    remaining_files: usize,
+    /// Lines not sent because the translation memory had them.
+    memory_lines: usize,
+    /// Lines not sent because they repeat an earlier line of the same file.
+    duplicate_lines: usize,
+}
+
+/// Translates every subtitle below `dir_path`.
 fn process_directory(
     dir_path: &str,
     subtitles_queue: &mut queue::FifoQueue<PathBuf>,
-    gemini_api_key: &String,
-    max_line_length: usize,
-) {
-    scanner::collect_subtitles_path(dir_path, subtitles_queue);
+    session: &mut translator::TranslationSession,
+    pipeline_options: &PipelineOptions,
+    report: &mut run_report::RunReport,
+) -> DirectoryOutcome {
+    let mut outcome = DirectoryOutcome {
+        failed_files: 0,
+        remaining_files: 0,
+        memory_lines: 0,
+        duplicate_lines: 0,
+    };
+    scanner::collect_input_subtitles(dir_path, subtitles_queue, &["srt"], &pipeline_options.translation.languages);
 
     if subtitles_queue.is_empty() {
-        LOGGER.warning(format!("No subtitle files (.srt) found in the specified directory: '{}'.\n", dir_path).as_str());
-        return;
+        LOGGER.warning(format!("No subtitle files (.srt) found in the specified path: '{}'.\n", dir_path).as_str());
+        return outcome;
     }
 
     LOGGER.success(format!("🔎 Total subtitle files found: {}\n", subtitles_queue.len()).as_str());
+    LOGGER.info(
+        format!(
+            "Generation settings ({}): {}\n",
+            pipeline_options.translation.backend,
+            pipeline_options.translation.generation.describe()
+        )
+        .as_str(),
+    );
 
+    let config = config::get();
+    let mut breaker = circuit_breaker::CircuitBreaker::new(config.circuit_breaker_threshold);
+    // Files that failed on quota or fatal errors since the last success; retried after a quota reset.
+    let mut interrupted_files: Vec<PathBuf> = Vec::new();
     let total_files = subtitles_queue.len();
-    for i in 0..total_files {
-        let subtitle_path = subtitles_queue.dequeue().unwrap(); 
-        let subtitle_number = i + 1;
+    let mut subtitle_number = 0;
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        subtitle_number += 1;
 
-        let file_name_display = subtitle_path.file_name().unwrap_or_default().to_string_lossy();
+        let file_name_display = subtitle_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
 
         LOGGER.process(
             format!(
@@ -153,43 +317,165 @@ fn process_directory(
         
         // Log relative path for better context if deep in subfolders
         let relative_path = subtitle_path.strip_prefix(dir_path).unwrap_or(&subtitle_path);
-        LOGGER.info(format!("Relative path: {}", relative_path.display()).as_str());
-
-        let target_dir_env = env!("TRANSLATE_TARGET_DIR", "TRANSLATE_TARGET_DIR environment variable not set");
+        if !relative_path.as_os_str().is_empty() {
+            LOGGER.info(format!("Relative path: {}", relative_path.display()).as_str());
+        }
 
-        if scanner::subtitle_exists_in_target_dir(&subtitle_path) {
+        if !pipeline_options.overwrite
+            && scanner::subtitle_exists_in_target_dir(&subtitle_path, &pipeline_options.translation.languages)
+        {
             LOGGER.warning(
                 format!(
                     "Subtitle file \"{}\" already exists in the target directory: {}, Skipping.",
                     file_name_display,
-                    target_dir_env
+                    config.translation_dir(&pipeline_options.translation.languages)
                 )
                 .as_str(),
             );
+            report.record(&subtitle_path, run_report::FileStatus::Skipped, None);
             LOGGER.log("\n");
             continue;
         }
 
-        match process_single_subtitle(&subtitle_path, gemini_api_key, max_line_length) {
-            Ok(_) => LOGGER.success(format!("File '{}' processed and saved successfully.", file_name_display).as_str()),
-            Err(e) => LOGGER.error(format!("Error processing file '{}': {}", file_name_display, e).as_str()),
+        match process_single_subtitle(&subtitle_path, Path::new(dir_path), session, pipeline_options) {
+            Ok(translation) => {
+                breaker.record_success();
+                interrupted_files.clear();
+                let status = if translation.failed_lines.is_empty() {
+                    LOGGER.success(format!("File '{}' processed and saved successfully.", file_name_display).as_str());
+                    run_report::FileStatus::Translated
+                } else {
+                    LOGGER.warning(
+                        format!(
+                            "File '{}' saved, but {} line(s) kept their source text; see the run report.",
+                            file_name_display,
+                            translation.failed_lines.len()
+                        )
+                        .as_str(),
+                    );
+                    run_report::FileStatus::PartiallyTranslated
+                };
+                let record = report.record(&subtitle_path, status, None);
+                record.chunks = translation.chunks;
+                record.failed_lines = translation.failed_lines;
+                record.glossary_violations = translation.glossary_violations;
+                record.memory_lines = translation.memory_lines;
+                record.duplicate_lines = translation.duplicate_lines;
+                outcome.memory_lines += translation.memory_lines;
+                outcome.duplicate_lines += translation.duplicate_lines;
+            }
+            Err(FileError::Failed(e)) => {
+                outcome.failed_files += 1;
+                LOGGER.error(format!("Error processing file '{}': {}", file_name_display, e).as_str());
+                report.record(&subtitle_path, run_report::FileStatus::Failed, Some(e));
+            }
+            Err(FileError::RateLimited(e)) => {
+                outcome.failed_files += 1;
+                breaker.record_quota_failure();
+                interrupted_files.push(subtitle_path.clone());
+                LOGGER.error(format!("Quota error processing file '{}': {}", file_name_display, e).as_str());
+                report.record(&subtitle_path, run_report::FileStatus::Failed, Some(e));
+            }
+            Err(FileError::Fatal(e)) => {
+                outcome.failed_files += 1;
+                breaker.record_fatal_error();
+                interrupted_files.push(subtitle_path.clone());
+                LOGGER.error(format!("Non-retryable error processing file '{}': {}", file_name_display, e).as_str());
+                report.record(&subtitle_path, run_report::FileStatus::Failed, Some(e));
+            }
         }
         LOGGER.log("\n"); 
+
+        let Some(reason) = breaker.tripped() else {
+            continue;
+        };
+        // Interrupted files are not counted as failures: they are retried or reported as remaining.
+        outcome.failed_files -= interrupted_files.len();
+
+        if reason == circuit_breaker::TripReason::QuotaExhausted && !config.quota_reset_time.is_empty() {
+            if let Err(e) = circuit_breaker::wait_for_quota_reset(&config.quota_reset_time) {
+                LOGGER.error(&e);
+            } else {
+                subtitle_number -= interrupted_files.len();
+                for path in interrupted_files.drain(..).rev() {
+                    subtitles_queue.requeue_front(path);
+                }
+                breaker.reset();
+                continue;
+            }
+        }
+
+        let remaining: Vec<PathBuf> = interrupted_files
+            .drain(..)
+            .chain(subtitles_queue.iter().cloned())
+            .collect();
+        subtitles_queue.clear();
+        outcome.remaining_files = remaining.len();
+        for path in &remaining {
+            report.record(path, run_report::FileStatus::Remaining, Some(format!("Run stopped because {}.", reason)));
+        }
+        LOGGER.bold_message_error(
+            format!(
+                "Stopping the run because {}. {} file(s) were not translated.",
+                reason,
+                remaining.len()
+            )
+            .as_str(),
+        );
+        match writer::write_remaining_list(Path::new(dir_path), &remaining) {
+            Ok(list_path) => LOGGER.info(format!("Remaining files listed in '{}'. Run the same command again to continue.", list_path.display()).as_str()),
+            Err(e) => LOGGER.error(&e),
+        }
+        break;
+    }
+    if outcome.memory_lines + outcome.duplicate_lines > 0 {
+        LOGGER.info(
+            format!(
+                "{} line(s) were not sent: {} from the translation memory, {} repeated within a file.",
+                outcome.memory_lines + outcome.duplicate_lines,
+                outcome.memory_lines,
+                outcome.duplicate_lines
+            )
+            .as_str(),
+        );
+    }
+    outcome
+}
+
+/// Why a file was not translated; `Fatal` errors stop the whole run.
+enum FileError {
+    Failed(String),
+    RateLimited(String),
+    Fatal(String),
+}
+
+impl From<String> for FileError {
+    fn from(message: String) -> Self {
+        FileError::Failed(message)
     }
 }
 
 fn process_single_subtitle(
-    subtitle_path: &PathBuf,
-    gemini_api_key: &String,
-    max_line_length: usize,
-) -> Result<(), String> {
+    subtitle_path: &Path,
+    input_root: &Path,
+    session: &mut translator::TranslationSession,
+    pipeline_options: &PipelineOptions,
+) -> Result<translator::SubtitleTranslation, FileError> {
     LOGGER.info("Preparing and formatting subtitle content...");
-    let mut sub_deformated = parser::format_subtitle_file(subtitle_path.clone());
-    if sub_deformated[0].is_empty() && sub_deformated[1].is_empty() {
-        // This can happen if read_file in parser fails and returns empty string, leading to empty splits.
-        // The expect in read_file should ideally prevent this, but as a safeguard:
-        return Err("Failed to parse subtitle file; content appears empty or corrupt.".to_string());
+    let mut cues = if pipeline_options.repair_sources {
+        LOGGER.info("Repairing subtitle structure before translation...");
+        repair::repair_file(subtitle_path, &pipeline_options.translation.languages)?
+    } else {
+        parser::read_subtitle_cues(subtitle_path)?
+    };
+    if cues.is_empty() {
+        return Err("Failed to parse subtitle file; content appears empty or corrupt.".to_string().into());
+    }
+    if !pipeline_options.timing_adjustments.is_empty() {
+        LOGGER.info("Applying timing adjustments...");
+        timing::apply_all(&mut cues, &pipeline_options.timing_adjustments);
     }
+    let mut sub_deformated = parser::format_cues(&cues);
     let ai_string = parser::convert_vec_to_ai_string(sub_deformated[1].clone());
     if ai_string.is_empty() && !sub_deformated[1].is_empty() {
          LOGGER.warning("Converted AI string is empty, but original subtitle parts were not. This might indicate an issue in `convert_vec_to_ai_string` or empty content lines.");
@@ -201,33 +487,744 @@ fn process_single_subtitle(
     }
 
 
-    LOGGER.info("⏳ Starting translation process with Gemini API...");
-    match translator::translate_subtitle(ai_string, gemini_api_key.clone()) {
-        Ok(translated_content) => {
+    let glossary = glossary::Glossary::load_for(subtitle_path, input_root, pipeline_options.glossary_file.as_deref())?;
+    if !glossary.is_empty() {
+        let sources: Vec<String> = glossary.sources().iter().map(|source| source.display().to_string()).collect();
+        LOGGER.info(format!("Using {} glossary term(s) from {}.", glossary.len(), sources.join(", ")).as_str());
+    }
+
+    LOGGER.info(
+        format!(
+            "⏳ Starting translation process with {} ({} to {})...",
+            pipeline_options.translation.backend,
+            pipeline_options.translation.languages.source,
+            pipeline_options.translation.languages.target
+        )
+        .as_str(),
+    );
+    match translator::translate_subtitle(
+        ai_string,
+        session,
+        &pipeline_options.translation,
+        glossary,
+    ) {
+        Ok(mut translation) => {
             LOGGER.success("Translation completed successfully.");
-            sub_deformated[1] = parser::convert_ai_string_to_vec(translated_content);
+            sub_deformated[1] = parser::convert_ai_string_to_vec(std::mem::take(&mut translation.text), cues.len())?;
             
+            let bilingual_content = match &pipeline_options.bilingual {
+                Some(bilingual_options) => Some(parser::convert_to_bilingual_srt_format(
+                    &cues,
+                    &sub_deformated[1],
+                    pipeline_options.max_line_length,
+                    bilingual_options,
+                    &pipeline_options.translation.languages,
+                )?),
+                None => None,
+            };
+
             LOGGER.info("Reconstructing subtitle file in SRT format...");
-            let srt_content =
-                parser::convert_formated_subtitle_to_srt_format(sub_deformated, max_line_length)?;
+            let srt_content = parser::convert_formated_subtitle_to_srt_format(
+                sub_deformated,
+                pipeline_options.max_line_length,
+                utils::is_rtl_language(&pipeline_options.translation.languages.target),
+            )?;
             
-            writer::write_translated_and_copy_original(subtitle_path, srt_content);
+            writer::write_translated_and_copy_original(
+                subtitle_path,
+                srt_content,
+                &pipeline_options.translation.languages,
+            );
 
-            return Ok(());
+            if let Some(bilingual_content) = bilingual_content {
+                writer::write_bilingual(
+                    subtitle_path,
+                    &bilingual_content,
+                    &pipeline_options.translation.languages,
+                )?;
+            }
+
+            Ok(translation)
+        }
+        Err(error) if error.is_fatal() => Err(FileError::Fatal(error.message)),
+        Err(error) if error.class == translator::ErrorClass::RateLimited => {
+            Err(FileError::RateLimited(error.message))
         }
         Err(error) => {
             // More robust error checking from translator
-            if error.starts_with("Error:") { 
+            if error.message.starts_with("Error:") { 
                 let err_msg = format!("Translation failed. API response: {}", error);
                 // No need to log error here as translator module should have logged the specifics
-                return Err(err_msg);
+                return Err(err_msg.into());
             }
-            if error.is_empty() && !sub_deformated[1].iter().all(|s| s.trim().is_empty()){
+            if error.message.is_empty() && !sub_deformated[1].iter().all(|s| s.trim().is_empty()){
                 LOGGER.warning("Translation result is empty, but original content was not. This might indicate an API issue or full content filtering.");
-                return Err("Translation resulted in empty content.".to_string()); // Or handle as appropriate
+                return Err("Translation resulted in empty content.".to_string().into()); // Or handle as appropriate
+            }
+
+            Err(error.message.into())
+        }
+    }
+}
+
+fn run_timing_command(args: &[String]) -> i32 {
+    let (path, adjustments) = match cli_handler::parse_timing_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    let mut exit_code = 0;
+
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    let languages = config::get().languages();
+    scanner::collect_input_subtitles(&path, &mut subtitles_queue, &["srt"], &languages);
+
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        LOGGER.process(format!("Retiming file: {}", subtitle_path.display()).as_str());
+        let result = parser::read_subtitle_cues_strict(&subtitle_path).and_then(|mut cues| {
+            timing::apply_all(&mut cues, &adjustments);
+            writer::write_adjusted_source(&subtitle_path, &subtitle::cues_to_srt(&cues), &languages)
+        });
+        if let Err(e) = result {
+            exit_code = 1;
+            LOGGER.error(format!("Error retiming '{}': {}", subtitle_path.display(), e).as_str());
+        }
+    }
+    exit_code
+}
+
+/// Command runners return the process exit code: 0 on success, 1 when some files
+/// failed (or, for `validate`, had errors), 2 on usage errors and 3 when `translate`
+/// was stopped early by the circuit breaker.
+fn run_translate_command(args: &[String]) -> i32 {
+    let translate_args = match cli_handler::parse_translate_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    let key_pool = match resolve_key_pool(
+        translate_args.translation.backend,
+        translate_args.api_key_file.as_deref(),
+    ) {
+        Some(key_pool) => key_pool,
+        None => return 2,
+    };
+
+    let pipeline_options = PipelineOptions {
+        max_line_length: translate_args.max_line_length,
+        timing_adjustments: translate_args.timing_adjustments,
+        repair_sources: translate_args.repair,
+        overwrite: translate_args.overwrite,
+        bilingual: translate_args.bilingual,
+        translation: translate_args.translation,
+        glossary_file: translate_args.glossary_file,
+    };
+
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    let mut report = run_report::RunReport::new(&translate_args.path, &pipeline_options.translation);
+    let mut session = translator::TranslationSession::new(key_pool, &pipeline_options.translation);
+    session.memory = open_translation_memory(translate_args.use_memory);
+    let outcome = process_directory(
+        &translate_args.path,
+        &mut subtitles_queue,
+        &mut session,
+        &pipeline_options,
+        &mut report,
+    );
+    session.finish();
+    if let Err(e) = report.write(translate_args.report_path.as_deref()) {
+        LOGGER.error(&e);
+    }
+    if outcome.failed_files > 0 {
+        LOGGER.bold_message_error(format!("{} file(s) could not be translated.", outcome.failed_files).as_str());
+    }
+    if outcome.remaining_files > 0 {
+        3
+    } else if outcome.failed_files > 0 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Opens the configured translation memory. A memory that cannot be read is reported and
+/// the run continues without one.
+fn open_translation_memory(enabled: bool) -> Option<translation_memory::TranslationMemory> {
+    if !enabled {
+        return None;
+    }
+    let opened = translation_memory::configured_path(config::get())
+        .and_then(|path| translation_memory::TranslationMemory::open(&path));
+    match opened {
+        Ok(memory) => {
+            LOGGER.info(
+                format!(
+                    "Using the translation memory at '{}' ({} entries).",
+                    memory.path().display(),
+                    memory.entries().len()
+                )
+                .as_str(),
+            );
+            Some(memory)
+        }
+        Err(e) => {
+            LOGGER.warning(format!("{} Continuing without the translation memory.", e).as_str());
+            None
+        }
+    }
+}
+
+/// Builds the key pool from the key file, environment, keychain or credentials file, prompting
+/// for a key on a terminal when none is set. `None` (already reported) means a usage error.
+fn resolve_key_pool(
+    backend: translator::Backend,
+    key_file: Option<&Path>,
+) -> Option<key_pool::KeyPool> {
+    match credentials::resolve_api_keys(backend, key_file) {
+        Ok(Some((entries, source))) => match key_pool::KeyPool::new(entries) {
+            Ok(key_pool) => {
+                LOGGER.info(format!("Using {} API key(s) from {}.", key_pool.len(), source).as_str());
+                Some(key_pool)
+            }
+            Err(e) => {
+                LOGGER.error(&e);
+                None
             }
+        },
+        Ok(None) if io::stdin().is_terminal() => get_api_key_loop().map(key_pool::KeyPool::single),
+        Ok(None) => {
+            LOGGER.error(
+                format!(
+                    "No API key found. Set {}, pass --api-key-file or run 'ai_translate login'.",
+                    backend.api_key_env_var()
+                )
+                .as_str(),
+            );
+            None
+        }
+        Err(e) => {
+            LOGGER.error(&e);
+            None
+        }
+    }
+}
 
-            return Err(error);
+fn run_glossary_command(args: &[String]) -> i32 {
+    let glossary_args = match cli_handler::parse_glossary_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    let output = glossary_args.output.clone().unwrap_or_else(|| {
+        writer::input_folder(Path::new(&glossary_args.path)).join(glossary::GLOSSARY_FILE_NAME)
+    });
+    let mut glossary = if output.is_file() {
+        match glossary::Glossary::load_file(&output) {
+            Ok(existing) => existing,
+            Err(e) => {
+                LOGGER.error(&e);
+                return 1;
+            }
         }
+    } else {
+        glossary::Glossary::default()
     };
-}
\ No newline at end of file
+
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    scanner::collect_input_subtitles(&glossary_args.path, &mut subtitles_queue, &["srt"], &glossary_args.translation.languages);
+    let total_files = subtitles_queue.len();
+    let mut extractor = term_extractor::TermExtractor::default();
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        match parser::read_subtitle_cues(&subtitle_path) {
+            Ok(cues) => cues.iter().for_each(|cue| extractor.add_cue_text(&cue.text)),
+            Err(e) => LOGGER.warning(format!("Skipping '{}': {}", subtitle_path.display(), e).as_str()),
+        }
+    }
+    let candidates: Vec<term_extractor::TermCandidate> = extractor
+        .candidates(glossary_args.min_count, glossary_args.max_terms)
+        .into_iter()
+        .filter(|candidate| !glossary.contains(&candidate.term))
+        .collect();
+    LOGGER.info(
+        format!(
+            "Found {} new candidate term(s) in {} subtitle file(s).",
+            candidates.len(),
+            total_files
+        )
+        .as_str(),
+    );
+    if glossary_args.list_only || candidates.is_empty() {
+        for candidate in &candidates {
+            let kind = if candidate.proper_noun { "name" } else { "phrase" };
+            LOGGER.log(format!("{} ({}, {}x)\n", candidate.term, kind, candidate.count).as_str());
+        }
+        return 0;
+    }
+
+    let Some(key_pool) = resolve_key_pool(
+        glossary_args.translation.backend,
+        glossary_args.api_key_file.as_deref(),
+    ) else {
+        return 2;
+    };
+    let mut session = translator::TranslationSession::new(key_pool, &glossary_args.translation);
+    LOGGER.process(
+        format!(
+            "⏳ Asking {} for {} renderings of {} term(s)...",
+            glossary_args.translation.generation.model,
+            glossary_args.translation.languages.target,
+            candidates.len()
+        )
+        .as_str(),
+    );
+    let proposed = translator::propose_glossary(&session, &glossary_args.translation, &candidates);
+    session.finish();
+    let entries = match proposed {
+        Ok(entries) => entries,
+        Err(e) => {
+            LOGGER.error(format!("Could not get glossary proposals: {}", e).as_str());
+            return 1;
+        }
+    };
+    if entries.len() < candidates.len() {
+        LOGGER.warning(format!("The model proposed no rendering for {} term(s).", candidates.len() - entries.len()).as_str());
+    }
+    for entry in &entries {
+        LOGGER.log(format!("{} => {}\n", entry.source, entry.target).as_str());
+    }
+    glossary.merge(entries);
+    if let Err(e) = glossary.save(&output) {
+        LOGGER.error(&e);
+        return 1;
+    }
+    LOGGER.success(
+        format!(
+            "Glossary with {} term(s) written to '{}'. Review it before translating; translate runs pick it up from there.",
+            glossary.len(),
+            output.display()
+        )
+        .as_str(),
+    );
+    0
+}
+
+fn run_memory_command(args: &[String]) -> i32 {
+    let memory_args = match cli_handler::parse_memory_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    let opened = translation_memory::configured_path(config::get())
+        .and_then(|path| translation_memory::TranslationMemory::open(&path));
+    let mut memory = match opened {
+        Ok(memory) => memory,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 1;
+        }
+    };
+    let matches_languages = |entry: &translation_memory::MemoryEntry| {
+        memory_args.source_language.as_ref().is_none_or(|language| &entry.source_language == language)
+            && memory_args.target_language.as_ref().is_none_or(|language| &entry.target_language == language)
+    };
+
+    let result = match memory_args.action {
+        cli_handler::MemoryAction::Stats => {
+            let mut pairs: Vec<(String, usize, u64)> = Vec::new();
+            for entry in memory.entries() {
+                let pair = format!("{} -> {}", entry.source_language, entry.target_language);
+                match pairs.iter_mut().find(|(existing, _, _)| *existing == pair) {
+                    Some((_, count, uses)) => {
+                        *count += 1;
+                        *uses += entry.uses;
+                    }
+                    None => pairs.push((pair, 1, entry.uses)),
+                }
+            }
+            LOGGER.bold(format!("Translation memory: {}", memory.path().display()).as_str());
+            for (pair, count, uses) in &pairs {
+                LOGGER.info(format!("{}: {} line(s), reused {} time(s)", pair, count, uses).as_str());
+            }
+            LOGGER.bold_message_info(format!("{} entries in total.", memory.entries().len()).as_str());
+            Ok(())
+        }
+        cli_handler::MemoryAction::Search(text) => {
+            let needle = text.to_lowercase();
+            let found: Vec<&translation_memory::MemoryEntry> = memory
+                .entries()
+                .iter()
+                .filter(|entry| matches_languages(entry))
+                .filter(|entry| {
+                    entry.source.to_lowercase().contains(&needle) || entry.translation.to_lowercase().contains(&needle)
+                })
+                .collect();
+            for entry in &found {
+                LOGGER.log(
+                    format!(
+                        "[{} -> {}] {} => {} (used {}x)\n",
+                        entry.source_language, entry.target_language, entry.source, entry.translation, entry.uses
+                    )
+                    .as_str(),
+                );
+            }
+            LOGGER.info(format!("{} matching entries.", found.len()).as_str());
+            Ok(())
+        }
+        cli_handler::MemoryAction::Export(path) => memory.export(&path).map(|count| {
+            LOGGER.success(format!("Exported {} entries to '{}'.", count, path.display()).as_str());
+        }),
+        cli_handler::MemoryAction::Import(path) => memory.import(&path).and_then(|(added, replaced)| {
+            memory.save()?;
+            LOGGER.success(
+                format!("Imported '{}': {} added, {} replaced.", path.display(), added, replaced).as_str(),
+            );
+            Ok(())
+        }),
+        cli_handler::MemoryAction::ImportPairs { source, target, min_overlap } => {
+            let config = config::get();
+            let languages = utils::LanguagePair {
+                source: memory_args.source_language.clone().unwrap_or_else(|| config.source_language.clone()),
+                target: memory_args.target_language.clone().unwrap_or_else(|| config.target_language.clone()),
+            };
+            let pairs = subtitle_pairs(&source, &target, &languages);
+            let mut totals = translation_memory::PairImport::default();
+            for (source_path, target_path) in &pairs {
+                let cues = parser::read_subtitle_cues(source_path)
+                    .and_then(|source_cues| Ok((source_cues, parser::read_subtitle_cues(target_path)?)));
+                let (source_cues, target_cues) = match cues {
+                    Ok(cues) => cues,
+                    Err(e) => {
+                        LOGGER.warning(format!("Skipping '{}': {}", source_path.display(), e).as_str());
+                        continue;
+                    }
+                };
+                let import = memory.import_pairs(&languages, &source_cues, &target_cues, min_overlap);
+                LOGGER.info(
+                    format!(
+                        "{}: {} of {} cue(s) aligned, {} line(s) stored.",
+                        source_path.display(),
+                        import.aligned_cues,
+                        import.source_cues,
+                        import.stored_lines
+                    )
+                    .as_str(),
+                );
+                totals.source_cues += import.source_cues;
+                totals.aligned_cues += import.aligned_cues;
+                totals.stored_lines += import.stored_lines;
+            }
+            memory.save().map(|_| {
+                LOGGER.success(
+                    format!(
+                        "Imported {} subtitle pair(s): {} of {} cue(s) aligned, {} line(s) stored.",
+                        pairs.len(),
+                        totals.aligned_cues,
+                        totals.source_cues,
+                        totals.stored_lines
+                    )
+                    .as_str(),
+                );
+            })
+        }
+        cli_handler::MemoryAction::Prune { older_than_days, unused } => {
+            let cutoff = older_than_days.map(|days| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|now| now.as_secs())
+                    .unwrap_or_default()
+                    .saturating_sub(days * 24 * 60 * 60)
+            });
+            let removed = memory.prune(|entry| {
+                matches_languages(entry)
+                    && cutoff.is_none_or(|cutoff| entry.last_used_at_unix < cutoff)
+                    && (!unused || entry.uses == 0)
+            });
+            memory.save().map(|_| {
+                LOGGER.success(format!("Removed {} entries; {} left.", removed, memory.entries().len()).as_str());
+            })
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            LOGGER.error(&e);
+            1
+        }
+    }
+}
+
+/// The subtitle pairs to import: the two files themselves, or each subtitle below `source`
+/// with the file of the same name (or its translated name) at the same place below `target`.
+fn subtitle_pairs(source: &Path, target: &Path, languages: &utils::LanguagePair) -> Vec<(PathBuf, PathBuf)> {
+    if !source.is_dir() {
+        return vec![(source.to_path_buf(), target.to_path_buf())];
+    }
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    scanner::collect_input_subtitles(&source.display().to_string(), &mut subtitles_queue, &["srt"], languages);
+    let mut pairs = Vec::new();
+    while let Some(source_path) = subtitles_queue.dequeue() {
+        let relative = source_path.strip_prefix(source).unwrap_or(&source_path);
+        let file_name = relative.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let folder = target.join(relative.parent().unwrap_or(Path::new("")));
+        let candidates = [
+            folder.join(utils::formated_to_translated_srt_name(&file_name, languages)),
+            folder.join(&file_name),
+        ];
+        match candidates.into_iter().find(|candidate| candidate.is_file()) {
+            Some(target_path) => pairs.push((source_path, target_path)),
+            None => LOGGER.warning(format!("No translation of '{}' found in '{}'.", source_path.display(), folder.display()).as_str()),
+        }
+    }
+    pairs
+}
+
+fn run_scan_command(args: &[String]) -> i32 {
+    let scan_args = match cli_handler::parse_scan_args("scan", args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    scanner::collect_input_subtitles(&scan_args.path, &mut subtitles_queue, &["srt"], &scan_args.languages);
+    let total_files = subtitles_queue.len();
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        LOGGER.log(format!("{}\n", subtitle_path.display()).as_str());
+    }
+    LOGGER.success(format!("🔎 Total subtitle files found: {}", total_files).as_str());
+    0
+}
+
+fn run_status_command(args: &[String]) -> i32 {
+    let status_args = match cli_handler::parse_scan_args("status", args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    scanner::collect_input_subtitles(&status_args.path, &mut subtitles_queue, &["srt"], &status_args.languages);
+
+    let mut pending = Vec::new();
+    let mut translated_count = 0;
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        if scanner::subtitle_exists_in_target_dir(&subtitle_path, &status_args.languages) {
+            translated_count += 1;
+        } else {
+            pending.push(subtitle_path);
+        }
+    }
+
+    for subtitle_path in &pending {
+        LOGGER.info(format!("Pending: {}", subtitle_path.display()).as_str());
+    }
+    let summary = format!(
+        "{} of {} subtitle file(s) translated to '{}', {} pending.",
+        translated_count,
+        translated_count + pending.len(),
+        status_args.languages.target,
+        pending.len()
+    );
+    if pending.is_empty() {
+        LOGGER.bold_message_success(&summary);
+    } else {
+        LOGGER.bold_message_info(&summary);
+    }
+    0
+}
+
+fn run_login_command(args: &[String]) -> i32 {
+    let login_args = match cli_handler::parse_login_args("login", args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    let entries = match &login_args.api_key_file {
+        Some(path) => match credentials::read_key_file(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                LOGGER.error(&e);
+                return 1;
+            }
+        },
+        None => match get_api_key_loop() {
+            Some(key) => vec![key_pool::KeyEntry { label: None, key }],
+            None => return 2,
+        },
+    };
+    match credentials::save_keys(login_args.backend, &entries, login_args.store) {
+        Ok(source) => {
+            LOGGER.success(
+                format!(
+                    "{} API key(s) for {} saved to {}.",
+                    entries.len(),
+                    login_args.backend,
+                    source
+                )
+                .as_str(),
+            );
+            0
+        }
+        Err(e) => {
+            LOGGER.error(&e);
+            1
+        }
+    }
+}
+
+fn run_logout_command(args: &[String]) -> i32 {
+    let login_args = match cli_handler::parse_login_args("logout", args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    match credentials::remove_key(login_args.backend) {
+        Ok(true) => {
+            LOGGER.success(format!("Saved API key for {} removed.", login_args.backend).as_str());
+            0
+        }
+        Ok(false) => {
+            LOGGER.info(format!("No saved API key for {}.", login_args.backend).as_str());
+            0
+        }
+        Err(e) => {
+            LOGGER.error(&e);
+            1
+        }
+    }
+}
+
+fn run_config_command(args: &[String], loaded_config: &config::LoadedConfig) -> i32 {
+    if !args.is_empty() {
+        LOGGER.error("Usage: ai_translate config [--config <file>] [--set <key>=<value>]...");
+        return 2;
+    }
+    match config::print_effective(loaded_config) {
+        Ok(()) => 0,
+        Err(e) => {
+            LOGGER.error(&e);
+            1
+        }
+    }
+}
+
+fn run_validate_command(args: &[String]) -> i32 {
+    let validate_args = match cli_handler::parse_validate_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    scanner::collect_input_subtitles(&validate_args.path, &mut subtitles_queue, &["srt"], &config::get().languages());
+
+    let mut file_reports = Vec::new();
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        file_reports.push(validator::validate_file(&subtitle_path, &validate_args.options));
+    }
+
+    let report = validator::build_report(file_reports);
+    validator::print_summary(&report);
+    if let Err(e) = validator::write_report(&report, &validate_args.report_path) {
+        LOGGER.error(&e);
+        return 2;
+    }
+
+    if report.total_errors > 0 { 1 } else { 0 }
+}
+
+fn run_fix_command(args: &[String]) -> i32 {
+    let path = match cli_handler::parse_fix_args(args) {
+        Ok(path) => path,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    let mut exit_code = 0;
+
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    let languages = config::get().languages();
+    scanner::collect_input_subtitles(&path, &mut subtitles_queue, &["srt"], &languages);
+
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        LOGGER.process(format!("Repairing file: {}", subtitle_path.display()).as_str());
+        if let Err(e) = repair::repair_file(&subtitle_path, &languages) {
+            exit_code = 1;
+            LOGGER.error(format!("Error repairing '{}': {}", subtitle_path.display(), e).as_str());
+        }
+    }
+    exit_code
+}
+
+fn run_convert_command(args: &[String]) -> i32 {
+    let convert_args = match cli_handler::parse_convert_args(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            LOGGER.error(&e);
+            return 2;
+        }
+    };
+    let mut exit_code = 0;
+
+    let extensions: Vec<&str> = match convert_args.from {
+        Some(from) => formats::SUPPORTED_EXTENSIONS
+            .into_iter()
+            .filter(|ext| formats::SubtitleFormat::from_name(ext) == Some(from))
+            .collect(),
+        None => formats::SUPPORTED_EXTENSIONS.to_vec(),
+    };
+    let mut subtitles_queue = queue::FifoQueue::<PathBuf>::new();
+    scanner::collect_input_subtitles(&convert_args.path, &mut subtitles_queue, &extensions, &config::get().languages());
+
+    while let Some(subtitle_path) = subtitles_queue.dequeue() {
+        let Some(source_format) =
+            convert_args.from.or_else(|| formats::SubtitleFormat::from_path(&subtitle_path))
+        else {
+            LOGGER.warning(format!("Unknown subtitle format for '{}', skipping.", subtitle_path.display()).as_str());
+            continue;
+        };
+        LOGGER.process(
+            format!(
+                "Converting {} from {} to {}",
+                subtitle_path.display(),
+                source_format,
+                convert_args.to
+            )
+            .as_str(),
+        );
+
+        let result = parser::read_file(&subtitle_path)
+            .and_then(|content| formats::parse(&content, source_format))
+            .and_then(|(cues, parse_losses)| {
+                let (content, losses) = formats::render(&cues, convert_args.to);
+                for loss in parse_losses.into_iter().chain(losses) {
+                    LOGGER.warning(&loss);
+                }
+                writer::write_converted(&subtitle_path, &content, convert_args.to.extension())
+            });
+        if let Err(e) = result {
+            exit_code = 1;
+            LOGGER.error(format!("Error converting '{}': {}", subtitle_path.display(), e).as_str());
+        }
+    }
+    exit_code
+}
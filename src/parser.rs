@@ -1,38 +1,138 @@
-use std::{io::Read, path::PathBuf};
+use std::{io::Read, path::Path, sync::LazyLock};
 
-pub fn format_subtitle_file(file_path: PathBuf) -> [Vec<String>; 2] {
-    let file_content = read_file(file_path);
-    let mut content_spliter = [Vec::<String>::new(), Vec::<String>::new()];
-    let mut section_count = 0;
+use crate::logger::Logger;
+use crate::subtitle::{Cue, Timestamp};
+use crate::utils::{self, LanguagePair};
 
-    file_content
-        .split("\r\n\r\n")
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Parser"));
+
+/// Layout of the optional dual-language output written next to the translation.
+#[derive(Debug, Clone)]
+pub struct BilingualOptions {
+    /// Show the original text above the translation instead of below it.
+    pub original_first: bool,
+    pub original_color: Option<String>,
+    pub translation_color: Option<String>,
+    pub original_max_line_length: usize,
+}
+
+/// Reads an SRT file and parses it into cues, skipping malformed blocks with a warning.
+pub fn read_subtitle_cues(file_path: &Path) -> Result<Vec<Cue>, String> {
+    let file_content = read_file(file_path)?;
+    Ok(parse_srt(&file_content))
+}
+
+/// Reads an SRT file and parses it into cues, failing on the first malformed block. Used
+/// where the cues are written back over the source, so no block may be lost.
+pub fn read_subtitle_cues_strict(file_path: &Path) -> Result<Vec<Cue>, String> {
+    let file_content = read_file(file_path)?;
+    parse_srt_blocks(&file_content).into_iter().collect()
+}
+
+/// Parses SRT content into cues. Accepts both CRLF and LF line endings and a leading BOM.
+/// Blocks with an unreadable timing line are skipped with a warning.
+pub fn parse_srt(content: &str) -> Vec<Cue> {
+    parse_srt_blocks(content)
         .into_iter()
-        .for_each(|section| {
-            if section.len() > 0 {
-                let mut parts = section.splitn(3, "\r\n");
+        .filter_map(|block| {
+            block
+                .map_err(|e| LOGGER.warning(format!("Skipping malformed block. {}", e).as_str()))
+                .ok()
+        })
+        .collect()
+}
 
-                content_spliter[0].push("".to_string());
+/// Parses every blank-line separated block on its own, so one malformed cue does not hide
+/// the rest of the file from callers that want to report problems rather than stop.
+pub fn parse_srt_blocks(content: &str) -> Vec<Result<Cue, String>> {
+    let normalized = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
 
-                for _ in 0..2 {
-                    content_spliter[0][section_count]
-                        .push_str((parts.next().unwrap_or_default().to_owned() + "\r\n").as_str());
+    split_blocks(&normalized)
+        .into_iter()
+        .enumerate()
+        .map(|(block_number, block)| {
+            let mut lines = block.into_iter();
+            let mut first = lines.next().unwrap_or_default();
+
+            let index = match first.trim().parse::<usize>() {
+                Ok(index) => {
+                    first = lines.next().unwrap_or_default();
+                    index
                 }
+                // Some files omit the counter; fall back to the block position.
+                Err(_) => block_number + 1,
+            };
 
-                content_spliter[1].push(parts.next().unwrap_or_default().to_owned());
-            }
+            let (start, end, timing_suffix) = parse_timing_line(first).map_err(|e| {
+                format!("Cue {} (block {}): {}", index, block_number + 1, e)
+            })?;
+
+            Ok(Cue {
+                index,
+                start,
+                end,
+                text: lines.collect::<Vec<_>>().join("\n"),
+                alignment: None,
+                timing_suffix,
+            })
+        })
+        .collect()
+}
 
-            section_count += 1;
-        });
+/// The counter written at the top of every block, or `None` where it is missing or not a
+/// number. Unlike `parse_srt_blocks`, malformed blocks keep their place.
+pub fn srt_block_numbers(content: &str) -> Vec<Option<usize>> {
+    let normalized = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    split_blocks(&normalized)
+        .into_iter()
+        .map(|block| block.first().and_then(|first| first.trim().parse::<usize>().ok()))
+        .collect()
+}
 
+/// Parses a `start --> end` line. Position hints after the end timestamp are returned as
+/// written so they can be put back when the cue is written.
+pub fn parse_timing_line(line: &str) -> Result<(Timestamp, Timestamp, String), String> {
+    let (start, rest) = line
+        .split_once("-->")
+        .ok_or_else(|| format!("Missing '-->' in timing line '{}'.", line.trim()))?;
+    let rest = rest.trim();
+    let (end, suffix) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Ok((Timestamp::parse(start)?, Timestamp::parse(end)?, suffix.trim().to_string()))
+}
+
+pub fn split_blocks(content: &str) -> Vec<Vec<&str>> {
+    let mut blocks = Vec::new();
+    let mut current = Vec::new();
+    for line in content.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
+
+/// Splits cues into the `[metadata, text]` pair the translation pipeline works on.
+pub fn format_cues(cues: &[Cue]) -> [Vec<String>; 2] {
+    let mut content_spliter = [Vec::<String>::new(), Vec::<String>::new()];
+    for cue in cues {
+        content_spliter[0].push(format!("{}\r\n{}\r\n", cue.index, cue.timing_line()));
+        content_spliter[1].push(cue.text.clone());
+    }
     content_spliter
 }
 
 pub fn convert_vec_to_ai_string(content: Vec<String>) -> String {
     let mut ai_string = String::new();
 
-    for num in 0..content.len() {
-        for (inex, line) in content[num].lines().enumerate() {
+    for (num, text) in content.iter().enumerate() {
+        for (inex, line) in text.lines().enumerate() {
             ai_string.push_str(format!("{}.{}_{}\n", num, inex, line).as_str());
         }
     }
@@ -40,70 +140,87 @@ pub fn convert_vec_to_ai_string(content: Vec<String>) -> String {
     ai_string
 }
 
-pub fn convert_ai_string_to_vec(content: String) -> Vec<String> {
-    let mut ai_string = Vec::new();
+/// Places each `<cue>.<line>_<text>` line back into its cue by the cue number in its prefix,
+/// so a cue without translatable lines cannot shift later text into the wrong cue.
+pub fn convert_ai_string_to_vec(content: String, cue_count: usize) -> Result<Vec<String>, String> {
+    let mut ai_string = vec![String::new(); cue_count];
 
-    let mut str = String::new();
-    let mut num: u16 = 0;
+    for line in content.split('\n').filter(|line| !line.is_empty()) {
+        let (prefix, text) = line.split_once('_').unwrap_or((line, ""));
+        let index = prefix
+            .split('.')
+            .next()
+            .and_then(|num| num.trim().parse::<usize>().ok())
+            .ok_or_else(|| format!("Translated line has an invalid prefix: '{}'", line))?;
 
-    content.split("\n").into_iter().for_each(|line| {
-        if line.len() > 0 {
-            let mut parts = line.splitn(2, "_");
-            let index: u16 = parts.next().unwrap().parse::<f32>().unwrap() as u16;
-            let text = parts.next().unwrap_or_default().to_owned();
-
-            if num + 1 != index {
-                str.push_str(&format!("{text}\n"));
-            } else {
-                num = index;
-                ai_string.push(str.to_owned());
-                str.clear();
-                str.push_str(&format!("{text}\n"));
+        match ai_string.get_mut(index) {
+            Some(cue_text) => cue_text.push_str(&format!("{text}\n")),
+            None => {
+                return Err(format!(
+                    "Translated line refers to cue {} but the subtitle only has {} cues.",
+                    index, cue_count
+                ))
             }
         }
-    });
-
-    ai_string.push(str.to_owned());
+    }
 
-    ai_string
+    Ok(ai_string)
 }
 
 pub fn convert_formated_subtitle_to_srt_format(
     formated_sub: [Vec<String>; 2],
     max_width: usize,
+    rtl: bool,
 ) -> Result<String, String> {
+    if formated_sub[0].len() != formated_sub[1].len() {
+        return Err("The number of metadata entries in the subtitle file does not match the number of translated lines.".to_owned());
+    }
+
     let mut srt_content = String::new();
 
-    for i in 0..formated_sub[0].len() {
-        let warped_sub = wrap_with_markers(&formated_sub[1][i], max_width);
+    for (metadata, text) in formated_sub[0].iter().zip(&formated_sub[1]) {
+        let warped_sub = wrap_for_direction(text, max_width, rtl);
 
-        if formated_sub
-        .get(0)
-        .and_then(|sub0| sub0.get(i)).is_some() {
-            srt_content.push_str(formated_sub[0][i].as_str());
-            srt_content.push_str(warped_sub.as_str());
-            srt_content.push_str("\r\n\r\n");
-        } else {
-            return Err("The number of metadata entries in the subtitle file does not match the number of translated lines.".to_owned());
-        }
+        srt_content.push_str(metadata.as_str());
+        srt_content.push_str(warped_sub.as_str());
+        srt_content.push_str("\r\n\r\n");
     }
 
     Ok(srt_content)
 }
 
-fn read_file(file_path: PathBuf) -> String {
-    let mut file_content = std::fs::File::open(file_path).expect("Failed to open file");
+pub fn read_file(file_path: &Path) -> Result<String, String> {
+    let mut file_content = std::fs::File::open(file_path)
+        .map_err(|e| format!("Failed to open file '{}': {}", file_path.display(), e))?;
 
     let mut content = String::new();
 
     file_content
         .read_to_string(&mut content)
-        .expect("Failed to read file");
+        .map_err(|e| format!("Failed to read file '{}': {}", file_path.display(), e))?;
 
-    content
+    Ok(content)
 }
 
-pub fn wrap_with_markers(text: &String, max_width: usize) -> String {
+pub fn wrap_with_markers(text: &str, max_width: usize) -> String {
+    wrap_lines(text, max_width)
+        .into_iter()
+        .map(|line| format!("\u{202b}{}\u{202c}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Wraps text, adding bidi markers only for right-to-left languages.
+pub fn wrap_for_direction(text: &str, max_width: usize, rtl: bool) -> String {
+    if rtl {
+        wrap_with_markers(text, max_width)
+    } else {
+        wrap_lines(text, max_width).join("\n")
+    }
+}
+
+/// Greedy word wrap that keeps the original line breaks; `max_width` of 0 disables wrapping.
+pub fn wrap_lines(text: &str, max_width: usize) -> Vec<String> {
     let mut lines = Vec::new();
 
     for line in text.lines() {
@@ -115,7 +232,7 @@ pub fn wrap_with_markers(text: &String, max_width: usize) -> String {
                 format!("{} {}", current, word)
             };
 
-            if tentative.chars().count() > max_width && max_width > 0 {
+            if tentative.chars().count() > max_width && max_width > 0 && !current.is_empty() {
                 lines.push(current);
                 current = word.to_string();
             } else {
@@ -129,8 +246,105 @@ pub fn wrap_with_markers(text: &String, max_width: usize) -> String {
     }
 
     lines
-        .into_iter()
-        .map(|line| format!("\u{202b}{}\u{202c}", line))
-        .collect::<Vec<_>>()
-        .join("\n")
+}
+
+/// Stacks the original and translated text of each cue, each block wrapped and coloured
+/// on its own so left-to-right and right-to-left lines never share bidi markers.
+pub fn convert_to_bilingual_srt_format(
+    original_cues: &[Cue],
+    translated_texts: &[String],
+    max_width: usize,
+    options: &BilingualOptions,
+    languages: &LanguagePair,
+) -> Result<String, String> {
+    if original_cues.len() != translated_texts.len() {
+        return Err("The number of original cues does not match the number of translated cues.".to_owned());
+    }
+
+    let mut srt_content = String::new();
+
+    for (cue, translated) in original_cues.iter().zip(translated_texts) {
+        let original_block = colorize(
+            &wrap_for_direction(
+                &cue.text,
+                options.original_max_line_length,
+                utils::is_rtl_language(&languages.source),
+            ),
+            options.original_color.as_deref(),
+        );
+        let translated_block = colorize(
+            &wrap_for_direction(translated, max_width, utils::is_rtl_language(&languages.target)),
+            options.translation_color.as_deref(),
+        );
+
+        let blocks = if options.original_first {
+            [original_block, translated_block]
+        } else {
+            [translated_block, original_block]
+        };
+
+        srt_content.push_str(&format!("{}\r\n{}\r\n", cue.index, cue.timing_line()));
+        srt_content.push_str(
+            &blocks
+                .iter()
+                .filter(|block| !block.is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        srt_content.push_str("\r\n\r\n");
+    }
+
+    Ok(srt_content)
+}
+
+fn colorize(block: &str, color: Option<&str>) -> String {
+    match color {
+        Some(color) if !block.is_empty() => format!("<font color=\"{}\">{}</font>", color, block),
+        _ => block.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BROKEN: &str = "1\r\n00:00:01,000 --> 00:00:02,000\r\nFirst\r\n\r\n2\r\n00:00:03 -> 00:00:04\r\nBad timing\r\n\r\n3\r\n00:00:05,000 --> 00:00:06,000 X1:10\r\nThird\r\nline two\r\n";
+
+    #[test]
+    fn over_long_word_does_not_leave_an_empty_line() {
+        assert_eq!(wrap_lines("supercalifragilistic word", 5), vec!["supercalifragilistic", "word"]);
+        assert_eq!(wrap_lines("a supercalifragilistic", 5), vec!["a", "supercalifragilistic"]);
+    }
+
+    #[test]
+    fn lenient_parse_skips_malformed_blocks() {
+        let cues = parse_srt(BROKEN);
+        assert_eq!(cues.iter().map(|cue| cue.index).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(cues[1].text, "Third\nline two");
+        assert_eq!(cues[1].end, Timestamp(6_000));
+    }
+
+    #[test]
+    fn position_hints_survive_a_round_trip() {
+        let cues = parse_srt(BROKEN);
+        assert_eq!(cues[1].timing_suffix, "X1:10");
+        assert_eq!(format_cues(&cues)[0][1], "3\r\n00:00:05,000 --> 00:00:06,000 X1:10\r\n");
+        assert_eq!(parse_srt(&crate::subtitle::cues_to_srt(&cues)), cues);
+    }
+
+    #[test]
+    fn blocks_report_each_malformed_cue() {
+        let blocks = parse_srt_blocks(BROKEN);
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks[1].as_ref().is_err_and(|e| e.starts_with("Cue 2 (block 2)")));
+        assert!(blocks.into_iter().collect::<Result<Vec<_>, _>>().is_err());
+    }
+
+    #[test]
+    fn missing_counter_falls_back_to_block_position() {
+        let cues = parse_srt("\u{feff}00:00:01,000 --> 00:00:02,000\nNo number\n");
+        assert_eq!(cues[0].index, 1);
+        assert_eq!(cues[0].text, "No number");
+    }
 }
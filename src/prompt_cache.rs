@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use reqwest::blocking;
+use serde::Deserialize;
+
+use crate::logger::Logger;
+use crate::token_budget::ModelLimits;
+use crate::utils;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("PromptCache"));
+
+const CACHED_CONTENTS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/cachedContents";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// A cache this close to expiring is extended before it is used again.
+const REFRESH_MARGIN: Duration = Duration::from_secs(120);
+
+/// Caches are bound to a project (so to an API key), a model and the cached instruction.
+#[derive(Hash, PartialEq, Eq, Clone)]
+struct CacheKey {
+    key_label: String,
+    model: String,
+    system_instruction: String,
+}
+
+enum CacheState {
+    Active {
+        name: String,
+        api_key: String,
+        expires_at: Instant,
+    },
+    /// Another request is creating or extending the cache; until it is done requests send
+    /// the instruction inline.
+    Pending,
+    /// Creating or extending the cache failed, or the instruction is too short to cache;
+    /// requests send the instruction inline.
+    Unavailable,
+}
+
+/// What a request has to do before it can use the cache; decided under the lock and done
+/// outside it.
+enum Step {
+    Create,
+    Extend { name: String, api_key: String },
+}
+
+#[derive(Deserialize)]
+struct CachedContentResponse {
+    name: String,
+}
+
+/// Gemini `cachedContents` entries holding the system instruction, created once per run
+/// and deleted by `cleanup` when the run ends. The API calls are made without holding the
+/// lock, so concurrent requests are not held up by a cache being created.
+#[derive(Default)]
+pub struct PromptCache {
+    entries: Mutex<HashMap<CacheKey, CacheState>>,
+}
+
+impl PromptCache {
+    fn entries(&self) -> MutexGuard<'_, HashMap<CacheKey, CacheState>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the cache to reference for this instruction, creating or extending it first.
+    /// `None` means the instruction has to be sent with the request, which is always the case
+    /// when `instruction_tokens` is below the model's minimum for a cache.
+    pub fn cached_content(
+        &self,
+        api_key: &str,
+        key_label: &str,
+        model: &str,
+        system_instruction: &str,
+        instruction_tokens: usize,
+        ttl: Duration,
+    ) -> Option<String> {
+        let cache_key = CacheKey {
+            key_label: key_label.to_string(),
+            model: model.to_string(),
+            system_instruction: system_instruction.to_string(),
+        };
+        let step = {
+            let mut entries = self.entries();
+            match entries.get(&cache_key) {
+                None => match ModelLimits::for_model(model).min_cached_tokens {
+                    Some(minimum) if instruction_tokens >= minimum => {
+                        entries.insert(cache_key.clone(), CacheState::Pending);
+                        Step::Create
+                    }
+                    minimum => {
+                        let reason = match minimum {
+                            Some(minimum) => format!(
+                                "the instructions (about {} tokens) are shorter than the {} tokens it needs",
+                                instruction_tokens, minimum
+                            ),
+                            None => "it does not support explicit caching".to_string(),
+                        };
+                        LOGGER.info(format!("No prompt cache for {}: {}. Sending the instructions with every request.", model, reason).as_str());
+                        entries.insert(cache_key, CacheState::Unavailable);
+                        return None;
+                    }
+                },
+                Some(CacheState::Active { name, api_key, expires_at }) => {
+                    if expires_at.saturating_duration_since(Instant::now()) >= REFRESH_MARGIN {
+                        return Some(name.clone());
+                    }
+                    let step = Step::Extend {
+                        name: name.clone(),
+                        api_key: api_key.clone(),
+                    };
+                    entries.insert(cache_key.clone(), CacheState::Pending);
+                    step
+                }
+                Some(CacheState::Pending | CacheState::Unavailable) => return None,
+            }
+        };
+
+        let state = match step {
+            Step::Create => match create_cache(api_key, model, system_instruction, ttl) {
+                Ok(name) => {
+                    LOGGER.info(format!("Created prompt cache '{}' for {} ({}).", name, model, key_label).as_str());
+                    CacheState::Active {
+                        name,
+                        api_key: api_key.to_string(),
+                        expires_at: Instant::now() + ttl,
+                    }
+                }
+                Err(e) => {
+                    LOGGER.warning(format!("Prompt caching is unavailable for {}; sending the instructions with every request. {}", model, e).as_str());
+                    CacheState::Unavailable
+                }
+            },
+            Step::Extend { name, api_key } => match extend_cache(&api_key, &name, ttl) {
+                Ok(()) => CacheState::Active {
+                    name,
+                    api_key,
+                    expires_at: Instant::now() + ttl,
+                },
+                Err(e) => {
+                    LOGGER.warning(format!("Failed to extend prompt cache '{}': {}", name, e).as_str());
+                    CacheState::Unavailable
+                }
+            },
+        };
+        let name = match &state {
+            CacheState::Active { name, .. } => Some(name.clone()),
+            _ => None,
+        };
+        self.entries().insert(cache_key, state);
+        name
+    }
+
+    /// Stops using a cache the API no longer accepts (expired or deleted elsewhere).
+    pub fn invalidate(&self, cache_name: &str) {
+        for state in self.entries().values_mut() {
+            if matches!(state, CacheState::Active { name, .. } if name == cache_name) {
+                *state = CacheState::Unavailable;
+            }
+        }
+    }
+
+    /// Deletes every cache this run created; they would otherwise be billed until their TTL ends.
+    pub fn cleanup(&self) {
+        let mut entries = self.entries();
+        for state in entries.values() {
+            if let CacheState::Active { name, api_key, .. } = state {
+                match delete_cache(api_key, name) {
+                    Ok(()) => LOGGER.debug(format!("Deleted prompt cache '{}'.", name).as_str()),
+                    Err(e) => LOGGER.warning(format!("Failed to delete prompt cache '{}': {}", name, e).as_str()),
+                }
+            }
+        }
+        entries.clear();
+    }
+}
+
+fn check_response(resp: blocking::Response) -> Result<String, String> {
+    let status = resp.status();
+    let text = resp.text().map_err(|e| format!("Error reading API response: {}", e))?;
+    if status.is_success() {
+        Ok(text)
+    } else {
+        Err(format!("Request failed (Status {}): {}", status, text))
+    }
+}
+
+fn create_cache(api_key: &str, model: &str, system_instruction: &str, ttl: Duration) -> Result<String, String> {
+    let body = serde_json::json!({
+        "model": format!("models/{}", model),
+        "displayName": "ai_translate prompt",
+        "systemInstruction": { "parts": [{ "text": system_instruction }] },
+        "ttl": format!("{}s", ttl.as_secs()),
+    });
+    let resp = utils::http_client()?
+        .post(CACHED_CONTENTS_URL)
+        .timeout(REQUEST_TIMEOUT)
+        .header("x-goog-api-key", api_key)
+        .json(&body)
+        .send()
+        .map_err(|e| format!("Error communicating with API: {}", e))?;
+    let text = check_response(resp)?;
+    serde_json::from_str::<CachedContentResponse>(&text)
+        .map(|cached| cached.name)
+        .map_err(|e| format!("Error parsing API response: {}", e))
+}
+
+fn extend_cache(api_key: &str, name: &str, ttl: Duration) -> Result<(), String> {
+    let url = format!("https://generativelanguage.googleapis.com/v1beta/{}?updateMask=ttl", name);
+    let resp = utils::http_client()?
+        .patch(&url)
+        .timeout(REQUEST_TIMEOUT)
+        .header("x-goog-api-key", api_key)
+        .json(&serde_json::json!({ "ttl": format!("{}s", ttl.as_secs()) }))
+        .send()
+        .map_err(|e| format!("Error communicating with API: {}", e))?;
+    check_response(resp).map(|_| ())
+}
+
+fn delete_cache(api_key: &str, name: &str) -> Result<(), String> {
+    let url = format!("https://generativelanguage.googleapis.com/v1beta/{}", name);
+    let resp = utils::http_client()?
+        .delete(&url)
+        .timeout(REQUEST_TIMEOUT)
+        .header("x-goog-api-key", api_key)
+        .send()
+        .map_err(|e| format!("Error communicating with API: {}", e))?;
+    check_response(resp).map(|_| ())
+}
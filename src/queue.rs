@@ -19,6 +19,15 @@ impl<T> FifoQueue<T> {
         self.queue.pop_front()
     }
 
+    /// Puts an item back at the head of the queue so it is the next one dequeued.
+    pub fn requeue_front(&mut self, item: T) {
+        self.queue.push_front(item);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.queue.iter()
+    }
+
     pub fn len(&self) -> usize {
         self.queue.len()
     }
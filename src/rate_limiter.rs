@@ -0,0 +1,119 @@
+use std::{
+    collections::VecDeque,
+    sync::{LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::logger::Logger;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("RateLimiter"));
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Keeps a run's requests within requests-per-minute and tokens-per-minute limits over a
+/// sliding one-minute window; a limit of 0 is no limit. Shared by every worker of a run.
+pub struct RateLimiter {
+    requests_per_minute: usize,
+    tokens_per_minute: usize,
+    window: Duration,
+    /// When each request of the last minute was sent and the tokens it was estimated at.
+    sent: Mutex<VecDeque<(Instant, usize)>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: usize, tokens_per_minute: usize) -> Self {
+        Self::with_window(requests_per_minute, tokens_per_minute, WINDOW)
+    }
+
+    fn with_window(requests_per_minute: usize, tokens_per_minute: usize, window: Duration) -> Self {
+        RateLimiter {
+            requests_per_minute,
+            tokens_per_minute,
+            window,
+            sent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Blocks until a request estimated at `tokens` fits both limits, then counts it. A request
+    /// larger than the whole token limit waits for an empty window instead of forever.
+    pub fn acquire(&self, tokens: usize) {
+        if self.requests_per_minute == 0 && self.tokens_per_minute == 0 {
+            return;
+        }
+        loop {
+            let wait = {
+                let mut sent = self.sent.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let now = Instant::now();
+                while sent.front().is_some_and(|(at, _)| now.duration_since(*at) >= self.window) {
+                    sent.pop_front();
+                }
+                let used_tokens: usize = sent.iter().map(|(_, tokens)| tokens).sum();
+                let requests_fit = self.requests_per_minute == 0 || sent.len() < self.requests_per_minute;
+                let tokens_fit = self.tokens_per_minute == 0
+                    || sent.is_empty()
+                    || used_tokens + tokens <= self.tokens_per_minute;
+                if requests_fit && tokens_fit {
+                    sent.push_back((now, tokens));
+                    return;
+                }
+                // Wait for the oldest request to leave the window, then check again.
+                sent.front()
+                    .map(|(at, _)| (*at + self.window).saturating_duration_since(now))
+                    .unwrap_or_default()
+            };
+            LOGGER.debug(format!("Rate limit reached; waiting {}ms before the next request.", wait.as_millis()).as_str());
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_WINDOW: Duration = Duration::from_millis(200);
+
+    /// How long a call to `acquire` blocked.
+    fn timed_acquire(limiter: &RateLimiter, tokens: usize) -> Duration {
+        let start = Instant::now();
+        limiter.acquire(tokens);
+        start.elapsed()
+    }
+
+    #[test]
+    fn no_limits_never_wait() {
+        let limiter = RateLimiter::with_window(0, 0, TEST_WINDOW);
+        let start = Instant::now();
+        for _ in 0..100 {
+            limiter.acquire(1_000_000);
+        }
+        assert!(start.elapsed() < TEST_WINDOW);
+    }
+
+    #[test]
+    fn requests_over_the_rpm_limit_wait_for_the_window() {
+        let limiter = RateLimiter::with_window(2, 0, TEST_WINDOW);
+        assert!(timed_acquire(&limiter, 10) < TEST_WINDOW / 2);
+        assert!(timed_acquire(&limiter, 10) < TEST_WINDOW / 2);
+        assert!(timed_acquire(&limiter, 10) >= TEST_WINDOW / 2);
+    }
+
+    #[test]
+    fn tokens_over_the_tpm_limit_wait_for_the_window() {
+        let limiter = RateLimiter::with_window(0, 100, TEST_WINDOW);
+        assert!(timed_acquire(&limiter, 60) < TEST_WINDOW / 2);
+        assert!(timed_acquire(&limiter, 40) < TEST_WINDOW / 2);
+        assert!(timed_acquire(&limiter, 1) >= TEST_WINDOW / 2);
+    }
+
+    #[test]
+    fn oversized_request_waits_for_an_empty_window() {
+        let limiter = RateLimiter::with_window(0, 100, TEST_WINDOW);
+        assert!(timed_acquire(&limiter, 500) < TEST_WINDOW / 2);
+        // Larger than the whole limit, but it does not wait forever once the window is empty.
+        assert!(timed_acquire(&limiter, 500) >= TEST_WINDOW / 2);
+        let waited = timed_acquire(&limiter, 10);
+        assert!(waited >= TEST_WINDOW / 2 && waited < TEST_WINDOW * 10);
+    }
+}
@@ -0,0 +1,225 @@
+use std::{path::Path, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::logger::Logger;
+use crate::parser;
+use crate::subtitle::{self, Cue, Timestamp};
+use crate::utils::LanguagePair;
+use crate::writer;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Repair"));
+/// A timing line already written as `HH:MM:SS,mmm --> HH:MM:SS,mmm`, possibly followed by
+/// position hints.
+static CANONICAL_TIMING_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\d{2}:\d{2}:\d{2},\d{3} --> \d{2}:\d{2}:\d{2},\d{3}(\s|$)").unwrap());
+
+/// Fallback display time for cues whose end is not after their start.
+const DEFAULT_CUE_DURATION_MS: u64 = 2000;
+
+#[derive(Debug, Default)]
+pub struct RepairSummary {
+    pub malformed_removed: usize,
+    pub empty_removed: usize,
+    pub reordered: bool,
+    pub duplicates_merged: usize,
+    pub overlaps_trimmed: usize,
+    pub durations_fixed: usize,
+    pub renumbered: usize,
+    pub timestamps_normalized: usize,
+}
+
+impl RepairSummary {
+    pub fn has_changes(&self) -> bool {
+        self.malformed_removed > 0
+            || self.empty_removed > 0
+            || self.reordered
+            || self.duplicates_merged > 0
+            || self.overlaps_trimmed > 0
+            || self.durations_fixed > 0
+            || self.renumbered > 0
+            || self.timestamps_normalized > 0
+    }
+
+    pub fn log(&self) {
+        if !self.has_changes() {
+            LOGGER.info("No structural problems found.");
+            return;
+        }
+        let changes = [
+            (self.malformed_removed, "malformed cue(s) removed"),
+            (self.empty_removed, "empty cue(s) removed"),
+            (self.duplicates_merged, "duplicate cue(s) merged"),
+            (self.overlaps_trimmed, "overlapping end time(s) trimmed"),
+            (self.durations_fixed, "zero or negative duration(s) fixed"),
+            (self.renumbered, "cue(s) renumbered"),
+            (self.timestamps_normalized, "timing line(s) normalized"),
+        ];
+        for (count, description) in changes.iter().filter(|(count, _)| *count > 0) {
+            LOGGER.info(format!("{} {}.", count, description).as_str());
+        }
+        if self.reordered {
+            LOGGER.info("Cues sorted by start time.");
+        }
+    }
+}
+
+/// Reads a possibly broken SRT file, dropping blocks that cannot be parsed at all.
+pub fn read_and_repair(path: &Path) -> Result<(Vec<Cue>, RepairSummary), String> {
+    Ok(repair_content(&parser::read_file(path)?))
+}
+
+fn repair_content(content: &str) -> (Vec<Cue>, RepairSummary) {
+    let mut summary = RepairSummary {
+        timestamps_normalized: count_non_canonical_timing_lines(content),
+        ..Default::default()
+    };
+
+    let mut cues = Vec::new();
+    for block in parser::parse_srt_blocks(content) {
+        match block {
+            Ok(cue) => cues.push(cue),
+            Err(e) => {
+                LOGGER.warning(format!("Dropping unreadable cue: {}", e).as_str());
+                summary.malformed_removed += 1;
+            }
+        }
+    }
+
+    let repaired = repair_cues(cues, &mut summary);
+    (repaired, summary)
+}
+
+pub fn repair_cues(mut cues: Vec<Cue>, summary: &mut RepairSummary) -> Vec<Cue> {
+    let before = cues.len();
+    cues.retain(|cue| !subtitle::strip_markup(&cue.text).trim().is_empty());
+    summary.empty_removed += before - cues.len();
+
+    if cues.windows(2).any(|pair| pair[1].start < pair[0].start) {
+        // Stable sort keeps the original order of cues that share a start time.
+        cues.sort_by_key(|cue| cue.start);
+        summary.reordered = true;
+    }
+
+    let mut merged: Vec<Cue> = Vec::with_capacity(cues.len());
+    for cue in cues {
+        if let Some(previous) = merged.last_mut() {
+            if previous.text.trim() == cue.text.trim() && cue.start <= previous.end {
+                // Same text shown again while (or right as) the previous copy is on screen.
+                previous.end = previous.end.max(cue.end);
+                summary.duplicates_merged += 1;
+                continue;
+            }
+        }
+        merged.push(cue);
+    }
+
+    for position in 0..merged.len() {
+        // Different cues sharing a start time are shown together, so only a later cue
+        // bounds this one.
+        let start = merged[position].start;
+        let next_start = merged[position + 1..]
+            .iter()
+            .map(|next| next.start)
+            .find(|next| *next > start);
+        let cue = &mut merged[position];
+
+        if cue.end <= cue.start {
+            let fallback = Timestamp(cue.start.as_millis() + DEFAULT_CUE_DURATION_MS);
+            cue.end = next_start.map_or(fallback, |next| fallback.min(next));
+            summary.durations_fixed += 1;
+        } else if let Some(next) = next_start {
+            if cue.end > next {
+                cue.end = next;
+                summary.overlaps_trimmed += 1;
+            }
+        }
+    }
+
+    for (position, cue) in merged.iter_mut().enumerate() {
+        if cue.index != position + 1 {
+            cue.index = position + 1;
+            summary.renumbered += 1;
+        }
+    }
+
+    merged
+}
+
+/// Counts timing lines whose timestamps are not already written as
+/// `HH:MM:SS,mmm --> HH:MM:SS,mmm`; position hints after them do not count.
+fn count_non_canonical_timing_lines(content: &str) -> usize {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.contains("-->") && !CANONICAL_TIMING_RE.is_match(line))
+        .count()
+}
+
+/// Repairs a file in place (keeping a backup of the original) and returns the repaired cues.
+pub fn repair_file(path: &Path, languages: &LanguagePair) -> Result<Vec<Cue>, String> {
+    let (cues, summary) = read_and_repair(path)?;
+    summary.log();
+    if summary.has_changes() {
+        writer::write_adjusted_source(path, &subtitle::cues_to_srt(&cues), languages)?;
+    }
+    Ok(cues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(index: usize, start: u64, end: u64, text: &str) -> Cue {
+        Cue {
+            index,
+            start: Timestamp(start),
+            end: Timestamp(end),
+            text: text.to_string(),
+            alignment: None,
+            timing_suffix: String::new(),
+        }
+    }
+
+    #[test]
+    fn clean_file_with_position_hints_is_left_alone() {
+        let content = "1\n00:00:01,000 --> 00:00:02,000 X1:10 X2:200 Y1:20 Y2:80\nHello\n\n2\n00:00:03,000 --> 00:00:04,000\nBye\n";
+        let (cues, summary) = repair_content(content);
+        assert!(!summary.has_changes());
+        assert_eq!(cues[0].timing_line(), "00:00:01,000 --> 00:00:02,000 X1:10 X2:200 Y1:20 Y2:80");
+    }
+
+    #[test]
+    fn loose_timestamps_are_normalized() {
+        let (_, summary) = repair_content("1\n0:00:01.000 --> 00:00:02,000 X1:10\nHello\n");
+        assert_eq!(summary.timestamps_normalized, 1);
+    }
+
+    #[test]
+    fn merges_only_identical_overlapping_cues() {
+        let mut summary = RepairSummary::default();
+        let cues = vec![cue(1, 1000, 3000, "Hello"), cue(2, 2000, 4000, "Hello"), cue(3, 5000, 6000, "Bye")];
+        let repaired = repair_cues(cues, &mut summary);
+        assert_eq!(summary.duplicates_merged, 1);
+        assert_eq!(repaired.len(), 2);
+        assert_eq!(repaired[0].end, Timestamp(4000));
+        assert_eq!(repaired[1].index, 2);
+    }
+
+    #[test]
+    fn keeps_different_cues_with_the_same_start() {
+        let mut summary = RepairSummary::default();
+        let cues = vec![
+            cue(1, 1000, 3000, "Top line"),
+            cue(2, 1000, 2500, "Speaker two"),
+            cue(3, 2800, 4000, "Next"),
+        ];
+        let repaired = repair_cues(cues, &mut summary);
+        assert_eq!(summary.duplicates_merged, 0);
+        assert_eq!(repaired.len(), 3);
+        assert_eq!(repaired[0].text, "Top line");
+        assert_eq!(repaired[0].end, Timestamp(2800));
+        assert_eq!(repaired[1].end, Timestamp(2500));
+        assert_eq!(summary.overlaps_trimmed, 1);
+    }
+}
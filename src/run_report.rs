@@ -0,0 +1,156 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::glossary::GlossaryViolation;
+use crate::logger::Logger;
+use crate::translator::{ChunkRecord, GenerationSettings, LineFailure, TranslationOptions};
+use crate::writer;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("RunReport"));
+
+const RUN_REPORT_FILE_NAME: &str = "translation_report.json";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Translated,
+    /// Written, but some lines were blocked or cut off and kept their source text.
+    PartiallyTranslated,
+    /// A translation already existed and `--overwrite` was not given.
+    Skipped,
+    Failed,
+    /// Not attempted (or interrupted) because the run was stopped early.
+    Remaining,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileRecord {
+    pub path: String,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Which model produced each chunk of a translated file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<ChunkRecord>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed_lines: Vec<LineFailure>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub glossary_violations: Vec<GlossaryViolation>,
+    /// Lines filled from the translation memory instead of being sent.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub memory_lines: usize,
+    /// Repeated lines that reused the translation of their first occurrence.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub duplicate_lines: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// Settings and per-file outcomes of a translation run, written as JSON so a translation
+/// can be traced back to the model and parameters that produced it.
+#[derive(Serialize, Debug)]
+pub struct RunReport {
+    pub tool_version: &'static str,
+    pub started_at_unix: u64,
+    pub finished_at_unix: u64,
+    pub input: String,
+    pub backend: String,
+    pub source_language: String,
+    pub target_language: String,
+    pub generation: GenerationSettings,
+    pub fallback_models: Vec<String>,
+    pub context_lines: usize,
+    pub rolling_summary: bool,
+    pub memory_examples: usize,
+    pub deduplicate_lines: bool,
+    pub chunk_output_tokens: usize,
+    pub concurrent_requests: usize,
+    pub requests_per_minute: usize,
+    pub tokens_per_minute: usize,
+    pub files: Vec<FileRecord>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+impl RunReport {
+    pub fn new(input: &str, options: &TranslationOptions) -> Self {
+        RunReport {
+            tool_version: env!("CARGO_PKG_VERSION"),
+            started_at_unix: unix_now(),
+            finished_at_unix: 0,
+            input: input.to_string(),
+            backend: options.backend.to_string(),
+            source_language: options.languages.source.clone(),
+            target_language: options.languages.target.clone(),
+            generation: options.generation.clone(),
+            fallback_models: options.fallbacks.iter().map(|choice| choice.label()).collect(),
+            context_lines: options.context_lines,
+            rolling_summary: options.rolling_summary,
+            memory_examples: options.memory_examples,
+            deduplicate_lines: options.deduplicate_lines,
+            chunk_output_tokens: options.chunk_output_tokens,
+            concurrent_requests: options.concurrent_requests,
+            requests_per_minute: options.requests_per_minute,
+            tokens_per_minute: options.tokens_per_minute,
+            files: Vec::new(),
+        }
+    }
+
+    /// Records the outcome of a file, replacing an earlier outcome when it was retried.
+    pub fn record(&mut self, path: &Path, status: FileStatus, error: Option<String>) -> &mut FileRecord {
+        let path = path.display().to_string();
+        let position = match self.files.iter().position(|record| record.path == path) {
+            Some(position) => position,
+            None => {
+                self.files.push(FileRecord {
+                    path,
+                    status,
+                    error: None,
+                    chunks: Vec::new(),
+                    failed_lines: Vec::new(),
+                    glossary_violations: Vec::new(),
+                    memory_lines: 0,
+                    duplicate_lines: 0,
+                });
+                self.files.len() - 1
+            }
+        };
+        let record = &mut self.files[position];
+        record.status = status;
+        record.error = error;
+        record.chunks.clear();
+        record.failed_lines.clear();
+        record.glossary_violations.clear();
+        record.memory_lines = 0;
+        record.duplicate_lines = 0;
+        record
+    }
+
+    /// Writes the report to `report_path`, or next to the input when it is `None`.
+    pub fn write(&mut self, report_path: Option<&Path>) -> Result<PathBuf, String> {
+        self.finished_at_unix = unix_now();
+        let report_path = match report_path {
+            Some(path) => path.to_path_buf(),
+            None => writer::input_folder(Path::new(&self.input)).join(RUN_REPORT_FILE_NAME),
+        };
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize run report: {}", e))?;
+        fs::write(&report_path, json)
+            .map_err(|e| format!("Failed to write run report to '{}': {}", report_path.display(), e))?;
+        LOGGER.info(format!("Run report written to '{}'.", report_path.display()).as_str());
+        Ok(report_path)
+    }
+}
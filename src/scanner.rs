@@ -1,12 +1,20 @@
-use std::{env, ffi::OsStr, fs, path::PathBuf, sync::LazyLock}; // Added env for robust env var handling
+use std::{ffi::OsStr, fs, path::{Path, PathBuf}, sync::LazyLock};
 
+use crate::config;
 use crate::logger::Logger;
 use crate::queue::FifoQueue;
-use crate::utils;
+use crate::utils::{self, LanguagePair};
 
 static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Scanner"));
 
-pub fn collect_subtitles_path(dir_path: &str, subtitles_queue: &mut FifoQueue<PathBuf>) {
+/// Recursively queues subtitle files whose extension is one of `extensions`, skipping the
+/// output folders and files already translated into any language.
+pub fn collect_subtitles_with_extensions(
+    dir_path: &str,
+    subtitles_queue: &mut FifoQueue<PathBuf>,
+    extensions: &[&str],
+    languages: &LanguagePair,
+) {
     LOGGER.info(format!("🔍 Scanning folder: '{}'", dir_path).as_str());
 
     let entries_result = fs::read_dir(dir_path);
@@ -44,32 +52,18 @@ pub fn collect_subtitles_path(dir_path: &str, subtitles_queue: &mut FifoQueue<Pa
         }
     });
 
-    let target_dir_name = env!("TRANSLATE_TARGET_DIR").parse().unwrap_or_else(|_| {
-        LOGGER.warning(
-            format!(
-                "Environment variable '{}' not set. Using default 'Translated_Subtitles'.",
-                "TRANSLATE_TARGET_DIR"
-            )
-            .as_str(),
-        );
-        "Translated_Subtitles".to_string()
-    });
-    let original_sub_dir_name = env!("ORIGINAL_SUB_TARGET_DIR").parse().unwrap_or_else(|_| {
-        LOGGER.warning(
-            format!(
-                "Environment variable '{}' not set. Using default 'Original_Subtitles_Backup'.",
-                "ORIGINAL_SUB_TARGET_DIR"
-            )
-            .as_str(),
-        );
-        "Original_Subtitles_Backup".to_string()
-    });
+    let config = config::get();
+    let target_dir_name = config.translation_dir(languages);
+    let original_sub_dir_name = config.original_sub_target_dir.as_str();
+    let converted_sub_dir_name = config.convert_target_dir.as_str();
 
     for entry in entries {
         let path = entry.path();
         if path.is_dir() {
-            if path.file_name() == Some(OsStr::new(target_dir_name.as_str()))
-                || path.file_name() == Some(OsStr::new(original_sub_dir_name.as_str()))
+            if path.file_name() == Some(OsStr::new(&target_dir_name))
+                || path.file_name().and_then(OsStr::to_str).is_some_and(utils::is_translation_dir_name)
+                || path.file_name() == Some(OsStr::new(original_sub_dir_name))
+                || path.file_name() == Some(OsStr::new(converted_sub_dir_name))
             {
                 LOGGER.info(
                     format!("➡️  Skipping scan of system folder: '{}'", path.display()).as_str(),
@@ -78,7 +72,7 @@ pub fn collect_subtitles_path(dir_path: &str, subtitles_queue: &mut FifoQueue<Pa
             }
 
             if let Some(path_str) = path.to_str() {
-                collect_subtitles_path(path_str, subtitles_queue);
+                collect_subtitles_with_extensions(path_str, subtitles_queue, extensions, languages);
             } else {
                 LOGGER.warning(
                     format!(
@@ -91,7 +85,11 @@ pub fn collect_subtitles_path(dir_path: &str, subtitles_queue: &mut FifoQueue<Pa
         } else if path.is_file()
             && path
                 .extension()
-                .map_or(false, |ext| ext.eq_ignore_ascii_case("srt"))
+                .is_some_and(|ext| {
+                    extensions
+                        .iter()
+                        .any(|allowed| ext.eq_ignore_ascii_case(allowed))
+                })
         {
             let file_name = path
                 .file_name()
@@ -99,16 +97,13 @@ pub fn collect_subtitles_path(dir_path: &str, subtitles_queue: &mut FifoQueue<Pa
                 .to_str()
                 .unwrap_or_default();
 
-            if !file_name.contains("_fa")
-                && !file_name.contains(".fa")
-                && !file_name.ends_with("fa.srt")
-            {
+            if !utils::is_translated_srt_name(file_name, languages) {
                 LOGGER.debug(format!("Subtitle file found: {}", path.display()).as_str());
                 subtitles_queue.enqueue(path);
             } else {
                 LOGGER.info(
                     format!(
-                        "File '{}' appears to be already translated (contains '_fa'). Skipping.",
+                        "File '{}' appears to be already translated (has a language suffix). Skipping.",
                         file_name
                     )
                     .as_str(),
@@ -119,7 +114,21 @@ pub fn collect_subtitles_path(dir_path: &str, subtitles_queue: &mut FifoQueue<Pa
     // LOGGER.success(format!("Finished scanning folder: '{}'", dir_path).as_str()); // Optional: can be verbose
 }
 
-pub fn subtitle_exists_in_target_dir(original_path: &PathBuf) -> bool {
+/// Queues a single subtitle file as-is, or every matching subtitle below a folder.
+pub fn collect_input_subtitles(
+    path: &str,
+    subtitles_queue: &mut FifoQueue<PathBuf>,
+    extensions: &[&str],
+    languages: &LanguagePair,
+) {
+    if Path::new(path).is_dir() {
+        collect_subtitles_with_extensions(path, subtitles_queue, extensions, languages);
+    } else {
+        subtitles_queue.enqueue(PathBuf::from(path));
+    }
+}
+
+pub fn subtitle_exists_in_target_dir(original_path: &Path, languages: &LanguagePair) -> bool {
     let parent_dir = match original_path.parent() {
         Some(dir) => dir,
         None => {
@@ -128,11 +137,7 @@ pub fn subtitle_exists_in_target_dir(original_path: &PathBuf) -> bool {
         }
     };
 
-    let target_dir_name: String = env!("TRANSLATE_TARGET_DIR")
-        .parse()
-        .unwrap_or("Translated_Subtitles".to_owned());
-
-    let fa_dir = parent_dir.join(target_dir_name);
+    let fa_dir = parent_dir.join(config::get().translation_dir(languages));
 
     if !fa_dir.is_dir() {
         LOGGER.debug(
@@ -152,7 +157,10 @@ pub fn subtitle_exists_in_target_dir(original_path: &PathBuf) -> bool {
             return false;
         }
     };
-    let fa_file_path = fa_dir.join(utils::formated_to_fa_srt_name(file_name.to_string_lossy().as_ref()));
+    let fa_file_path = fa_dir.join(utils::formated_to_translated_srt_name(
+        file_name.to_string_lossy().as_ref(),
+        languages,
+    ));
 
     let exists = fa_file_path.is_file();
     if exists {
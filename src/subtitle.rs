@@ -0,0 +1,165 @@
+use std::fmt;
+
+/// A subtitle timestamp stored as milliseconds from the start of the video.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    pub fn from_millis(millis: i64) -> Self {
+        // Timing operations may push early cues before zero; clamp them to the start.
+        Timestamp(millis.max(0) as u64)
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.0
+    }
+
+    /// Parses `HH:MM:SS,mmm`, also accepting loose forms like `0:1:2,5` or `00:01:02.500`.
+    /// The fractional part is read as a decimal fraction of a second, so `,5` means 500 ms.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let trimmed = input.trim();
+        let (clock, fraction) = match trimmed.find([',', '.']) {
+            Some(pos) => (&trimmed[..pos], &trimmed[pos + 1..]),
+            None => (trimmed, ""),
+        };
+
+        let fields: Vec<&str> = clock.split(':').collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            return Err(format!("Invalid timestamp '{}'.", trimmed));
+        }
+        let mut numbers = Vec::with_capacity(3);
+        for field in &fields {
+            if field.is_empty() || !field.chars().all(|c| c.is_ascii_digit()) {
+                return Err(format!("Invalid timestamp '{}'.", trimmed));
+            }
+            numbers.push(
+                field
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid timestamp '{}'.", trimmed))?,
+            );
+        }
+        if numbers.len() == 2 {
+            numbers.insert(0, 0); // MM:SS without hours
+        }
+        let (hours, minutes, seconds) = (numbers[0], numbers[1], numbers[2]);
+        if minutes > 59 || seconds > 59 {
+            return Err(format!("Invalid timestamp '{}'.", trimmed));
+        }
+
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Invalid timestamp '{}'.", trimmed));
+        }
+        let millis = fraction
+            .chars()
+            .chain(std::iter::repeat('0'))
+            .take(3)
+            .collect::<String>()
+            .parse::<u64>()
+            .unwrap_or(0);
+
+        Ok(Timestamp(
+            ((hours * 60 + minutes) * 60 + seconds) * 1000 + millis,
+        ))
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.0;
+        write!(
+            f,
+            "{:02}:{:02}:{:02},{:03}",
+            total / 3_600_000,
+            (total / 60_000) % 60,
+            (total / 1000) % 60,
+            total % 1000
+        )
+    }
+}
+
+/// A single subtitle entry: its number, display interval and (possibly multi-line) text.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Cue {
+    pub index: usize,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub text: String,
+    /// Screen position as a numpad code (1 = bottom left ... 9 = top right), when known.
+    pub alignment: Option<u8>,
+    /// Position hints written after the end time of an SRT timing line (`X1:… Y2:…`),
+    /// kept as they were read.
+    pub timing_suffix: String,
+}
+
+impl Cue {
+    pub fn duration_ms(&self) -> i64 {
+        self.end.as_millis() as i64 - self.start.as_millis() as i64
+    }
+
+    pub fn timing_line(&self) -> String {
+        if self.timing_suffix.is_empty() {
+            format!("{} --> {}", self.start, self.end)
+        } else {
+            format!("{} --> {} {}", self.start, self.end, self.timing_suffix)
+        }
+    }
+}
+
+/// Removes HTML-style (`<i>`) and ASS-style (`{\an8}`) tags, leaving only the visible text.
+pub fn strip_markup(text: &str) -> String {
+    let mut visible = String::with_capacity(text.len());
+    let mut closing: Option<char> = None;
+    for c in text.chars() {
+        match closing {
+            Some(end) if c == end => closing = None,
+            Some(_) => {}
+            None if c == '<' => closing = Some('>'),
+            None if c == '{' => closing = Some('}'),
+            None => visible.push(c),
+        }
+    }
+    visible
+}
+
+/// Serializes cues to SRT using the CRLF line endings the rest of the pipeline writes.
+pub fn cues_to_srt(cues: &[Cue]) -> String {
+    let mut srt_content = String::new();
+    for cue in cues {
+        srt_content.push_str(&format!("{}\r\n{}\r\n", cue.index, cue.timing_line()));
+        srt_content.push_str(&cue.text.lines().collect::<Vec<_>>().join("\r\n"));
+        srt_content.push_str("\r\n\r\n");
+    }
+    srt_content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_strict_and_loose_timestamps() {
+        assert_eq!(Timestamp::parse("01:02:03,456"), Ok(Timestamp(3_723_456)));
+        assert_eq!(Timestamp::parse("00:01:02.500"), Ok(Timestamp(62_500)));
+        assert_eq!(Timestamp::parse("0:1:2,5"), Ok(Timestamp(62_500)));
+        assert_eq!(Timestamp::parse("01:02"), Ok(Timestamp(62_000)));
+        assert_eq!(Timestamp::parse(" 00:00:01,0009 "), Ok(Timestamp(1_000)));
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        for input in ["", "12", "00:60:00,000", "00:00:60,000", "00:00:01,5x", "a:00:01", "1:2:3:4"] {
+            assert!(Timestamp::parse(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn formats_as_srt_and_clamps_negative_times() {
+        assert_eq!(Timestamp(3_723_456).to_string(), "01:02:03,456");
+        assert_eq!(Timestamp::from_millis(-250), Timestamp(0));
+    }
+
+    #[test]
+    fn strips_html_and_ass_markup() {
+        assert_eq!(strip_markup("{\\an8}<i>Hello</i> there"), "Hello there");
+    }
+}
@@ -0,0 +1,284 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::subtitle;
+
+/// Words that never start or end a recurring term and are never taken for names.
+/// The extraction is tuned for English sources.
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "again", "all", "am", "an", "and", "any", "are", "as", "at", "be",
+    "because", "been", "before", "but", "by", "can", "could", "did", "do", "does", "don't",
+    "for", "from", "get", "go", "going", "got", "had", "has", "have", "he", "her", "here",
+    "hey", "him", "his", "how", "i", "i'm", "if", "in", "into", "is", "it", "it's", "just",
+    "know", "let", "like", "me", "my", "no", "not", "now", "of", "oh", "ok", "okay", "on",
+    "one", "or", "our", "out", "please", "really", "right", "say", "see", "she", "so", "some",
+    "that", "that's", "the", "their", "them", "then", "there", "they", "think", "this", "to",
+    "up", "us", "very", "was", "we", "well", "were", "what", "when", "where", "who", "why",
+    "will", "with", "would", "yeah", "yes", "you", "your",
+];
+
+/// Sentence-ending punctuation, or a word with its trailing apostrophes and hyphenated parts.
+static TOKEN_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[.!?…]|\p{L}[\p{L}\p{N}'’]*(?:-\p{L}[\p{L}\p{N}'’]*)*").unwrap());
+
+const MAX_EXAMPLE_LENGTH: usize = 120;
+
+/// A term worth a glossary entry, with how often it occurred across the scanned files.
+#[derive(Debug, Clone)]
+pub struct TermCandidate {
+    pub term: String,
+    pub count: usize,
+    /// Looks like the name of a person, place or organization rather than a phrase.
+    pub proper_noun: bool,
+    /// The first line the term was seen in.
+    pub example: String,
+}
+
+#[derive(Default, Clone)]
+struct TermStats {
+    count: usize,
+    /// Capitalized occurrences that were not at the start of a sentence.
+    capitalized_inside: usize,
+    example: String,
+}
+
+/// Collects candidate proper nouns (capitalized words and runs of them) and recurring
+/// lowercase two- and three-word phrases from subtitle text.
+#[derive(Default)]
+pub struct TermExtractor {
+    names: HashMap<String, TermStats>,
+    /// Runs of capitalized words starting a sentence, like "Captain Hook" or "Tell Wendy";
+    /// whether the first word belongs to the name is decided once all text is read.
+    sentence_start_runs: HashMap<String, TermStats>,
+    lowercase_words: HashMap<String, usize>,
+    phrases: HashMap<String, TermStats>,
+}
+
+impl TermExtractor {
+    /// Adds the text of one cue; its lines are read as one passage.
+    pub fn add_cue_text(&mut self, text: &str) {
+        let visible = subtitle::strip_markup(text).lines().map(str::trim).collect::<Vec<_>>().join(" ");
+        let example: String = visible.chars().take(MAX_EXAMPLE_LENGTH).collect();
+
+        let mut sentence_start = true;
+        let mut sentence: Vec<&str> = Vec::new();
+        let mut sentences: Vec<Vec<&str>> = Vec::new();
+        let mut name_run: Vec<&str> = Vec::new();
+        let mut run_at_sentence_start = false;
+        for token in TOKEN_RE.find_iter(&visible).map(|m| m.as_str()) {
+            if token.chars().all(|c| matches!(c, '.' | '!' | '?' | '…')) {
+                self.close_name_run(&mut name_run, run_at_sentence_start, &example);
+                sentences.push(std::mem::take(&mut sentence));
+                sentence_start = true;
+                continue;
+            }
+            let word = strip_possessive(token);
+            sentence.push(word);
+            if is_capitalized(word) && !is_stop_word(word) {
+                let stats = self.names.entry(word.to_string()).or_default();
+                stats.count += 1;
+                if !sentence_start {
+                    stats.capitalized_inside += 1;
+                }
+                if stats.example.is_empty() {
+                    stats.example = example.clone();
+                }
+                if name_run.is_empty() {
+                    run_at_sentence_start = sentence_start;
+                }
+                name_run.push(word);
+            } else {
+                self.close_name_run(&mut name_run, run_at_sentence_start, &example);
+                if word.chars().next().is_some_and(char::is_lowercase) {
+                    *self.lowercase_words.entry(word.to_lowercase()).or_default() += 1;
+                }
+            }
+            sentence_start = false;
+        }
+        self.close_name_run(&mut name_run, run_at_sentence_start, &example);
+        sentences.push(sentence);
+
+        for sentence in sentences {
+            self.count_phrases(&sentence, &example);
+        }
+    }
+
+    /// Records a run of two or more capitalized words, such as "Captain Hook".
+    fn close_name_run(&mut self, run: &mut Vec<&str>, at_sentence_start: bool, example: &str) {
+        if run.len() >= 2 {
+            let runs = if at_sentence_start { &mut self.sentence_start_runs } else { &mut self.names };
+            let stats = runs.entry(run.join(" ")).or_default();
+            stats.count += 1;
+            stats.capitalized_inside += 1;
+            if stats.example.is_empty() {
+                stats.example = example.to_string();
+            }
+        }
+        run.clear();
+    }
+
+    fn count_phrases(&mut self, words: &[&str], example: &str) {
+        for size in 2..=3 {
+            for window in words.windows(size) {
+                let edges = [window[0], window[size - 1]];
+                if window.iter().any(|word| !word.chars().all(|c| c.is_lowercase() || c == '-' || c == '\''))
+                    || edges.iter().any(|word| is_stop_word(word) || word.chars().count() < 3)
+                {
+                    continue;
+                }
+                let stats = self.phrases.entry(window.join(" ")).or_default();
+                stats.count += 1;
+                if stats.example.is_empty() {
+                    stats.example = example.to_string();
+                }
+            }
+        }
+    }
+
+    /// Candidates seen at least `min_count` times, most frequent first, at most `max_terms`.
+    pub fn candidates(&self, min_count: usize, max_terms: usize) -> Vec<TermCandidate> {
+        // A run starting a sentence keeps its first word only if that word is also
+        // capitalized elsewhere in a sentence, as "Captain" is.
+        let mut names = self.names.clone();
+        for (run, run_stats) in &self.sentence_start_runs {
+            let (first, rest) = run.split_once(' ').unwrap_or((run, ""));
+            let name = if self.names.get(first).is_some_and(|stats| stats.capitalized_inside > 0) {
+                run.as_str()
+            } else if rest.contains(' ') {
+                rest
+            } else {
+                continue;
+            };
+            let stats = names.entry(name.to_string()).or_default();
+            stats.count += run_stats.count;
+            stats.capitalized_inside += run_stats.count;
+            if stats.example.is_empty() {
+                stats.example = run_stats.example.clone();
+            }
+        }
+
+        let name_containers = containing_counts(&names);
+        let phrase_containers = containing_counts(&self.phrases);
+        let names = names.iter().filter(|(name, stats)| {
+            // A word that is mostly written in lowercase is an ordinary word capitalized
+            // for emphasis or at a line break, not a name.
+            let lowercase = self.lowercase_words.get(&name.to_lowercase()).copied().unwrap_or_default();
+            stats.count >= min_count
+                && stats.capitalized_inside > 0
+                && lowercase * 3 < stats.count
+                && !is_subsumed(name, stats, &name_containers)
+        });
+        let phrases = self
+            .phrases
+            .iter()
+            .filter(|(phrase, stats)| stats.count >= min_count && !is_subsumed(phrase, stats, &phrase_containers));
+
+        let mut candidates: Vec<TermCandidate> = names
+            .map(|entry| (entry, true))
+            .chain(phrases.map(|entry| (entry, false)))
+            .map(|((term, stats), proper_noun)| TermCandidate {
+                term: term.clone(),
+                count: stats.count,
+                proper_noun,
+                example: stats.example.clone(),
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+        candidates.truncate(max_terms);
+        candidates
+    }
+}
+
+/// For every run of words inside a longer term, the highest count among the terms containing it.
+fn containing_counts(terms: &HashMap<String, TermStats>) -> HashMap<String, usize> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for (term, stats) in terms {
+        let words: Vec<&str> = term.split(' ').collect();
+        for size in 1..words.len() {
+            for window in words.windows(size) {
+                let count = counts.entry(window.join(" ")).or_default();
+                *count = (*count).max(stats.count);
+            }
+        }
+    }
+    counts
+}
+
+/// Whether `term` only ever occurs as part of a longer term, e.g. "Hook" in "Captain Hook".
+fn is_subsumed(term: &str, stats: &TermStats, containing_counts: &HashMap<String, usize>) -> bool {
+    containing_counts.get(term).is_some_and(|&count| count >= stats.count)
+}
+
+fn strip_possessive(word: &str) -> &str {
+    word.strip_suffix("'s").or_else(|| word.strip_suffix("’s")).unwrap_or(word)
+}
+
+/// `Word`, but not `I`, `OK` or shouted `STOP`.
+fn is_capitalized(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(char::is_uppercase)
+        && word.chars().count() > 1
+        && chars.any(char::is_lowercase)
+}
+
+fn is_stop_word(word: &str) -> bool {
+    let lower = word.to_lowercase().replace('’', "'");
+    STOP_WORDS.contains(&lower.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(cues: &[&str], min_count: usize) -> Vec<(String, usize, bool)> {
+        let mut extractor = TermExtractor::default();
+        for cue in cues {
+            extractor.add_cue_text(cue);
+        }
+        extractor
+            .candidates(min_count, 100)
+            .into_iter()
+            .map(|candidate| (candidate.term, candidate.count, candidate.proper_noun))
+            .collect()
+    }
+
+    #[test]
+    fn name_at_sentence_start_joins_the_same_name_inside_a_sentence() {
+        let found = candidates(&["Captain Hook is coming.", "We fear Captain Hook.", "Run from Captain Hook!"], 3);
+        assert_eq!(found, vec![("Captain Hook".to_string(), 3, true)]);
+    }
+
+    #[test]
+    fn ordinary_word_starting_a_sentence_is_not_part_of_the_name() {
+        let found = candidates(&["Tell Wendy goodbye.", "Tell Wendy now.", "<i>Tell Wendy</i> again."], 3);
+        assert_eq!(found, vec![("Wendy".to_string(), 3, true)]);
+    }
+
+    #[test]
+    fn words_capitalized_only_at_sentence_start_are_not_names() {
+        assert!(candidates(&["Hook!", "Hook!", "Hook!"], 3).is_empty());
+        assert!(candidates(&["We saw Smee.", "We saw Smee."], 3).is_empty());
+    }
+
+    #[test]
+    fn phrases_do_not_start_or_end_with_stop_words() {
+        let found = candidates(&["The ship of gold.", "The ship of gold!", "The ship of gold?"], 3);
+        assert_eq!(found, vec![("ship of gold".to_string(), 3, false)]);
+
+        let found = candidates(&["Into the pirate ship.", "Into the pirate ship.", "Into the pirate ship."], 3);
+        assert_eq!(found, vec![("pirate ship".to_string(), 3, false)]);
+    }
+
+    #[test]
+    fn shorter_term_seen_on_its_own_more_often_is_kept() {
+        let found = candidates(
+            &["The pirate ship sank.", "The pirate ship sank.", "The pirate ship sank.", "My ship sank."],
+            3,
+        );
+        assert_eq!(
+            found,
+            vec![("ship sank".to_string(), 4, false), ("pirate ship sank".to_string(), 3, false)]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::{fmt, sync::LazyLock};
+
+use crate::logger::Logger;
+use crate::subtitle::{Cue, Timestamp};
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Timing"));
+
+/// A timing correction applied to every cue of a subtitle file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimingAdjustment {
+    /// Moves all cues by a signed offset in milliseconds.
+    Shift(i64),
+    /// Linear remap defined by two sync points: `(old, new)` for an early and a late cue.
+    Stretch {
+        first: (Timestamp, Timestamp),
+        second: (Timestamp, Timestamp),
+    },
+    /// Converts timing made for one frame rate to another, e.g. a 25 fps release to 23.976 fps.
+    FrameRate { from: f64, to: f64 },
+}
+
+impl TimingAdjustment {
+    /// Parses a textual adjustment:
+    /// - `shift:+1500`, `shift:-2.5s` or `shift:-00:00:01,200`
+    /// - `fps:25:23.976`
+    /// - `stretch:00:00:10,000=00:00:11,200;01:30:00,000=01:30:04,800`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (kind, value) = spec
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid timing adjustment '{}'.", spec))?;
+
+        match kind.trim().to_lowercase().as_str() {
+            "shift" => parse_offset(value).map(TimingAdjustment::Shift),
+            "fps" => {
+                let (from, to) = value
+                    .split_once(':')
+                    .ok_or_else(|| format!("Expected 'fps:<from>:<to>', got '{}'.", spec))?;
+                Ok(TimingAdjustment::FrameRate {
+                    from: parse_frame_rate(from)?,
+                    to: parse_frame_rate(to)?,
+                })
+            }
+            "stretch" => {
+                let (first, second) = value.split_once(';').ok_or_else(|| {
+                    format!("Expected 'stretch:<old>=<new>;<old>=<new>', got '{}'.", spec)
+                })?;
+                let first = parse_sync_point(first)?;
+                let second = parse_sync_point(second)?;
+                if first.0 == second.0 {
+                    return Err("Stretch sync points must refer to different cues.".to_string());
+                }
+                Ok(TimingAdjustment::Stretch { first, second })
+            }
+            other => Err(format!(
+                "Unknown timing adjustment '{}'. Use 'shift', 'stretch' or 'fps'.",
+                other
+            )),
+        }
+    }
+
+    pub fn apply(&self, cues: &mut [Cue]) {
+        match self {
+            TimingAdjustment::Shift(offset_ms) => shift(cues, *offset_ms),
+            TimingAdjustment::Stretch { first, second } => stretch(cues, *first, *second),
+            TimingAdjustment::FrameRate { from, to } => convert_frame_rate(cues, *from, *to),
+        }
+    }
+}
+
+impl fmt::Display for TimingAdjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimingAdjustment::Shift(offset_ms) => write!(f, "shift by {:+} ms", offset_ms),
+            TimingAdjustment::Stretch { first, second } => write!(
+                f,
+                "stretch {} -> {} and {} -> {}",
+                first.0, first.1, second.0, second.1
+            ),
+            TimingAdjustment::FrameRate { from, to } => {
+                write!(f, "frame rate {:.3} fps -> {:.3} fps", from, to)
+            }
+        }
+    }
+}
+
+pub fn apply_all(cues: &mut [Cue], adjustments: &[TimingAdjustment]) {
+    for adjustment in adjustments {
+        LOGGER.debug(format!("Applying timing adjustment: {}", adjustment).as_str());
+        adjustment.apply(cues);
+    }
+}
+
+pub fn shift(cues: &mut [Cue], offset_ms: i64) {
+    remap(cues, |ms| ms as f64 + offset_ms as f64);
+}
+
+pub fn stretch(
+    cues: &mut [Cue],
+    first: (Timestamp, Timestamp),
+    second: (Timestamp, Timestamp),
+) {
+    let old_span = second.0.as_millis() as f64 - first.0.as_millis() as f64;
+    if old_span == 0.0 {
+        LOGGER.warning("Stretch sync points are identical; timing left unchanged.");
+        return;
+    }
+    let factor = (second.1.as_millis() as f64 - first.1.as_millis() as f64) / old_span;
+    let old_anchor = first.0.as_millis() as f64;
+    let new_anchor = first.1.as_millis() as f64;
+    remap(cues, |ms| new_anchor + (ms as f64 - old_anchor) * factor);
+}
+
+/// Rescales timestamps so cues timed against `from_fps` playback line up with `to_fps`.
+pub fn convert_frame_rate(cues: &mut [Cue], from_fps: f64, to_fps: f64) {
+    if from_fps <= 0.0 || to_fps <= 0.0 {
+        LOGGER.warning("Frame rates must be positive; timing left unchanged.");
+        return;
+    }
+    let factor = from_fps / to_fps;
+    remap(cues, |ms| ms as f64 * factor);
+}
+
+fn remap(cues: &mut [Cue], map: impl Fn(u64) -> f64) {
+    for cue in cues.iter_mut() {
+        cue.start = Timestamp::from_millis(map(cue.start.as_millis()).round() as i64);
+        cue.end = Timestamp::from_millis(map(cue.end.as_millis()).round() as i64);
+    }
+}
+
+/// Accepts plain milliseconds (`-1500`), seconds with an `s` suffix (`2.5s`) or a signed timestamp.
+fn parse_offset(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    let (sign, magnitude) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    if magnitude.contains(':') {
+        return Ok(sign * Timestamp::parse(magnitude)?.as_millis() as i64);
+    }
+    if let Some(seconds) = magnitude.strip_suffix('s') {
+        if let Some(millis) = seconds.strip_suffix('m') {
+            return millis
+                .parse::<i64>()
+                .map(|ms| sign * ms)
+                .map_err(|_| format!("Invalid offset '{}'.", value));
+        }
+        return seconds
+            .parse::<f64>()
+            .map(|s| sign * (s * 1000.0).round() as i64)
+            .map_err(|_| format!("Invalid offset '{}'.", value));
+    }
+    magnitude
+        .parse::<i64>()
+        .map(|ms| sign * ms)
+        .map_err(|_| format!("Invalid offset '{}'.", value))
+}
+
+/// Maps the usual NTSC shorthands to their exact rational rates.
+pub fn parse_frame_rate(value: &str) -> Result<f64, String> {
+    match value.trim() {
+        "23.976" | "23.98" => Ok(24000.0 / 1001.0),
+        "29.97" => Ok(30000.0 / 1001.0),
+        "47.952" => Ok(48000.0 / 1001.0),
+        "59.94" => Ok(60000.0 / 1001.0),
+        other => match other.parse::<f64>() {
+            Ok(fps) if fps > 0.0 => Ok(fps),
+            _ => Err(format!("Invalid frame rate '{}'.", other)),
+        },
+    }
+}
+
+fn parse_sync_point(value: &str) -> Result<(Timestamp, Timestamp), String> {
+    let (old, new) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Expected '<old>=<new>' sync point, got '{}'.", value))?;
+    Ok((Timestamp::parse(old)?, Timestamp::parse(new)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: u64, end: u64) -> Cue {
+        Cue {
+            start: Timestamp(start),
+            end: Timestamp(end),
+            ..Default::default()
+        }
+    }
+
+    fn times(cues: &[Cue]) -> Vec<(u64, u64)> {
+        cues.iter().map(|cue| (cue.start.as_millis(), cue.end.as_millis())).collect()
+    }
+
+    #[test]
+    fn parses_adjustments() {
+        assert_eq!(TimingAdjustment::parse("shift:+1500"), Ok(TimingAdjustment::Shift(1500)));
+        assert_eq!(TimingAdjustment::parse("shift:-2.5s"), Ok(TimingAdjustment::Shift(-2500)));
+        assert_eq!(TimingAdjustment::parse("shift:250ms"), Ok(TimingAdjustment::Shift(250)));
+        assert_eq!(
+            TimingAdjustment::parse("shift:-00:00:01,200"),
+            Ok(TimingAdjustment::Shift(-1200))
+        );
+        assert_eq!(
+            TimingAdjustment::parse("fps:25:23.976"),
+            Ok(TimingAdjustment::FrameRate { from: 25.0, to: 24000.0 / 1001.0 })
+        );
+        assert!(TimingAdjustment::parse("stretch:00:00:10,000=00:00:11,000;00:00:10,000=00:00:12,000").is_err());
+        assert!(TimingAdjustment::parse("speed:2").is_err());
+        assert!(TimingAdjustment::parse("fps:0:25").is_err());
+    }
+
+    #[test]
+    fn shift_clamps_at_zero() {
+        let mut cues = vec![cue(500, 1500), cue(3000, 4000)];
+        shift(&mut cues, -1000);
+        assert_eq!(times(&cues), vec![(0, 500), (2000, 3000)]);
+    }
+
+    #[test]
+    fn stretch_maps_both_sync_points_exactly() {
+        let mut cues = vec![cue(10_000, 12_000), cue(100_000, 101_000)];
+        stretch(
+            &mut cues,
+            (Timestamp(10_000), Timestamp(11_000)),
+            (Timestamp(100_000), Timestamp(104_000)),
+        );
+        assert_eq!(times(&cues), vec![(11_000, 13_067), (104_000, 105_033)]);
+    }
+
+    #[test]
+    fn frame_rate_conversion_rescales_and_rounds() {
+        let mut cues = vec![cue(1_000, 60_000)];
+        convert_frame_rate(&mut cues, 25.0, 24000.0 / 1001.0);
+        assert_eq!(times(&cues), vec![(1_043, 62_563)]);
+        convert_frame_rate(&mut cues, -1.0, 25.0);
+        assert_eq!(times(&cues), vec![(1_043, 62_563)]);
+    }
+}
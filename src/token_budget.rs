@@ -0,0 +1,314 @@
+use std::{sync::LazyLock, time::Duration};
+
+use serde::Deserialize;
+
+use crate::logger::Logger;
+use crate::translator::GenerationSettings;
+use crate::utils;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("TokenBudget"));
+
+/// Tokens per character assumed before any request has been measured.
+const DEFAULT_INPUT_TOKENS_PER_CHAR: f64 = 0.3;
+/// Translated text is usually longer than its source and the target language may tokenize
+/// worse; until responses have been measured, output is estimated this much above the input.
+const DEFAULT_OUTPUT_EXPANSION: f64 = 1.5;
+/// Share of the output limit a chunk is planned to fill, leaving room for estimation error.
+const OUTPUT_HEADROOM: f64 = 0.7;
+/// Share of the input limit left for the chunk once instructions and context are counted.
+const INPUT_HEADROOM: f64 = 0.5;
+/// Text sent to `countTokens` to calibrate; more would not change the ratio much.
+const MAX_COUNT_SAMPLE_CHARS: usize = 20_000;
+
+/// Token limits of a model.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelLimits {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    /// Smallest content an explicit `cachedContents` entry accepts; `None` when the model
+    /// has no explicit caching.
+    pub min_cached_tokens: Option<usize>,
+}
+
+impl ModelLimits {
+    /// Published limits of the known model families; unknown models get conservative ones.
+    pub fn for_model(model: &str) -> Self {
+        let (input_tokens, output_tokens) = match model {
+            m if m.starts_with("gemini-2.5") => (1_048_576, 65_536),
+            m if m.starts_with("gemini-1.5-pro") => (2_097_152, 8_192),
+            m if m.starts_with("gemini-2.0") || m.starts_with("gemini-1.5") => (1_048_576, 8_192),
+            m if m.starts_with("gemma-3") => (131_072, 8_192),
+            _ => (32_768, 8_192),
+        };
+        let min_cached_tokens = match model {
+            m if m.starts_with("gemini-2.5-flash") => Some(1_024),
+            m if m.starts_with("gemini-1.5") || m.starts_with("gemini-2.0") => Some(32_768),
+            m if m.starts_with("gemini-") => Some(4_096),
+            _ => None,
+        };
+        ModelLimits {
+            input_tokens,
+            output_tokens,
+            min_cached_tokens,
+        }
+    }
+}
+
+/// How large a chunk may get, in estimated tokens and in lines.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkBudget {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub max_lines: usize,
+}
+
+impl ChunkBudget {
+    /// The budget for `settings`: `output_tokens` when set, otherwise a share of the smaller of
+    /// `max_output_tokens` and the model's limit, less the thinking budget thinking models
+    /// take from it.
+    pub fn new(settings: &GenerationSettings, output_tokens: usize, max_lines: usize) -> Self {
+        let limits = ModelLimits::for_model(&settings.model);
+        let output_tokens = if output_tokens > 0 {
+            output_tokens
+        } else {
+            let limit = limits.output_tokens.min(settings.max_output_tokens as usize);
+            let thinking = settings.thinking_budget.filter(|budget| *budget > 0).unwrap_or_default() as usize;
+            (limit.saturating_sub(thinking) as f64 * OUTPUT_HEADROOM) as usize
+        };
+        ChunkBudget {
+            input_tokens: (limits.input_tokens as f64 * INPUT_HEADROOM) as usize,
+            output_tokens: output_tokens.max(1),
+            max_lines,
+        }
+    }
+}
+
+/// Estimates token counts from character counts, using ratios measured on this run's
+/// requests (`usageMetadata`) or on a `countTokens` call, and defaults until then.
+#[derive(Debug, Default)]
+pub struct TokenEstimator {
+    prompt_chars: usize,
+    prompt_tokens: usize,
+    /// Source characters of translated chunks and the output tokens their translations took.
+    source_chars: usize,
+    output_tokens: usize,
+}
+
+impl TokenEstimator {
+    /// Whether any request has been measured yet.
+    pub fn is_calibrated(&self) -> bool {
+        self.prompt_tokens > 0
+    }
+
+    fn input_tokens_per_char(&self) -> f64 {
+        if self.prompt_chars > 0 && self.prompt_tokens > 0 {
+            self.prompt_tokens as f64 / self.prompt_chars as f64
+        } else {
+            DEFAULT_INPUT_TOKENS_PER_CHAR
+        }
+    }
+
+    fn output_tokens_per_source_char(&self) -> f64 {
+        if self.source_chars > 0 && self.output_tokens > 0 {
+            self.output_tokens as f64 / self.source_chars as f64
+        } else {
+            self.input_tokens_per_char() * DEFAULT_OUTPUT_EXPANSION
+        }
+    }
+
+    pub fn input_tokens(&self, text: &str) -> usize {
+        (text.chars().count() as f64 * self.input_tokens_per_char()).ceil() as usize
+    }
+
+    /// Output tokens the translation of `source` is expected to take.
+    pub fn output_tokens(&self, source: &str) -> usize {
+        (source.chars().count() as f64 * self.output_tokens_per_source_char()).ceil() as usize
+    }
+
+    /// Records the prompt that was sent and the tokens it took.
+    pub fn record_prompt(&mut self, prompt_chars: usize, prompt_tokens: usize) {
+        if prompt_chars > 0 && prompt_tokens > 0 {
+            self.prompt_chars += prompt_chars;
+            self.prompt_tokens += prompt_tokens;
+        }
+    }
+
+    /// Records the output tokens the translation of `source_chars` characters took.
+    pub fn record_output(&mut self, source_chars: usize, output_tokens: usize) {
+        if source_chars > 0 && output_tokens > 0 {
+            self.source_chars += source_chars;
+            self.output_tokens += output_tokens;
+        }
+    }
+
+    /// Calibrates the input ratio with the `countTokens` endpoint on a sample of `text`.
+    pub fn calibrate_with_count(&mut self, api_key: &str, model: &str, text: &str) -> Result<(), String> {
+        let sample: String = text.chars().take(MAX_COUNT_SAMPLE_CHARS).collect();
+        let tokens = count_tokens(api_key, model, &sample)?;
+        self.record_prompt(sample.chars().count(), tokens);
+        LOGGER.debug(
+            format!(
+                "countTokens: {} characters are {} tokens on {} ({:.2} tokens per character).",
+                sample.chars().count(),
+                tokens,
+                model,
+                self.input_tokens_per_char()
+            )
+            .as_str(),
+        );
+        Ok(())
+    }
+
+    /// Splits `<cue>.<line>_<text>` lines into chunks that fit `budget`. A cue is never split
+    /// across chunks; a single cue larger than the budget becomes a chunk of its own.
+    pub fn split_into_chunks(&self, text: &str, budget: &ChunkBudget) -> Vec<String> {
+        let mut cues: Vec<Vec<&str>> = Vec::new();
+        let mut current_cue = None;
+        for line in text.lines().map(str::trim_end).filter(|line| !line.is_empty()) {
+            let cue = line.split_once('_').map(|(id, _)| id.split('.').next().unwrap_or(id));
+            if cue.is_none() || cue != current_cue {
+                cues.push(Vec::new());
+            }
+            current_cue = cue;
+            if let Some(cue_lines) = cues.last_mut() {
+                cue_lines.push(line);
+            }
+        }
+
+        let mut chunks = Vec::new();
+        let mut chunk: Vec<&str> = Vec::new();
+        let (mut input, mut output) = (0, 0);
+        for cue in cues {
+            let cue_text = cue.join("\n");
+            let (cue_input, cue_output) = (self.input_tokens(&cue_text), self.output_tokens(&cue_text));
+            let fits = chunk.len() + cue.len() <= budget.max_lines
+                && input + cue_input <= budget.input_tokens
+                && output + cue_output <= budget.output_tokens;
+            if !fits && !chunk.is_empty() {
+                chunks.push(chunk.join("\n"));
+                chunk.clear();
+                (input, output) = (0, 0);
+            }
+            chunk.extend(cue);
+            input += cue_input;
+            output += cue_output;
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk.join("\n"));
+        }
+        chunks
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CountTokensResponse {
+    total_tokens: usize,
+}
+
+fn count_tokens(api_key: &str, model: &str, text: &str) -> Result<usize, String> {
+    let url = format!("https://generativelanguage.googleapis.com/v1beta/models/{}:countTokens", model);
+    let body = serde_json::json!({ "contents": [{ "parts": [{ "text": text }] }] });
+    let resp = utils::http_client()?
+        .post(&url)
+        .timeout(Duration::from_secs(60))
+        .header("x-goog-api-key", api_key)
+        .json(&body)
+        .send()
+        .map_err(|e| format!("Error communicating with API: {}", e))?;
+    let status = resp.status();
+    let text = resp.text().map_err(|e| format!("Error reading API response: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("Request failed (Status {}): {}", status, text));
+    }
+    serde_json::from_str::<CountTokensResponse>(&text)
+        .map(|counted| counted.total_tokens)
+        .map_err(|e| format!("Error parsing API response: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(input_tokens: usize, output_tokens: usize, max_lines: usize) -> ChunkBudget {
+        ChunkBudget {
+            input_tokens,
+            output_tokens,
+            max_lines,
+        }
+    }
+
+    fn cue_ids(chunk: &str) -> Vec<&str> {
+        let mut ids: Vec<&str> = chunk.lines().map(|line| line.split('.').next().unwrap()).collect();
+        ids.dedup();
+        ids
+    }
+
+    #[test]
+    fn budget_follows_the_model_and_settings() {
+        let settings = GenerationSettings::default();
+        let default = ChunkBudget::new(&settings, 0, 150);
+        assert_eq!((default.input_tokens, default.output_tokens, default.max_lines), (524_288, 5_734, 150));
+        assert_eq!(ChunkBudget::new(&settings, 1_000, 150).output_tokens, 1_000);
+
+        let thinking = GenerationSettings {
+            model: "gemini-2.5-flash".to_string(),
+            max_output_tokens: 20_000,
+            thinking_budget: Some(4_000),
+            ..GenerationSettings::default()
+        };
+        assert_eq!(ChunkBudget::new(&thinking, 0, 150).output_tokens, 11_200);
+
+        let unknown = GenerationSettings {
+            model: "some-local-model".to_string(),
+            max_output_tokens: 1,
+            ..GenerationSettings::default()
+        };
+        let budget = ChunkBudget::new(&unknown, 0, 150);
+        assert_eq!((budget.input_tokens, budget.output_tokens), (16_384, 1));
+    }
+
+    #[test]
+    fn chunks_respect_the_line_limit_without_splitting_cues() {
+        let text = "0.0_One\n0.1_one b\n1.0_Two\n2.0_Three\n2.1_three b\n3.0_Four";
+        let chunks = TokenEstimator::default().split_into_chunks(text, &budget(10_000, 10_000, 3));
+        assert_eq!(chunks, vec!["0.0_One\n0.1_one b\n1.0_Two", "2.0_Three\n2.1_three b\n3.0_Four"]);
+
+        let chunks = TokenEstimator::default().split_into_chunks(text, &budget(10_000, 10_000, 2));
+        assert_eq!(chunks.iter().map(|chunk| cue_ids(chunk)).collect::<Vec<_>>(), vec![vec!["0"], vec!["1"], vec!["2"], vec!["3"]]);
+    }
+
+    #[test]
+    fn chunks_respect_the_token_limits() {
+        let line = |cue: usize| format!("{}.0_{}", cue, "x".repeat(94));
+        let text: Vec<String> = (0..6).map(line).collect();
+        let estimator = TokenEstimator::default();
+        // Each 100-character line is estimated at 30 input and 45 output tokens.
+        assert_eq!((estimator.input_tokens(&line(0)), estimator.output_tokens(&line(0))), (30, 45));
+
+        let by_output = estimator.split_into_chunks(&text.join("\n"), &budget(10_000, 100, 150));
+        assert_eq!(by_output.iter().map(|chunk| chunk.lines().count()).collect::<Vec<_>>(), vec![2, 2, 2]);
+        let by_input = estimator.split_into_chunks(&text.join("\n"), &budget(90, 10_000, 150));
+        assert_eq!(by_input.iter().map(|chunk| chunk.lines().count()).collect::<Vec<_>>(), vec![3, 3]);
+    }
+
+    #[test]
+    fn oversized_cue_gets_a_chunk_of_its_own() {
+        let text = "0.0_Short\n1.0_A much longer first line\n1.1_and a second line\n1.2_and a third\n2.0_Short";
+        let chunks = TokenEstimator::default().split_into_chunks(text, &budget(10_000, 10_000, 2));
+        assert_eq!(chunks.iter().map(|chunk| cue_ids(chunk)).collect::<Vec<_>>(), vec![vec!["0"], vec!["1"], vec!["2"]]);
+        assert_eq!(chunks[1].lines().count(), 3);
+    }
+
+    #[test]
+    fn measured_ratios_replace_the_defaults() {
+        let mut estimator = TokenEstimator::default();
+        assert!(!estimator.is_calibrated());
+        estimator.record_prompt(1_000, 500);
+        estimator.record_output(100, 80);
+        estimator.record_prompt(0, 100);
+        assert!(estimator.is_calibrated());
+        assert_eq!(estimator.input_tokens(&"x".repeat(10)), 5);
+        assert_eq!(estimator.output_tokens(&"x".repeat(10)), 8);
+    }
+}
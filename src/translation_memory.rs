@@ -0,0 +1,632 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::alignment;
+use crate::config::Config;
+use crate::logger::Logger;
+use crate::parser;
+use crate::subtitle::Cue;
+use crate::utils::LanguagePair;
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("TranslationMemory"));
+
+const MEMORY_FILE_NAME: &str = "translation_memory.json";
+const MEMORY_FORMAT_VERSION: u32 = 1;
+/// Word overlap (Dice coefficient) an entry needs to serve as an example for a line.
+const MIN_EXAMPLE_SIMILARITY: f64 = 0.5;
+/// Words found in more entries than this are too common to find similar lines by.
+const MAX_WORD_POSTINGS: usize = 2000;
+
+/// An accepted translation of one subtitle line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemoryEntry {
+    pub source_language: String,
+    pub target_language: String,
+    /// Source text with whitespace normalized, see [`normalize`].
+    pub source: String,
+    /// [`context_hash`] of the line before it.
+    pub context_hash: String,
+    pub translation: String,
+    pub created_at_unix: u64,
+    pub last_used_at_unix: u64,
+    /// Times the entry filled a line instead of a request.
+    #[serde(default)]
+    pub uses: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MemoryFile {
+    version: u32,
+    entries: Vec<MemoryEntry>,
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct TextKey {
+    source_language: String,
+    target_language: String,
+    source: String,
+}
+
+impl TextKey {
+    fn of(entry: &MemoryEntry) -> Self {
+        TextKey {
+            source_language: entry.source_language.clone(),
+            target_language: entry.target_language.clone(),
+            source: entry.source.clone(),
+        }
+    }
+
+    fn new(languages: &LanguagePair, source: &str) -> Self {
+        TextKey {
+            source_language: languages.source.clone(),
+            target_language: languages.target.clone(),
+            source: source.to_string(),
+        }
+    }
+}
+
+/// Local store of accepted line translations, keyed by normalized source text, language pair
+/// and the line before it, so recurring lines are filled without a request.
+pub struct TranslationMemory {
+    path: PathBuf,
+    entries: Vec<MemoryEntry>,
+    index: HashMap<TextKey, Vec<usize>>,
+    /// Entries by the words of their source text, built on the first example lookup.
+    word_index: Option<HashMap<String, Vec<usize>>>,
+    dirty: bool,
+}
+
+/// Outcome of importing one pair of subtitle files.
+#[derive(Default)]
+pub struct PairImport {
+    pub source_cues: usize,
+    pub aligned_cues: usize,
+    pub stored_lines: usize,
+}
+
+/// Distinct lowercase words of a line.
+fn words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+/// Collapses runs of whitespace and trims, so spacing differences still match.
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// FNV-1a hash of the normalized line before a line (empty for the first line of a file).
+pub fn context_hash(previous_line: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in normalize(previous_line).bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// One `<id>_<text>` line of the text sent for translation, with the context hash the
+/// memory files it under.
+pub struct SourceLine<'a> {
+    pub id: &'a str,
+    pub text: &'a str,
+    pub context_hash: String,
+}
+
+/// Splits `<id>_<text>` lines, hashing each line's predecessor.
+pub fn source_lines(subtitle_text: &str) -> Vec<SourceLine<'_>> {
+    let mut previous = "";
+    subtitle_text
+        .lines()
+        .filter_map(|line| line.split_once('_'))
+        .map(|(id, text)| {
+            let line = SourceLine {
+                id,
+                text,
+                context_hash: context_hash(previous),
+            };
+            previous = text;
+            line
+        })
+        .collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// `translation_memory_file` from the config, or `translation_memory.json` in the user data folder.
+pub fn configured_path(config: &Config) -> Result<PathBuf, String> {
+    if !config.translation_memory_file.is_empty() {
+        return Ok(PathBuf::from(&config.translation_memory_file));
+    }
+    dirs::data_dir()
+        .map(|dir| dir.join("ai_translate").join(MEMORY_FILE_NAME))
+        .ok_or_else(|| "Could not determine the user data folder for the translation memory.".to_string())
+}
+
+fn read_entries(path: &Path) -> Result<Vec<MemoryEntry>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read translation memory '{}': {}", path.display(), e))?;
+    let file: MemoryFile = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse translation memory '{}': {}", path.display(), e))?;
+    if file.version > MEMORY_FORMAT_VERSION {
+        return Err(format!(
+            "Translation memory '{}' was written by a newer version (format {}).",
+            path.display(),
+            file.version
+        ));
+    }
+    Ok(file.entries)
+}
+
+fn write_entries(path: &Path, entries: &[MemoryEntry]) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Error creating folder '{}': {}", parent.display(), e))?;
+    }
+    let file = MemoryFile {
+        version: MEMORY_FORMAT_VERSION,
+        entries: entries.to_vec(),
+    };
+    let json = serde_json::to_string(&file).map_err(|e| format!("Failed to serialize translation memory: {}", e))?;
+    // Write next to the target and rename, so an interrupted run cannot truncate the memory.
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, json)
+        .and_then(|_| fs::rename(&temp_path, path))
+        .map_err(|e| format!("Failed to write translation memory '{}': {}", path.display(), e))
+}
+
+impl TranslationMemory {
+    /// Opens the memory at `path`; a missing file is an empty memory.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let entries = if path.is_file() { read_entries(path)? } else { Vec::new() };
+        let mut memory = TranslationMemory {
+            path: path.to_path_buf(),
+            entries,
+            index: HashMap::new(),
+            word_index: None,
+            dirty: false,
+        };
+        memory.rebuild_index();
+        Ok(memory)
+    }
+
+    fn rebuild_index(&mut self) {
+        self.word_index = None;
+        self.index.clear();
+        for (position, entry) in self.entries.iter().enumerate() {
+            self.index.entry(TextKey::of(entry)).or_default().push(position);
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[MemoryEntry] {
+        &self.entries
+    }
+
+    /// The stored translation of `source` after a line hashing to `context_hash`. With
+    /// `any_context`, a line only seen after other lines still matches if it was always
+    /// translated the same way.
+    pub fn lookup(
+        &mut self,
+        languages: &LanguagePair,
+        source: &str,
+        context_hash: &str,
+        any_context: bool,
+    ) -> Option<String> {
+        let positions = self.index.get(&TextKey::new(languages, &normalize(source)))?;
+        let position = match positions.iter().find(|&&position| self.entries[position].context_hash == context_hash) {
+            Some(&position) => position,
+            None if !any_context => return None,
+            None => {
+                let first = positions[0];
+                let translation = &self.entries[first].translation;
+                if !positions.iter().all(|&position| &self.entries[position].translation == translation) {
+                    return None;
+                }
+                first
+            }
+        };
+        let entry = &mut self.entries[position];
+        entry.uses += 1;
+        entry.last_used_at_unix = unix_now();
+        self.dirty = true;
+        Some(entry.translation.clone())
+    }
+
+    /// Remembers an accepted translation, replacing the one stored for the same line and context.
+    pub fn store(&mut self, languages: &LanguagePair, source: &str, context_hash: &str, translation: &str) {
+        let source = normalize(source);
+        let translation = translation.trim();
+        if source.is_empty() || translation.is_empty() {
+            return;
+        }
+        let now = unix_now();
+        let mut entry = MemoryEntry {
+            source_language: languages.source.clone(),
+            target_language: languages.target.clone(),
+            source,
+            context_hash: context_hash.to_string(),
+            translation: translation.to_string(),
+            created_at_unix: now,
+            last_used_at_unix: now,
+            uses: 0,
+        };
+        if let Some(position) = self.position_of(&entry) {
+            entry.created_at_unix = self.entries[position].created_at_unix;
+            entry.uses = self.entries[position].uses;
+        }
+        self.upsert(entry);
+    }
+
+    fn position_of(&self, entry: &MemoryEntry) -> Option<usize> {
+        self.index.get(&TextKey::of(entry)).and_then(|positions| {
+            positions
+                .iter()
+                .copied()
+                .find(|&position| self.entries[position].context_hash == entry.context_hash)
+        })
+    }
+
+    /// Inserts `entry` or replaces the entry for the same line and context.
+    fn upsert(&mut self, entry: MemoryEntry) {
+        self.dirty = true;
+        match self.position_of(&entry) {
+            Some(position) => self.entries[position] = entry,
+            None => {
+                let position = self.entries.len();
+                self.index.entry(TextKey::of(&entry)).or_default().push(position);
+                if let Some(word_index) = self.word_index.as_mut() {
+                    for word in words(&entry.source) {
+                        word_index.entry(word).or_default().push(position);
+                    }
+                }
+                self.entries.push(entry);
+            }
+        }
+    }
+
+    /// Stores the lines of source cues whose timing matches a target cue, as in a human
+    /// translation of the same video. Cues are paired line by line when both have the same
+    /// number of lines, and a one-line source cue takes the whole target cue.
+    pub fn import_pairs(
+        &mut self,
+        languages: &LanguagePair,
+        source: &[Cue],
+        target: &[Cue],
+        min_overlap: f64,
+    ) -> PairImport {
+        let ai_text = parser::convert_vec_to_ai_string(parser::format_cues(source)[1].clone());
+        let lines = source_lines(&ai_text);
+        let mut import = PairImport {
+            source_cues: source.len(),
+            ..Default::default()
+        };
+        let mut lines_by_cue: HashMap<&str, Vec<&SourceLine>> = HashMap::new();
+        for line in &lines {
+            let cue = line.id.split_once('.').map_or(line.id, |(cue, _)| cue);
+            lines_by_cue.entry(cue).or_default().push(line);
+        }
+        for (source_position, target_position) in alignment::align_cues(source, target, min_overlap) {
+            let Some(cue_lines) = lines_by_cue.get(source_position.to_string().as_str()) else {
+                continue;
+            };
+            let target_lines: Vec<&str> = target[target_position]
+                .text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect();
+            let pairs: Vec<(&SourceLine, String)> = if cue_lines.len() == target_lines.len() {
+                cue_lines.iter().copied().zip(target_lines.iter().map(|line| line.to_string())).collect()
+            } else if cue_lines.len() == 1 && !target_lines.is_empty() {
+                vec![(cue_lines[0], target_lines.join(" "))]
+            } else {
+                continue;
+            };
+            import.aligned_cues += 1;
+            for (line, translation) in pairs {
+                if !normalize(line.text).is_empty() {
+                    self.store(languages, line.text, &line.context_hash, &translation);
+                    import.stored_lines += 1;
+                }
+            }
+        }
+        import
+    }
+
+    /// Up to `limit` stored translations of lines similar to `lines`, best match first, to show
+    /// the model as examples of earlier (often human) translations.
+    pub fn similar_examples(&mut self, languages: &LanguagePair, lines: &[&str], limit: usize) -> Vec<(String, String)> {
+        if limit == 0 || self.entries.is_empty() {
+            return Vec::new();
+        }
+        let entries = &self.entries;
+        let word_index = self.word_index.get_or_insert_with(|| {
+            let mut word_index: HashMap<String, Vec<usize>> = HashMap::new();
+            for (position, entry) in entries.iter().enumerate() {
+                for word in words(&entry.source) {
+                    word_index.entry(word).or_default().push(position);
+                }
+            }
+            word_index
+        });
+
+        let mut scored: Vec<(f64, usize)> = Vec::new();
+        for line in lines {
+            let line_words = words(line);
+            let mut shared: HashMap<usize, usize> = HashMap::new();
+            for word in &line_words {
+                match word_index.get(word) {
+                    Some(positions) if positions.len() <= MAX_WORD_POSTINGS => {
+                        for &position in positions {
+                            *shared.entry(position).or_default() += 1;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let best = shared
+                .into_iter()
+                .filter(|&(position, _)| {
+                    entries[position].source_language == languages.source
+                        && entries[position].target_language == languages.target
+                })
+                .map(|(position, shared)| {
+                    let entry_words = words(&entries[position].source).len();
+                    (2.0 * shared as f64 / (line_words.len() + entry_words) as f64, position)
+                })
+                .filter(|&(similarity, _)| similarity >= MIN_EXAMPLE_SIMILARITY)
+                .max_by(|a, b| a.0.total_cmp(&b.0));
+            scored.extend(best);
+        }
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut examples: Vec<(String, String)> = Vec::new();
+        for (_, position) in scored {
+            let entry = &entries[position];
+            if examples.len() < limit && !examples.iter().any(|(source, _)| source == &entry.source) {
+                examples.push((entry.source.clone(), entry.translation.clone()));
+            }
+        }
+        examples
+    }
+
+    /// Writes the memory back if it changed.
+    pub fn save(&mut self) -> Result<(), String> {
+        if !self.dirty {
+            return Ok(());
+        }
+        write_entries(&self.path, &self.entries)?;
+        self.dirty = false;
+        LOGGER.debug(format!("Saved {} entries to '{}'.", self.entries.len(), self.path.display()).as_str());
+        Ok(())
+    }
+
+    /// Writes every entry to `path` in the memory's own format.
+    pub fn export(&self, path: &Path) -> Result<usize, String> {
+        write_entries(path, &self.entries)?;
+        Ok(self.entries.len())
+    }
+
+    /// Merges entries exported from another memory; for a line already stored in the same
+    /// context, the more recently used translation wins. Returns (added, replaced).
+    pub fn import(&mut self, path: &Path) -> Result<(usize, usize), String> {
+        let (mut added, mut replaced) = (0, 0);
+        for mut entry in read_entries(path)? {
+            entry.source = normalize(&entry.source);
+            if entry.source.is_empty() || entry.translation.trim().is_empty() {
+                continue;
+            }
+            match self.position_of(&entry) {
+                Some(position) if self.entries[position].last_used_at_unix >= entry.last_used_at_unix => {}
+                Some(_) => {
+                    self.upsert(entry);
+                    replaced += 1;
+                }
+                None => {
+                    self.upsert(entry);
+                    added += 1;
+                }
+            }
+        }
+        Ok((added, replaced))
+    }
+
+    /// Removes the entries `remove` selects. Returns how many were removed.
+    pub fn prune(&mut self, remove: impl Fn(&MemoryEntry) -> bool) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|entry| !remove(entry));
+        let removed = before - self.entries.len();
+        if removed > 0 {
+            self.dirty = true;
+            self.rebuild_index();
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn languages() -> LanguagePair {
+        LanguagePair {
+            source: "en".to_string(),
+            target: "de".to_string(),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ai_translate_memory_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn memory(name: &str) -> TranslationMemory {
+        TranslationMemory::open(&temp_path(name)).unwrap()
+    }
+
+    #[test]
+    fn looks_up_by_normalized_text_and_context() {
+        let mut memory = memory("lookup.json");
+        let after_question = context_hash("Are you coming?");
+        memory.store(&languages(), "  Yes. ", &after_question, " Ja. ");
+
+        assert_eq!(memory.lookup(&languages(), "Yes.", &after_question, false), Some("Ja.".to_string()));
+        assert_eq!(memory.entries()[0].uses, 1);
+        let other_pair = LanguagePair {
+            source: "en".to_string(),
+            target: "fr".to_string(),
+        };
+        assert_eq!(memory.lookup(&other_pair, "Yes.", &after_question, false), None);
+    }
+
+    #[test]
+    fn other_contexts_match_only_when_allowed_and_consistent() {
+        let mut memory = memory("any_context.json");
+        memory.store(&languages(), "Yes.", &context_hash("Are you coming?"), "Ja.");
+        let new_context = context_hash("Is it raining?");
+
+        assert_eq!(memory.lookup(&languages(), "Yes.", &new_context, false), None);
+        assert_eq!(memory.lookup(&languages(), "Yes.", &new_context, true), Some("Ja.".to_string()));
+
+        memory.store(&languages(), "Yes.", &context_hash("Do you love me?"), "Doch.");
+        assert_eq!(memory.lookup(&languages(), "Yes.", &new_context, true), None);
+    }
+
+    #[test]
+    fn store_replaces_the_same_line_and_context() {
+        let mut memory = memory("store.json");
+        let context = context_hash("");
+        memory.store(&languages(), "Hello", &context, "Hallo");
+        memory.store(&languages(), "Hello", &context, "Guten Tag");
+        memory.store(&languages(), "   ", &context, "leer");
+        memory.store(&languages(), "Bye", &context, " ");
+
+        assert_eq!(memory.entries().len(), 1);
+        assert_eq!(memory.lookup(&languages(), "Hello", &context, false), Some("Guten Tag".to_string()));
+    }
+
+    #[test]
+    fn saved_memory_reopens_and_leaves_no_temporary_file() {
+        let path = temp_path("save.json");
+        let mut memory = TranslationMemory::open(&path).unwrap();
+        memory.store(&languages(), "Hello", &context_hash(""), "Hallo");
+        memory.save().unwrap();
+
+        assert!(!path.with_extension("json.tmp").exists());
+        let mut reopened = TranslationMemory::open(&path).unwrap();
+        assert_eq!(reopened.lookup(&languages(), "Hello", &context_hash(""), false), Some("Hallo".to_string()));
+    }
+
+    #[test]
+    fn newer_format_is_rejected() {
+        let path = temp_path("newer.json");
+        fs::write(&path, r#"{"version": 99, "entries": []}"#).unwrap();
+        assert!(TranslationMemory::open(&path).err().unwrap().contains("newer version"));
+    }
+
+    #[test]
+    fn import_keeps_the_more_recently_used_translation() {
+        let context = context_hash("");
+        let mut other = memory("import_other.json");
+        other.store(&languages(), "Hello", &context, "Servus");
+        other.store(&languages(), "Bye", &context, "Tschüss");
+        other.entries[0].last_used_at_unix = u64::MAX;
+        let export_path = temp_path("import_export.json");
+        assert_eq!(other.export(&export_path).unwrap(), 2);
+
+        let mut memory = memory("import.json");
+        memory.store(&languages(), "Hello", &context, "Hallo");
+        memory.store(&languages(), "Bye", &context, "Auf Wiedersehen");
+        memory.entries[1].last_used_at_unix = u64::MAX;
+
+        assert_eq!(memory.import(&export_path).unwrap(), (0, 1));
+        assert_eq!(memory.lookup(&languages(), "Hello", &context, false), Some("Servus".to_string()));
+        assert_eq!(memory.lookup(&languages(), "Bye", &context, false), Some("Auf Wiedersehen".to_string()));
+    }
+
+    #[test]
+    fn prune_removes_selected_entries_and_keeps_lookups_working() {
+        let mut memory = memory("prune.json");
+        let context = context_hash("");
+        memory.store(&languages(), "One", &context, "Eins");
+        memory.store(&languages(), "Two", &context, "Zwei");
+        memory.store(&languages(), "Three", &context, "Drei");
+
+        assert_eq!(memory.prune(|entry| entry.source == "Two"), 1);
+        assert_eq!(memory.prune(|_| false), 0);
+        assert_eq!(memory.lookup(&languages(), "Two", &context, false), None);
+        assert_eq!(memory.lookup(&languages(), "Three", &context, false), Some("Drei".to_string()));
+    }
+
+    fn cue(start: u64, end: u64, text: &str) -> Cue {
+        Cue {
+            start: crate::subtitle::Timestamp(start),
+            end: crate::subtitle::Timestamp(end),
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn import_pairs_lines_of_aligned_cues() {
+        let source = [
+            cue(1_000, 2_000, "Where is he?\nIn the ship."),
+            cue(3_000, 4_000, "Run!"),
+            cue(5_000, 6_000, "One line\nand another."),
+            cue(7_000, 8_000, "Only mine"),
+            cue(8_000, 9_000, "were merged"),
+        ];
+        let target = [
+            cue(1_100, 2_100, "Wo ist er?\nIm Schiff."),
+            cue(3_000, 4_000, "Lauf,\nschnell!"),
+            cue(5_000, 6_000, "Drei\nZeilen\nhier."),
+            cue(7_000, 9_000, "Zusammengelegt"),
+        ];
+        let mut memory = memory("import_pairs.json");
+        let import = memory.import_pairs(&languages(), &source, &target, 0.5);
+
+        assert_eq!((import.source_cues, import.aligned_cues, import.stored_lines), (5, 2, 3));
+        let found: Vec<(&str, &str)> = memory
+            .entries()
+            .iter()
+            .map(|entry| (entry.source.as_str(), entry.translation.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("Where is he?", "Wo ist er?"), ("In the ship.", "Im Schiff."), ("Run!", "Lauf, schnell!")]
+        );
+        assert_eq!(memory.entries()[1].context_hash, context_hash("Where is he?"));
+    }
+
+    #[test]
+    fn source_lines_hash_the_previous_line() {
+        let lines = source_lines("0.0_Hello\n1.0_World");
+        assert_eq!(lines[0].context_hash, context_hash(""));
+        assert_eq!(lines[1].context_hash, context_hash(" Hello "));
+        assert_eq!((lines[1].id, lines[1].text), ("1.0", "World"));
+    }
+}
@@ -2,211 +2,1488 @@
 #![allow(dead_code)]
 
 use regex::Regex;
-use reqwest::blocking;
 use serde::{Deserialize, Serialize};
-use std::{env, sync::LazyLock, thread, time::Duration};
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    fmt,
+    hash::{BuildHasher, Hasher},
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, LazyLock, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
 
+use crate::config::{self, Config};
+use crate::key_pool::KeyPool;
+use crate::glossary::{Glossary, GlossaryEntry, GlossaryViolation};
+use crate::prompt_cache::PromptCache;
+use crate::rate_limiter::RateLimiter;
+use crate::term_extractor::TermCandidate;
+use crate::token_budget::{ChunkBudget, TokenEstimator};
+use crate::translation_memory::{self, SourceLine, TranslationMemory};
 use crate::logger::Logger;
+use crate::utils::{self, LanguagePair};
 
 static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Translator"));
 
-pub fn translate_subtitle(subtitle_text: String, api_key: String) -> Result<String, String> {
-    let chunk_size: usize = env!("SUBTITLE_LINE_CHUNKS").parse().unwrap_or(150);
-    let max_retries: u8 = env!("MAX_RETRY_ERROR").parse().unwrap_or(3);
-    let retry_delay_ms: u64 = env!("RETRY_DELAY_MS").parse().unwrap_or(1000);
+/// The translation service used for a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    Gemini,
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gemini" => Some(Backend::Gemini),
+            _ => None,
+        }
+    }
+
+    /// Environment variable checked for this backend's API key.
+    pub fn api_key_env_var(&self) -> &'static str {
+        match self {
+            Backend::Gemini => "GEMINI_API_KEY",
+        }
+    }
+
+    /// Whether the backend can be asked for JSON matching a response schema.
+    pub fn supports_structured_output(&self) -> bool {
+        match self {
+            Backend::Gemini => true,
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Gemini => write!(f, "gemini"),
+        }
+    }
+}
+
+/// Harm-category thresholds accepted by `safety_threshold`.
+pub const SAFETY_THRESHOLDS: [&str; 5] = [
+    "BLOCK_NONE",
+    "BLOCK_ONLY_HIGH",
+    "BLOCK_MEDIUM_AND_ABOVE",
+    "BLOCK_LOW_AND_ABOVE",
+    "OFF",
+];
+
+const HARM_CATEGORIES: [&str; 4] = [
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+];
+
+/// Model and sampling parameters sent with every request. Unset options are left to the
+/// model's own defaults.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GenerationSettings {
+    pub model: String,
+    pub temperature: f64,
+    pub top_p: Option<f64>,
+    pub top_k: Option<u32>,
+    pub max_output_tokens: u32,
+    /// Tokens the model may spend thinking; 0 turns thinking off and -1 lets the model decide.
+    /// Only thinking models accept it.
+    pub thinking_budget: Option<i32>,
+    /// One of `SAFETY_THRESHOLDS`, applied to every harm category.
+    pub safety_threshold: Option<String>,
+    /// Ask for a JSON array of `{id, text}` items instead of prefixed text lines.
+    pub structured_output: bool,
+    /// Store the system instruction in a `cachedContents` entry once per run instead of
+    /// sending it with every chunk. Explicit caches have a minimum size (1,024 tokens on
+    /// gemini-2.5-flash, 4,096 on other current models, 32,768 on gemini-2.0 and 1.5) while
+    /// the instructions are about 500 to 1,000 tokens, so the cache is skipped for any model
+    /// whose minimum they do not reach. Skipped or refused caches send the instruction inline.
+    pub context_cache: bool,
+    pub context_cache_ttl_secs: u64,
+}
+
+impl Default for GenerationSettings {
+    fn default() -> Self {
+        GenerationSettings {
+            model: "gemini-2.0-flash".to_string(),
+            temperature: 1.5,
+            top_p: None,
+            top_k: None,
+            max_output_tokens: 8192,
+            thinking_budget: None,
+            safety_threshold: None,
+            structured_output: false,
+            context_cache: false,
+            context_cache_ttl_secs: 3600,
+        }
+    }
+}
+
+impl GenerationSettings {
+    /// Returns one message per invalid setting; `section` prefixes the setting names.
+    pub fn problems(&self, section: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.model.trim().is_empty() {
+            problems.push(format!("{}.model must not be empty", section));
+        }
+        if !(0.0..=2.0).contains(&self.temperature) {
+            problems.push(format!("{}.temperature must be between 0 and 2", section));
+        }
+        if self.top_p.is_some_and(|top_p| !(0.0..=1.0).contains(&top_p)) {
+            problems.push(format!("{}.top_p must be between 0 and 1", section));
+        }
+        if self.top_k == Some(0) {
+            problems.push(format!("{}.top_k must be at least 1", section));
+        }
+        if self.max_output_tokens == 0 {
+            problems.push(format!("{}.max_output_tokens must be at least 1", section));
+        }
+        if self.context_cache_ttl_secs < 60 {
+            problems.push(format!("{}.context_cache_ttl_secs must be at least 60", section));
+        }
+        if self.thinking_budget.is_some_and(|budget| budget < -1) {
+            problems.push(format!("{}.thinking_budget must be -1 or more", section));
+        }
+        if let Some(threshold) = &self.safety_threshold {
+            if !SAFETY_THRESHOLDS.contains(&threshold.as_str()) {
+                problems.push(format!(
+                    "{}.safety_threshold must be one of {}, got '{}'",
+                    section,
+                    SAFETY_THRESHOLDS.join(", "),
+                    threshold
+                ));
+            }
+        }
+        problems
+    }
+
+    /// One-line summary for logs.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![
+            format!("model {}", self.model),
+            format!("temperature {}", self.temperature),
+        ];
+        if let Some(top_p) = self.top_p {
+            parts.push(format!("top-p {}", top_p));
+        }
+        if let Some(top_k) = self.top_k {
+            parts.push(format!("top-k {}", top_k));
+        }
+        parts.push(format!("max output tokens {}", self.max_output_tokens));
+        if let Some(budget) = self.thinking_budget {
+            parts.push(format!("thinking budget {}", budget));
+        }
+        if let Some(threshold) = &self.safety_threshold {
+            parts.push(format!("safety {}", threshold));
+        }
+        if self.structured_output {
+            parts.push("structured output".to_string());
+        }
+        if self.context_cache {
+            parts.push(format!("context cache ({}s TTL)", self.context_cache_ttl_secs));
+        }
+        parts.join(", ")
+    }
+}
+
+/// A backend and the generation settings to call it with; one link of the fallback chain.
+#[derive(Debug, Clone)]
+pub struct ModelChoice {
+    pub backend: Backend,
+    pub generation: GenerationSettings,
+}
+
+impl ModelChoice {
+    /// Shown in logs and the run report, e.g. `gemini/gemini-2.5-pro`.
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.backend, self.generation.model)
+    }
+}
+
+/// Checks a fallback entry written as `model` or `backend:model`.
+pub fn parse_fallback_entry(entry: &str) -> Result<(Option<Backend>, String), String> {
+    let (backend, model) = match entry.split_once(':') {
+        Some((backend_name, model)) => {
+            let backend = Backend::from_name(backend_name.trim())
+                .ok_or_else(|| format!("unsupported backend '{}' in fallback model '{}'", backend_name, entry))?;
+            (Some(backend), model.trim())
+        }
+        None => (None, entry.trim()),
+    };
+    if model.is_empty() {
+        return Err(format!("fallback model '{}' has no model name", entry));
+    }
+    Ok((backend, model.to_string()))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TranslationOptions {
+    pub backend: Backend,
+    pub languages: LanguagePair,
+    pub generation: GenerationSettings,
+    /// Models tried in order once a chunk keeps failing on the primary one.
+    pub fallbacks: Vec<ModelChoice>,
+    /// Source lines (with their accepted translations) carried into the next request.
+    pub context_lines: usize,
+    /// Keep a short summary of the story so far and send it with every request.
+    pub rolling_summary: bool,
+    /// Similar earlier translations from the translation memory shown with each chunk.
+    pub memory_examples: usize,
+    /// Fill lines from memory entries stored after a different line (config: memory_any_context).
+    pub memory_any_context: bool,
+    /// Send each distinct line of a file once.
+    pub deduplicate_lines: bool,
+    /// Estimated output tokens per chunk; 0 sizes chunks from the model's output limit.
+    pub chunk_output_tokens: usize,
+    /// Calibrate the token estimate with the `countTokens` endpoint before the first chunk.
+    pub count_tokens: bool,
+    /// Chunks of a file in flight at once; see `Config::concurrent_requests` for the context
+    /// this costs.
+    pub concurrent_requests: usize,
+    /// Limits for the whole run; 0 for none.
+    pub requests_per_minute: usize,
+    pub tokens_per_minute: usize,
+}
+
+impl TranslationOptions {
+    pub fn from_config(config: &Config) -> Self {
+        let backend = config.backend();
+        let mut options = TranslationOptions {
+            backend,
+            languages: config.languages(),
+            generation: config.generation_settings(backend).clone(),
+            fallbacks: Vec::new(),
+            context_lines: config.context_lines,
+            rolling_summary: config.rolling_summary,
+            memory_examples: config.memory_examples,
+            memory_any_context: config.memory_any_context,
+            deduplicate_lines: config.deduplicate_lines,
+            chunk_output_tokens: config.chunk_output_tokens,
+            count_tokens: config.count_tokens,
+            concurrent_requests: config.concurrent_requests,
+            requests_per_minute: config.requests_per_minute,
+            tokens_per_minute: config.tokens_per_minute,
+        };
+        // Entries were checked when the configuration was loaded.
+        let _ = options.set_fallbacks(&config.fallback_models, config);
+        options
+    }
+
+    /// Builds the fallback chain. A model on the primary backend keeps this run's generation
+    /// settings; a model on another backend uses that backend's config section.
+    pub fn set_fallbacks(&mut self, entries: &[String], config: &Config) -> Result<(), String> {
+        self.fallbacks = entries
+            .iter()
+            .map(|entry| {
+                let (backend, model) = parse_fallback_entry(entry)?;
+                let backend = backend.unwrap_or(self.backend);
+                let mut generation = if backend == self.backend {
+                    self.generation.clone()
+                } else {
+                    config.generation_settings(backend).clone()
+                };
+                generation.model = model;
+                Ok(ModelChoice { backend, generation })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(())
+    }
+
+    /// The primary model followed by the fallbacks.
+    pub fn model_chain(&self) -> Vec<ModelChoice> {
+        let primary = ModelChoice {
+            backend: self.backend,
+            generation: self.generation.clone(),
+        };
+        std::iter::once(primary).chain(self.fallbacks.iter().cloned()).collect()
+    }
+}
+
+/// Which model produced a chunk and how many attempts it took.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChunkRecord {
+    pub chunk: usize,
+    pub lines: usize,
+    pub model: String,
+    pub attempts: u8,
+    /// The chunk only went through after retrying with relaxed safety settings.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub relaxed_safety: bool,
+}
+
+/// Read-only context sent ahead of each chunk so names, pronouns and register carry over
+/// chunk boundaries: the file's glossary, the last translated lines and a rolling story summary.
+#[derive(Default, Clone)]
+pub struct TranslationContext {
+    max_lines: usize,
+    recent: VecDeque<(String, String)>,
+    summary: String,
+    glossary: Glossary,
+}
+
+impl TranslationContext {
+    pub fn new(max_lines: usize, glossary: Glossary) -> Self {
+        TranslationContext {
+            max_lines,
+            glossary,
+            ..Default::default()
+        }
+    }
+
+    /// Remembers the accepted translation of `source_chunk`; both use `<id>_<text>` lines.
+    fn record(&mut self, source_chunk: &str, translated_chunk: &str) {
+        if self.max_lines == 0 {
+            return;
+        }
+        let translated: HashMap<&str, &str> = translated_chunk
+            .lines()
+            .filter_map(|line| line.split_once('_'))
+            .collect();
+        for (id, text) in source_chunk.lines().filter_map(|line| line.split_once('_')) {
+            if let Some(translation) = translated.get(id) {
+                self.recent.push_back((text.trim().to_string(), translation.trim().to_string()));
+            }
+        }
+        while self.recent.len() > self.max_lines {
+            self.recent.pop_front();
+        }
+    }
+
+    /// The block placed before the chunk, or `None` when there is no context yet. Line ids are
+    /// left out so the model cannot mistake context lines for lines it has to return.
+    fn render(&self) -> Option<String> {
+        if self.recent.is_empty() && self.summary.is_empty() {
+            return None;
+        }
+        let mut block = String::from("CONTEXT (read-only; do not translate or repeat it):\n");
+        if !self.summary.is_empty() {
+            block.push_str(&format!("Story so far: {}\n", self.summary));
+        }
+        if !self.recent.is_empty() {
+            block.push_str("Previous lines and their accepted translations:\n");
+            for (source, translation) in &self.recent {
+                block.push_str(&format!("{} => {}\n", source, translation));
+            }
+        }
+        block.push_str("END OF CONTEXT\n\n");
+        Some(block)
+    }
+}
+
+/// A subtitle line the model would not translate even on its own; the source text is kept.
+#[derive(Serialize, Debug, Clone)]
+pub struct LineFailure {
+    /// 1-based cue and line numbers within the cue.
+    pub cue: usize,
+    pub line: usize,
+    pub reason: String,
+}
+
+impl LineFailure {
+    fn from_prefixed_line(line: &str, reason: String) -> Self {
+        let (cue, line) = parse_line_id(line.split_once('_').map_or(line, |(id, _)| id));
+        LineFailure { cue, line, reason }
+    }
+}
+
+/// Turns a `<cue>.<line>` id into 1-based cue and line numbers.
+pub fn parse_line_id(id: &str) -> (usize, usize) {
+    let mut numbers = id.split('.').map(|n| n.trim().parse::<usize>().unwrap_or_default());
+    (
+        numbers.next().unwrap_or_default() + 1,
+        numbers.next().unwrap_or_default() + 1,
+    )
+}
+
+/// A translated subtitle and the per-chunk record of how it was produced.
+#[derive(Default)]
+pub struct SubtitleTranslation {
+    pub text: String,
+    pub chunks: Vec<ChunkRecord>,
+    pub failed_lines: Vec<LineFailure>,
+    /// Translated lines missing the required rendering of a glossary term.
+    pub glossary_violations: Vec<GlossaryViolation>,
+    /// Lines filled from the translation memory instead of being sent.
+    pub memory_lines: usize,
+    /// Repeats of a line earlier in the file that were not sent again.
+    pub duplicate_lines: usize,
+}
+
+impl SubtitleTranslation {
+    fn push_text(&mut self, text: &str) {
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        self.text.push_str(text);
+    }
+}
+
+/// How a failed request should be handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Retrying cannot help (bad key, bad request, unknown model); stop the run.
+    Fatal,
+    /// Quota or rate limit hit; wait for the delay the API asks for.
+    RateLimited,
+    /// Network hiccups, overloaded servers and unusable model output; back off and retry.
+    Transient,
+    /// The response hit the output token limit; the chunk must be made smaller.
+    Truncated,
+    /// The response was blocked (safety, recitation, ...) even after relaxing the safety
+    /// settings; the offending lines must be isolated.
+    Blocked,
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub class: ErrorClass,
+    pub message: String,
+    /// Delay requested by the server through `Retry-After` or a `RetryInfo` detail.
+    pub retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    pub fn new(class: ErrorClass, message: String) -> Self {
+        ApiError {
+            class,
+            message,
+            retry_after: None,
+        }
+    }
+
+    pub fn transient(message: String) -> Self {
+        ApiError::new(ErrorClass::Transient, message)
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        self.class == ErrorClass::Fatal
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Run-wide state shared by every request of a translation run, including requests made
+/// at the same time from several worker threads.
+pub struct TranslationSession {
+    key_pool: Mutex<KeyPool>,
+    prompt_cache: PromptCache,
+    /// Fills lines translated before without a request; `None` when disabled.
+    pub memory: Option<TranslationMemory>,
+    /// Token ratios measured on this run's requests, used to size the chunks of later files.
+    token_estimator: Mutex<TokenEstimator>,
+    rate_limiter: RateLimiter,
+}
+
+impl TranslationSession {
+    pub fn new(key_pool: KeyPool, options: &TranslationOptions) -> Self {
+        TranslationSession {
+            key_pool: Mutex::new(key_pool),
+            prompt_cache: PromptCache::default(),
+            memory: None,
+            token_estimator: Mutex::new(TokenEstimator::default()),
+            rate_limiter: RateLimiter::new(options.requests_per_minute, options.tokens_per_minute),
+        }
+    }
+
+    /// Deletes the run's prompt caches, saves the translation memory and logs how each API
+    /// key was used.
+    pub fn finish(&mut self) {
+        self.prompt_cache.cleanup();
+        if let Some(Err(e)) = self.memory.as_mut().map(TranslationMemory::save) {
+            LOGGER.warning(&e);
+        }
+        lock(&self.key_pool).log_usage();
+    }
+
+    /// The key the next request is sent with.
+    fn current_key(&self) -> RequestKey {
+        let key_pool = lock(&self.key_pool);
+        RequestKey {
+            api_key: key_pool.current_key().to_string(),
+            label: key_pool.current_label().to_string(),
+            index: key_pool.current_index(),
+        }
+    }
+}
+
+/// An API key taken from the pool for one request. Usage is recorded against `index`, since
+/// other workers may rotate the pool while the request is running.
+struct RequestKey {
+    api_key: String,
+    label: String,
+    index: usize,
+}
+
+/// A worker that panicked cannot leave the guarded state half updated in a way that matters
+/// here, so a poisoned lock is used as is.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+
+pub fn translate_subtitle(
+    subtitle_text: String,
+    session: &mut TranslationSession,
+    options: &TranslationOptions,
+    glossary: Glossary,
+) -> Result<SubtitleTranslation, ApiError> {
+    let config = config::get();
+    let max_retries = config.max_retry_error;
 
     if subtitle_text.trim().is_empty() {
         LOGGER.warning("Input subtitle text is empty. Nothing to translate.");
-        return Err("".to_string());
+        return Err(ApiError::transient(String::new()));
+    }
+
+    let lines = translation_memory::source_lines(&subtitle_text);
+    let mut remembered: HashMap<&str, String> = HashMap::new();
+    if let Some(memory) = session.memory.as_mut() {
+        for line in &lines {
+            if let Some(translated) = memory.lookup(&options.languages, line.text, &line.context_hash, options.memory_any_context) {
+                remembered.insert(line.id, translated);
+            }
+        }
+    }
+    if !remembered.is_empty() {
+        LOGGER.info(
+            format!(
+                "{} of {} line(s) filled from the translation memory.",
+                remembered.len(),
+                lines.len()
+            )
+            .as_str(),
+        );
+    }
+    // A line repeated within the file is sent once; its other occurrences copy the translation.
+    let mut first_occurrence: HashMap<String, &str> = HashMap::new();
+    let mut duplicates: HashMap<&str, &str> = HashMap::new();
+    if options.deduplicate_lines {
+        for line in lines.iter().filter(|line| !remembered.contains_key(line.id)) {
+            let text = translation_memory::normalize(line.text);
+            if text.is_empty() {
+                continue;
+            }
+            match first_occurrence.get(&text) {
+                Some(first) => {
+                    duplicates.insert(line.id, first);
+                }
+                None => {
+                    first_occurrence.insert(text, line.id);
+                }
+            }
+        }
+        if !duplicates.is_empty() {
+            LOGGER.info(format!("{} repeated line(s) will reuse the translation of their first occurrence.", duplicates.len()).as_str());
+        }
     }
+    let pending_text = if remembered.is_empty() && duplicates.is_empty() {
+        subtitle_text.clone()
+    } else {
+        subtitle_text
+            .lines()
+            .filter(|line| {
+                line.split_once('_')
+                    .is_none_or(|(id, _)| !remembered.contains_key(id) && !duplicates.contains_key(id))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
 
-    let chunks = split_into_chunks(&subtitle_text, chunk_size);
-    if chunks.is_empty() || chunks.iter().all(|c| c.trim().is_empty()) {
+    let mut estimator = lock(&session.token_estimator);
+    if options.count_tokens && !estimator.is_calibrated() {
+        let key = session.current_key();
+        if let Err(e) = estimator.calibrate_with_count(&key.api_key, &options.generation.model, &pending_text) {
+            LOGGER.warning(format!("Counting tokens failed; chunk sizes are estimated. {}", e).as_str());
+        }
+    }
+    let budget = ChunkBudget::new(&options.generation, options.chunk_output_tokens, config.subtitle_line_chunks);
+    let chunks = estimator.split_into_chunks(&pending_text, &budget);
+    drop(estimator);
+    if remembered.is_empty() && (chunks.is_empty() || chunks.iter().all(|c| c.trim().is_empty())) {
         LOGGER.warning("Subtitle text split into empty chunks. Nothing to translate.");
-        return Err("".to_string());
+        return Err(ApiError::transient(String::new()));
     }
 
-    let mut translated_chunks = Vec::new();
+    let mut translation = SubtitleTranslation::default();
+    let mut context = TranslationContext::new(options.context_lines, glossary);
     let total_chunks = chunks.len();
 
     LOGGER.info(
         format!(
-            "Input text split into {} chunks for translation.",
-            total_chunks
+            "Input text split into {} chunks for translation (up to about {} output tokens or {} lines each).",
+            total_chunks, budget.output_tokens, budget.max_lines
         )
         .as_str(),
     );
 
-    for (i, chunk) in chunks.iter().enumerate() {
-        if chunk.trim().is_empty() {
-            LOGGER.info(format!("Chunk {}/{} is empty, skipping.", i + 1, total_chunks).as_str());
-            translated_chunks.push("".to_string()); // Add empty string to maintain structure if needed
-            continue;
-        }
-        LOGGER.process(format!("⏳ Translating chunk {} of {}...", i + 1, total_chunks).as_str());
-
-        match attempt_translation_with_retries(
-            api_key.as_str(),
-            chunk,
-            i + 1,
-            total_chunks,
-            max_retries,
-            retry_delay_ms,
-        ) {
-            Ok(translated_chunk_text) => {
-                translated_chunks.push(translated_chunk_text);
-                LOGGER.success(
-                    format!(
-                        "Chunk {} of {} translated successfully.",
-                        i + 1,
-                        total_chunks
-                    )
-                    .as_str(),
-                );
+    let concurrency = options.concurrent_requests.max(1);
+    let examples: Vec<String> = chunks
+        .iter()
+        .map(|chunk| memory_examples_block(session, chunk, options))
+        .collect();
+    let shared: &TranslationSession = session;
+    // Up to `concurrency` chunks are in flight and the next one starts, with the context as it
+    // stands then, as soon as any of them finishes. Results are recorded in chunk order so the
+    // output never depends on timing.
+    thread::scope(|scope| -> Result<(), ApiError> {
+        let (sender, receiver) = mpsc::channel();
+        let mut finished: HashMap<usize, Result<SubtitleTranslation, ApiError>> = HashMap::new();
+        let (mut next_to_start, mut next_to_record, mut in_flight) = (0, 0, 0);
+        while next_to_record < total_chunks {
+            while in_flight < concurrency && next_to_start < total_chunks {
+                let i = next_to_start;
+                let (sender, chunk, examples, snapshot) = (sender.clone(), &chunks[i], &examples[i], context.clone());
+                scope.spawn(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        translate_chunk(shared, chunk, options, (i + 1, total_chunks), examples, &snapshot)
+                    }))
+                    .unwrap_or_else(|_| Err(ApiError::transient("A translation worker panicked.".to_string())));
+                    // The receiver is gone once an earlier chunk has failed; the result is not needed then.
+                    let _ = sender.send((i, result));
+                });
+                next_to_start += 1;
+                in_flight += 1;
             }
-            Err(e) => {
-                LOGGER.error(
-                    format!(
-                        "❌ Translation of chunk {} of {} failed after {} retries: {}",
-                        i + 1,
-                        total_chunks,
-                        max_retries, // This should be attempt count from the error if available, or max_retries
-                        e
-                    )
-                    .as_str(),
-                );
-                return Err(format!("Error: {}", e).to_string()); // Propagate a generic error string
+            let (i, result) = receiver
+                .recv()
+                .map_err(|_| ApiError::transient("The translation workers stopped unexpectedly.".to_string()))?;
+            in_flight -= 1;
+            finished.insert(i, result);
+
+            while let Some(result) = finished.remove(&next_to_record) {
+                let i = next_to_record;
+                let chunk = &chunks[i];
+                match result {
+                    Ok(piece) => {
+                        LOGGER.success(
+                            format!(
+                                "Chunk {} of {} translated successfully.",
+                                i + 1,
+                                total_chunks
+                            )
+                            .as_str(),
+                        );
+                        let failed: Vec<(usize, usize)> =
+                            piece.failed_lines.iter().map(|failure| (failure.cue, failure.line)).collect();
+                        let accepted: Vec<&str> = piece
+                            .text
+                            .lines()
+                            .filter(|line| !failed.contains(&parse_line_id(line.split_once('_').map_or(line, |(id, _)| id))))
+                            .collect();
+                        context.record(chunk, &accepted.join("\n"));
+                        translation.push_text(&piece.text);
+                        translation.chunks.extend(piece.chunks);
+                        translation.failed_lines.extend(piece.failed_lines);
+                        if options.rolling_summary && i + 1 < total_chunks {
+                            update_summary(shared, options, &mut context, chunk);
+                        }
+                    }
+                    Err(e) if e.is_fatal() => {
+                        LOGGER.error(
+                            format!(
+                                "❌ Translation of chunk {} of {} stopped by a non-retryable error: {}",
+                                i + 1,
+                                total_chunks,
+                                e
+                            )
+                            .as_str(),
+                        );
+                        return Err(e);
+                    }
+                    Err(e) => {
+                        LOGGER.error(
+                            format!(
+                                "❌ Translation of chunk {} of {} failed after {} retries: {}",
+                                i + 1,
+                                total_chunks,
+                                max_retries,
+                                e
+                            )
+                            .as_str(),
+                        );
+                        return Err(ApiError {
+                            message: format!("Error: {}", e.message),
+                            ..e
+                        });
+                    }
+                }
+                next_to_record += 1;
             }
         }
+        Ok(())
+    })?;
+    if !translation.failed_lines.is_empty() {
+        LOGGER.warning(
+            format!(
+                "{} line(s) could not be translated and keep their source text.",
+                translation.failed_lines.len()
+            )
+            .as_str(),
+        );
+    }
+    if !remembered.is_empty() || !duplicates.is_empty() {
+        let translated: HashMap<&str, &str> = translation
+            .text
+            .lines()
+            .filter_map(|line| line.split_once('_'))
+            .collect();
+        translation.text = lines
+            .iter()
+            .filter_map(|line| match (remembered.get(line.id), duplicates.get(line.id)) {
+                (Some(text), _) => Some(format!("{}_{}", line.id, text)),
+                (None, Some(first)) => translated.get(first).map(|text| format!("{}_{}", line.id, text)),
+                (None, None) => translated.get(line.id).map(|text| format!("{}_{}", line.id, text)),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        // A repeat of a line that could not be translated kept its source text as well.
+        let repeated_failures: Vec<LineFailure> = duplicates
+            .iter()
+            .filter_map(|(id, first)| {
+                let first = parse_line_id(first);
+                let failure = translation
+                    .failed_lines
+                    .iter()
+                    .find(|failure| (failure.cue, failure.line) == first)?;
+                let (cue, line) = parse_line_id(id);
+                Some(LineFailure {
+                    cue,
+                    line,
+                    reason: failure.reason.clone(),
+                })
+            })
+            .collect();
+        translation.failed_lines.extend(repeated_failures);
+        translation.failed_lines.sort_by_key(|failure| (failure.cue, failure.line));
+        translation.memory_lines = remembered.len();
+        translation.duplicate_lines = duplicates.len();
     }
-    Ok(translated_chunks.join("\n"))
+    translation.glossary_violations = context
+        .glossary
+        .check(&subtitle_text, &translation.text)
+        .into_iter()
+        .filter(|violation| {
+            !translation
+                .failed_lines
+                .iter()
+                .any(|failure| failure.cue == violation.cue && failure.line == violation.line)
+        })
+        .collect();
+    for violation in &translation.glossary_violations {
+        LOGGER.warning(
+            format!(
+                "Cue {} line {}: glossary term '{}' was not rendered as '{}'.",
+                violation.cue, violation.line, violation.term, violation.expected
+            )
+            .as_str(),
+        );
+    }
+    if let Some(memory) = session.memory.as_mut() {
+        remember_translations(memory, options, &lines, &remembered, &translation);
+    }
+    Ok(translation)
+}
+
+/// Stores the newly translated lines, leaving out lines that failed or break the glossary.
+fn remember_translations(
+    memory: &mut TranslationMemory,
+    options: &TranslationOptions,
+    lines: &[SourceLine],
+    remembered: &HashMap<&str, String>,
+    translation: &SubtitleTranslation,
+) {
+    let rejected: Vec<(usize, usize)> = translation
+        .failed_lines
+        .iter()
+        .map(|failure| (failure.cue, failure.line))
+        .chain(translation.glossary_violations.iter().map(|violation| (violation.cue, violation.line)))
+        .collect();
+    let translated: HashMap<&str, &str> = translation
+        .text
+        .lines()
+        .filter_map(|line| line.split_once('_'))
+        .collect();
+    for line in lines.iter().filter(|line| !remembered.contains_key(line.id)) {
+        if rejected.contains(&parse_line_id(line.id)) {
+            continue;
+        }
+        if let Some(text) = translated.get(line.id) {
+            memory.store(&options.languages, line.text, &line.context_hash, text);
+        }
+    }
+    if let Err(e) = memory.save() {
+        LOGGER.warning(&e);
+    }
+}
+
+/// Where to halve a piece: the cue boundary nearest the middle, or the middle itself when the
+/// piece holds a single cue.
+fn split_point(lines: &[&str]) -> usize {
+    let cue = |line: &str| parse_line_id(line.split_once('_').map_or(line, |(id, _)| id)).0;
+    let middle = lines.len() / 2;
+    (1..lines.len())
+        .filter(|&i| cue(lines[i - 1]) != cue(lines[i]))
+        .min_by_key(|&i| i.abs_diff(middle))
+        .unwrap_or(middle)
+}
+
+/// Translates one chunk, usually on a worker thread, into a translation of its own.
+fn translate_chunk(
+    session: &TranslationSession,
+    chunk: &str,
+    options: &TranslationOptions,
+    (chunk_index, total_chunks): (usize, usize),
+    examples: &str,
+    context: &TranslationContext,
+) -> Result<SubtitleTranslation, ApiError> {
+    let mut translation = SubtitleTranslation::default();
+    if chunk.trim().is_empty() {
+        LOGGER.info(format!("Chunk {}/{} is empty, skipping.", chunk_index, total_chunks).as_str());
+        return Ok(translation);
+    }
+    LOGGER.process(format!("⏳ Translating chunk {} of {}...", chunk_index, total_chunks).as_str());
+    let position = (chunk_index, total_chunks);
+    translate_piece(session, chunk, options, position, examples, context, &mut translation)?;
+    Ok(translation)
+}
+
+/// Translates part of a chunk. A response cut off at the token limit or blocked by the
+/// model is retried as two halves, down to single lines; a single line that still fails
+/// keeps its source text and is recorded in `failed_lines`.
+fn translate_piece(
+    session: &TranslationSession,
+    piece: &str,
+    options: &TranslationOptions,
+    (chunk_index, total_chunks): (usize, usize),
+    examples: &str,
+    context: &TranslationContext,
+    translation: &mut SubtitleTranslation,
+) -> Result<(), ApiError> {
+    let position = (chunk_index, total_chunks);
+    let attempt = attempt_translation_with_retries(session, piece, options, position, examples, context);
+    let error = match attempt {
+        Ok((translated, record)) => {
+            translation.push_text(&translated);
+            translation.chunks.push(record);
+            return Ok(());
+        }
+        Err(error) if matches!(error.class, ErrorClass::Truncated | ErrorClass::Blocked) => error,
+        Err(error) => return Err(error),
+    };
+
+    let lines: Vec<&str> = piece.lines().collect();
+    if lines.len() > 1 {
+        let (first, second) = lines.split_at(split_point(&lines));
+        LOGGER.warning(
+            format!(
+                "Chunk {}/{}: {} Retrying its {} lines as {} and {}.",
+                chunk_index,
+                total_chunks,
+                error.message,
+                lines.len(),
+                first.len(),
+                second.len()
+            )
+            .as_str(),
+        );
+        translate_piece(session, &first.join("\n"), options, position, examples, context, translation)?;
+        return translate_piece(session, &second.join("\n"), options, position, examples, context, translation);
+    }
+
+    let failure = LineFailure::from_prefixed_line(piece, error.message);
+    LOGGER.error(
+        format!(
+            "Cue {} line {} could not be translated: {} The source text is kept.",
+            failure.cue, failure.line, failure.reason
+        )
+        .as_str(),
+    );
+    translation.failed_lines.push(failure);
+    translation.push_text(piece);
+    Ok(())
 }
 
 fn attempt_translation_with_retries(
-    api_key: &str,
-    chunk_text: &String,
-    chunk_index: usize,
-    total_chunks: usize,
-    max_retries: u8,
-    retry_delay_ms: u64,
-) -> Result<String, String> {
-    let prompt = build_translation_prompt(chunk_text);
-    let mut last_error: String = "Unknown error".to_string();
+    session: &TranslationSession,
+    chunk_text: &str,
+    options: &TranslationOptions,
+    (chunk_index, total_chunks): (usize, usize),
+    examples: &str,
+    context: &TranslationContext,
+) -> Result<(String, ChunkRecord), ApiError> {
+    let config = config::get();
+    let max_retries = config.max_retry_error;
+    let mut last_error = ApiError::transient("Unknown error".to_string());
+    let model_chain = options.model_chain();
+    let mut chain_position = 0;
+    let mut failures_on_model = 0;
+    let mut safety_relaxed = false;
 
     for attempt in 1..=max_retries {
-        match gemini_api(api_key, &prompt) {
-            Ok(response_text) => {
-                // Check if response is a GeminiErrorResponse first
-                if let Ok(gemini_error) =
-                    serde_json::from_str::<GeminiErrorResponse>(&response_text)
-                {
-                    last_error = format!(
-                        "Gemini API Error: (Code: {}) {} - Status: {}",
-                        gemini_error.error.code,
-                        gemini_error.error.message,
-                        gemini_error.error.status
-                    );
-                    LOGGER.error(last_error.as_str());
-                    // Fall through to retry logic if not max_retries
-                } else {
-                    // Attempt to parse as successful response if not an error object
-                    match serde_json::from_str::<GeminiResponse>(&response_text) {
-                        Ok(resp) => {
-                            if resp.candidates.is_empty()
-                                || resp.candidates[0].content.parts.is_empty()
-                            {
-                                last_error = "API response was successful but did not contain expected content (candidates/parts).".to_string();
-                                LOGGER.warning(&last_error);
-                                // Fall through to retry
-                            } else {
-                                // Successfully got translatable text
-                                let res_translated =
-                                    resp.candidates[0].content.parts[0].text.clone();
-                                match check_translated_and_orginal_lines(
-                                    &res_translated,
-                                    &chunk_text,
-                                    chunk_index,
-                                    total_chunks,
-                                ) {
-                                    Ok(res) => return Ok(res),
-                                    Err(error) => {
-                                        if error == "NotEqual".to_owned() {
-                                            last_error = "The count of translated lines did not match the number of lines submitted for translation.".to_string();
-                                            LOGGER.warning(&last_error);
-                                        } else {
-                                            last_error = error;
-                                        }
-                                    }
-                                };
-                            }
+        let model = &model_chain[chain_position];
+        let relaxed_generation;
+        let generation = if safety_relaxed {
+            relaxed_generation = GenerationSettings {
+                safety_threshold: Some(RELAXED_SAFETY_THRESHOLD.to_string()),
+                ..model.generation.clone()
+            };
+            &relaxed_generation
+        } else {
+            &model.generation
+        };
+        let structured = generation.structured_output && model.backend.supports_structured_output();
+        let prompt = TranslationPrompt {
+            system_instruction: build_system_instruction(&options.languages, structured),
+            user_content: format!(
+                "{}{}{}{}",
+                context.glossary.render_for(chunk_text).unwrap_or_default(),
+                examples,
+                context.render().unwrap_or_default(),
+                build_user_content(chunk_text, structured)
+            ),
+        };
+        let key = session.current_key();
+        let cached_content = if generation.context_cache {
+            let instruction_tokens = lock(&session.token_estimator).input_tokens(&prompt.system_instruction);
+            session.prompt_cache.cached_content(
+                &key.api_key,
+                &key.label,
+                &generation.model,
+                &prompt.system_instruction,
+                instruction_tokens,
+                Duration::from_secs(generation.context_cache_ttl_secs),
+            )
+        } else {
+            None
+        };
+        let estimated_tokens = {
+            let estimator = lock(&session.token_estimator);
+            estimator.input_tokens(&prompt.system_instruction)
+                + estimator.input_tokens(&prompt.user_content)
+                + estimator.output_tokens(chunk_text)
+        };
+        session.rate_limiter.acquire(estimated_tokens);
+        lock(&session.key_pool).record_request(key.index);
+        let response = match model.backend {
+            Backend::Gemini => gemini_api(&key.api_key, &prompt, generation, cached_content.as_deref()),
+        };
+        match response {
+            Ok(response_text) => match serde_json::from_str::<GeminiResponse>(&response_text) {
+                Ok(resp) => {
+                    if let Some(usage) = &resp.usage_metadata {
+                        lock(&session.key_pool).record_tokens(key.index, usage.total_token_count);
+                        lock(&session.token_estimator).record_prompt(
+                            prompt.system_instruction.chars().count() + prompt.user_content.chars().count(),
+                            usage.prompt_token_count,
+                        );
+                    }
+                    match stop_reason(&resp) {
+                        Some(StopReason::MaxTokens) => {
+                            return Err(ApiError::new(
+                                ErrorClass::Truncated,
+                                format!(
+                                    "The response was cut off at the output token limit ({} tokens).",
+                                    generation.max_output_tokens
+                                ),
+                            ));
                         }
-                        Err(e) => {
-                            // This means the response was not a known GeminiErrorResponse nor a valid GeminiResponse
-                            last_error = format!(
-                                "Error parsing API response: {}. Raw response (partial): '{}'",
-                                e,
-                                response_text.chars().take(200).collect::<String>()
+                        Some(StopReason::Blocked(reason)) => {
+                            let can_relax = reason != "RECITATION"
+                                && config.relax_safety_on_block
+                                && !safety_relaxed
+                                && !generation
+                                    .safety_threshold
+                                    .as_deref()
+                                    .is_some_and(|threshold| threshold == "BLOCK_NONE" || threshold == "OFF");
+                            let blocked = ApiError::new(
+                                ErrorClass::Blocked,
+                                format!("The model blocked the response ({}).", reason),
                             );
-                            LOGGER.error(&last_error);
-                            // Fall through to retry
+                            if can_relax {
+                                safety_relaxed = true;
+                                last_error = blocked;
+                                LOGGER.warning(
+                                    format!(
+                                        "Chunk {}/{} was blocked ({}); retrying with safety threshold {}.",
+                                        chunk_index, total_chunks, reason, RELAXED_SAFETY_THRESHOLD
+                                    )
+                                    .as_str(),
+                                );
+                                continue;
+                            }
+                            return Err(blocked);
                         }
+                        None => {}
                     }
+                    if resp.candidates.is_empty() || resp.candidates[0].content.parts.is_empty() {
+                        last_error = ApiError::transient("API response was successful but did not contain expected content (candidates/parts).".to_string());
+                        LOGGER.warning(&last_error.message);
+                    } else {
+                        let res_translated = resp.candidates[0].content.parts[0].text.clone();
+                        let checked = if structured {
+                            parse_structured_response(&res_translated, chunk_text)
+                        } else {
+                            check_translated_and_orginal_lines(
+                                &res_translated,
+                                chunk_text,
+                                chunk_index,
+                                total_chunks,
+                            )
+                        };
+                        match checked {
+                            Ok(res) => {
+                                lock(&session.key_pool).record_success(key.index);
+                                if let Some(usage) = &resp.usage_metadata {
+                                    lock(&session.token_estimator)
+                                        .record_output(chunk_text.chars().count(), usage.candidates_token_count);
+                                }
+                                let record = ChunkRecord {
+                                    chunk: chunk_index,
+                                    lines: count_non_empty_lines(chunk_text),
+                                    model: model.label(),
+                                    attempts: attempt,
+                                    relaxed_safety: safety_relaxed,
+                                };
+                                return Ok((res, record));
+                            }
+                            Err(error) if error == "NotEqual" => {
+                                last_error = ApiError::transient("The count of translated lines did not match the number of lines submitted for translation.".to_string());
+                                LOGGER.warning(&last_error.message);
+                            }
+                            Err(error) => last_error = ApiError::transient(error),
+                        }
+                    }
+                }
+                Err(e) => {
+                    last_error = ApiError::transient(format!(
+                        "Error parsing API response: {}. Raw response (partial): '{}'",
+                        e,
+                        response_text.chars().take(200).collect::<String>()
+                    ));
+                    LOGGER.error(&last_error.message);
                 }
+            },
+            Err(error) => {
+                LOGGER.error(&error.message);
+                if let (true, Some(cache_name)) = (error.is_fatal(), &cached_content) {
+                    // Most likely the cache expired or was deleted; fall back to inline instructions.
+                    LOGGER.warning("Request with a prompt cache was rejected; retrying without the cache.");
+                    session.prompt_cache.invalidate(cache_name);
+                    last_error = ApiError::transient(error.message);
+                    continue;
+                }
+                if error.is_fatal() {
+                    return Err(error);
+                }
+                last_error = error;
+            }
+        }
+        let mut key_pool = lock(&session.key_pool);
+        if last_error.class == ErrorClass::RateLimited && key_pool.len() > 1 {
+            if key_pool.current_index() != key.index {
+                // Another chunk already switched away from the rate-limited key.
+                continue;
+            }
+            let cooldown = last_error
+                .retry_after
+                .unwrap_or(Duration::from_millis(config.key_cooldown_ms));
+            key_pool.record_rate_limited(key.index, cooldown);
+            if key_pool.rotate() {
+                LOGGER.warning(
+                    format!(
+                        "{} is rate limited; switching to {}.",
+                        key.label,
+                        key_pool.current_label()
+                    )
+                    .as_str(),
+                );
+                continue;
             }
-            Err(e) => {
-                // Network or other pre-API call errors
-                last_error = format!("Error communicating with API: {}", e);
-                LOGGER.error(&last_error);
-                // Fall through to retry
+            if let Some(wait) = key_pool.current_cooldown() {
+                // Every key is cooling down; wait for the first one to come back.
+                last_error.retry_after = Some(wait);
             }
         }
+        drop(key_pool);
+
+        failures_on_model += 1;
+        if failures_on_model >= config.fallback_after_attempts && chain_position + 1 < model_chain.len() {
+            chain_position += 1;
+            failures_on_model = 0;
+            LOGGER.warning(
+                format!(
+                    "Chunk {}/{} failed {} time(s) on {}; retrying with {}.",
+                    chunk_index,
+                    total_chunks,
+                    config.fallback_after_attempts,
+                    model.label(),
+                    model_chain[chain_position].label()
+                )
+                .as_str(),
+            );
+            continue;
+        }
 
         if attempt < max_retries {
+            let delay = retry_delay(&last_error, attempt, config.retry_delay_ms, config.max_retry_delay_ms);
             LOGGER.warning(
                 format!(
-                    "Retrying translation (attempt {}/{}) after {}ms...",
+                    "Retrying translation (attempt {}/{}) after {}ms{}...",
                     attempt + 1, // Next attempt number
                     max_retries,
-                    retry_delay_ms
+                    delay.as_millis(),
+                    if last_error.class == ErrorClass::RateLimited { " (rate limited)" } else { "" }
                 )
                 .as_str(),
             );
-            thread::sleep(Duration::from_millis(retry_delay_ms * attempt as u64));
-            // Simple increasing backoff
+            thread::sleep(delay);
+        }
+    }
+    Err(ApiError {
+        message: format!(
+            "Translation failed after {} attempts. Last error: {}",
+            max_retries, last_error.message
+        ),
+        ..last_error
+    })
+}
+
+/// Prompt block with translations of lines similar to those in `chunk_text` from the
+/// translation memory, or an empty string when there are none.
+fn memory_examples_block(session: &mut TranslationSession, chunk_text: &str, options: &TranslationOptions) -> String {
+    let Some(memory) = session.memory.as_mut() else {
+        return String::new();
+    };
+    let lines: Vec<&str> = chunk_text
+        .lines()
+        .filter_map(|line| line.split_once('_').map(|(_, text)| text))
+        .collect();
+    let examples = memory.similar_examples(&options.languages, &lines, options.memory_examples);
+    if examples.is_empty() {
+        return String::new();
+    }
+    LOGGER.debug(format!("Showing {} earlier translation(s) of similar lines.", examples.len()).as_str());
+    let examples: Vec<String> = examples
+        .iter()
+        .map(|(source, translation)| format!("{} => {}", source, translation))
+        .collect();
+    format!(
+        "EXAMPLES (earlier translations of similar lines):\n{}\nEND OF EXAMPLES\n\n",
+        examples.join("\n")
+    )
+}
+
+/// Folds `chunk_text` into the context's story summary with one extra request on the
+/// primary model. Best effort: on failure the previous summary is kept.
+fn update_summary(
+    session: &TranslationSession,
+    options: &TranslationOptions,
+    context: &mut TranslationContext,
+    chunk_text: &str,
+) {
+    let source_lines: String = chunk_text
+        .lines()
+        .map(|line| line.split_once('_').map_or(line, |(_, text)| text))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let prompt = TranslationPrompt {
+        system_instruction: "You keep a running summary of a video's subtitles for a translator. Given the summary so far and the next subtitle lines, reply with an updated summary of at most five sentences in English: who the characters are, how they relate and whether they address each other formally or informally. Reply with the summary only.".to_string(),
+        user_content: format!(
+            "Summary so far: {}\n\nNext lines:\n{}",
+            if context.summary.is_empty() { "(none)" } else { &context.summary },
+            source_lines
+        ),
+    };
+    let settings = GenerationSettings {
+        max_output_tokens: SUMMARY_MAX_OUTPUT_TOKENS,
+        structured_output: false,
+        ..options.generation.clone()
+    };
+
+    let key = session.current_key();
+    let estimated_tokens = lock(&session.token_estimator).input_tokens(&prompt.user_content);
+    session.rate_limiter.acquire(estimated_tokens + SUMMARY_MAX_OUTPUT_TOKENS as usize);
+    lock(&session.key_pool).record_request(key.index);
+    let response = match options.backend {
+        Backend::Gemini => gemini_api(&key.api_key, &prompt, &settings, None),
+    };
+    let summary = response
+        .map_err(|e| e.message)
+        .and_then(|text| {
+            serde_json::from_str::<GeminiResponse>(&text).map_err(|e| format!("Error parsing API response: {}", e))
+        })
+        .and_then(|resp| {
+            if let Some(usage) = &resp.usage_metadata {
+                lock(&session.key_pool).record_tokens(key.index, usage.total_token_count);
+            }
+            resp.candidates
+                .first()
+                .and_then(|candidate| candidate.content.parts.first())
+                .map(|part| part.text.trim().to_string())
+                .filter(|text| !text.is_empty())
+                .ok_or_else(|| "The summary response was empty.".to_string())
+        });
+    match summary {
+        Ok(summary) => {
+            LOGGER.debug(format!("Story summary: {}", summary).as_str());
+            context.summary = summary;
+        }
+        Err(e) => LOGGER.warning(format!("Could not update the story summary; keeping the previous one. {}", e).as_str()),
+    }
+}
+
+/// Asks the primary model once for the rendering of each candidate term, retrying transient
+/// and rate-limit errors like a chunk. Terms the model leaves out are not returned.
+pub fn propose_glossary(
+    session: &TranslationSession,
+    options: &TranslationOptions,
+    candidates: &[TermCandidate],
+) -> Result<Vec<GlossaryEntry>, ApiError> {
+    let config = config::get();
+    let source = utils::language_name(&options.languages.source);
+    let target = utils::language_name(&options.languages.target);
+    let prompt = TranslationPrompt {
+        system_instruction: format!("You help prepare a glossary for translating the subtitles of a series from {source} to {target}. For every term listed, give the form a professional {target} subtitle translator should use every time it appears: transliterate names of people, places and organizations into {target} script, and translate other terms the way {target} viewers would expect. Reply with exactly one line per term in the form `term => rendering | note`, repeating the term exactly as given; the note says in a few {source} words what the term is (e.g. character name, place, title, slang). Reply with nothing else."),
+        user_content: candidates
+            .iter()
+            .map(|candidate| {
+                format!(
+                    "{} ({} occurrences; e.g. \"{}\")",
+                    candidate.term, candidate.count, candidate.example
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    let settings = GenerationSettings {
+        structured_output: false,
+        ..options.generation.clone()
+    };
+
+    let mut last_error = ApiError::transient("Unknown error".to_string());
+    for attempt in 1..=config.max_retry_error {
+        let key = session.current_key();
+        session.rate_limiter.acquire(lock(&session.token_estimator).input_tokens(&prompt.user_content) * 2);
+        lock(&session.key_pool).record_request(key.index);
+        let response = match options.backend {
+            Backend::Gemini => gemini_api(&key.api_key, &prompt, &settings, None),
+        };
+        let mut key_pool = lock(&session.key_pool);
+        let reply = response.and_then(|text| {
+            let resp = serde_json::from_str::<GeminiResponse>(&text)
+                .map_err(|e| ApiError::transient(format!("Error parsing API response: {}", e)))?;
+            if let Some(usage) = &resp.usage_metadata {
+                key_pool.record_tokens(key.index, usage.total_token_count);
+            }
+            resp.candidates
+                .first()
+                .and_then(|candidate| candidate.content.parts.first())
+                .map(|part| part.text.clone())
+                .ok_or_else(|| ApiError::transient("The response did not contain any text.".to_string()))
+        });
+        match reply {
+            Ok(text) => {
+                key_pool.record_success(key.index);
+                return Ok(parse_glossary_reply(&text, candidates));
+            }
+            Err(error) if error.is_fatal() => return Err(error),
+            Err(error) => {
+                LOGGER.warning(&error.message);
+                last_error = error;
+            }
+        }
+        if last_error.class == ErrorClass::RateLimited && key_pool.len() > 1 {
+            key_pool.record_rate_limited(
+                key.index,
+                last_error
+                    .retry_after
+                    .unwrap_or(Duration::from_millis(config.key_cooldown_ms)),
+            );
+            if key_pool.current_index() != key.index || key_pool.rotate() {
+                continue;
+            }
+        }
+        drop(key_pool);
+        if attempt < config.max_retry_error {
+            thread::sleep(retry_delay(&last_error, attempt, config.retry_delay_ms, config.max_retry_delay_ms));
         }
     }
-    Err(format!(
-        "Translation failed after {} attempts. Last error: {}",
-        max_retries, last_error
-    ))
+    Err(last_error)
+}
+
+/// Reads `term => rendering | note` lines, keeping the spelling of the candidate list.
+fn parse_glossary_reply(reply: &str, candidates: &[TermCandidate]) -> Vec<GlossaryEntry> {
+    let mut entries = Vec::new();
+    for line in reply.lines() {
+        let Some((term, rest)) = line.trim().trim_matches('`').split_once("=>") else {
+            continue;
+        };
+        let term = term.trim().trim_start_matches(['-', '*']).trim();
+        let Some(candidate) = candidates.iter().find(|candidate| candidate.term.eq_ignore_ascii_case(term)) else {
+            continue;
+        };
+        let (rendering, note) = rest.split_once('|').unwrap_or((rest, ""));
+        if rendering.trim().is_empty() {
+            continue;
+        }
+        match GlossaryEntry::new(candidate.term.clone(), rendering.trim().to_string(), note.trim().to_string()) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => LOGGER.warning(&e),
+        }
+    }
+    entries
+}
+
+/// Output limit for the rolling summary request.
+const SUMMARY_MAX_OUTPUT_TOKENS: u32 = 1024;
+
+/// Safety threshold used for the retry after a blocked response.
+const RELAXED_SAFETY_THRESHOLD: &str = "BLOCK_NONE";
+
+/// Why the model stopped, when the same request would stop the same way again.
+enum StopReason {
+    MaxTokens,
+    /// `SAFETY`, `RECITATION`, `PROHIBITED_CONTENT`, ... or a blocked prompt.
+    Blocked(String),
+}
+
+fn stop_reason(resp: &GeminiResponse) -> Option<StopReason> {
+    if let Some(reason) = resp
+        .prompt_feedback
+        .as_ref()
+        .and_then(|feedback| feedback.block_reason.clone())
+    {
+        return Some(StopReason::Blocked(reason));
+    }
+    match resp.candidates.first()?.finish_reason.as_deref()? {
+        "MAX_TOKENS" => Some(StopReason::MaxTokens),
+        reason @ ("SAFETY" | "RECITATION" | "PROHIBITED_CONTENT" | "BLOCKLIST" | "SPII") => {
+            Some(StopReason::Blocked(reason.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Waits as long as a rate-limited response asked for; otherwise exponential backoff
+/// (`base`, `2 * base`, `4 * base`, ... up to `max_retry_delay_ms`) with up to half of each
+/// delay randomly dropped so parallel clients do not retry in lockstep.
+fn retry_delay(error: &ApiError, attempt: u8, base_delay_ms: u64, max_delay_ms: u64) -> Duration {
+    if error.class == ErrorClass::RateLimited {
+        if let Some(retry_after) = error.retry_after {
+            return retry_after;
+        }
+    }
+    let exponential_ms = base_delay_ms
+        .saturating_mul(1u64 << (attempt.saturating_sub(1)).min(20))
+        .min(max_delay_ms);
+    let half = exponential_ms / 2;
+    Duration::from_millis(half + (jitter_fraction() * (exponential_ms - half) as f64) as u64)
+}
+
+/// A pseudo-random number in `[0, 1)`; `RandomState` is seeded differently for every instance.
+fn jitter_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Maps an HTTP status and Gemini's `status` field to how the request should be retried.
+fn classify_error(http_status: Option<u16>, gemini_status: &str) -> ErrorClass {
+    match gemini_status {
+        "RESOURCE_EXHAUSTED" => return ErrorClass::RateLimited,
+        "UNAVAILABLE" | "DEADLINE_EXCEEDED" | "INTERNAL" | "ABORTED" => return ErrorClass::Transient,
+        "INVALID_ARGUMENT" | "FAILED_PRECONDITION" | "PERMISSION_DENIED" | "UNAUTHENTICATED"
+        | "NOT_FOUND" => return ErrorClass::Fatal,
+        _ => {}
+    }
+    match http_status {
+        Some(429) => ErrorClass::RateLimited,
+        Some(408) | Some(500..=599) | None => ErrorClass::Transient,
+        Some(_) => ErrorClass::Fatal,
+    }
 }
 
-fn gemini_api(api_key: &str, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let model_name = "gemini-2.0-flash"; // or "gemini-1.0-pro" or "gemini-1.5-flash-latest" etc.
+/// Reads `RetryInfo.retryDelay` (e.g. `"37s"` or `"1.5s"`) from Gemini error details.
+fn retry_info_delay(details: &[serde_json::Value]) -> Option<Duration> {
+    details
+        .iter()
+        .filter(|detail| {
+            detail
+                .get("@type")
+                .and_then(|t| t.as_str())
+                .is_some_and(|t| t.ends_with("google.rpc.RetryInfo"))
+        })
+        .filter_map(|detail| detail.get("retryDelay")?.as_str())
+        .filter_map(|delay| delay.strip_suffix('s')?.parse::<f64>().ok())
+        .map(|seconds| Duration::from_secs_f64(seconds.max(0.0)))
+        .next()
+}
+
+/// Sends one chunk. With `cached_content` the system instruction comes from that cache
+/// instead of being sent again.
+fn gemini_api(
+    api_key: &str,
+    prompt: &TranslationPrompt,
+    settings: &GenerationSettings,
+    cached_content: Option<&str>,
+) -> Result<String, ApiError> {
+    let model_name = settings.model.as_str();
     let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-        model_name, api_key
+        "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
+        model_name
     );
 
     let body = GeminiRequestBody {
         contents: vec![Content {
             parts: vec![Part {
-                text: prompt.to_owned(),
+                text: prompt.user_content.clone(),
             }],
         }],
+        system_instruction: match cached_content {
+            Some(_) => None,
+            None => Some(Content {
+                parts: vec![Part {
+                    text: prompt.system_instruction.clone(),
+                }],
+            }),
+        },
+        cached_content: cached_content.map(str::to_string),
         generation_config: Some(GenerationConfig {
-            temperature: Some(1.5), // Adjusted for more deterministic technical translation
-            max_output_tokens: Some(8192), // Maximize output tokens
-                                    // candidate_count: Some(1), // Default is 1, explicit for clarity
-                                    // stop_sequences: None, // No specific stop sequences
-                                    // top_p: Some(0.95), // Adjust if needed
-                                    // top_k: Some(40),   // Adjust if needed
+            temperature: Some(settings.temperature as f32),
+            max_output_tokens: Some(settings.max_output_tokens),
+            top_p: settings.top_p.map(|top_p| top_p as f32),
+            top_k: settings.top_k,
+            thinking_config: settings
+                .thinking_budget
+                .map(|thinking_budget| ThinkingConfig { thinking_budget }),
+            response_mime_type: settings
+                .structured_output
+                .then(|| "application/json".to_string()),
+            response_schema: settings.structured_output.then(structured_response_schema),
         }),
+        safety_settings: settings
+            .safety_threshold
+            .as_ref()
+            .map(|threshold| {
+                HARM_CATEGORIES
+                    .iter()
+                    .map(|category| SafetySetting {
+                        category: category.to_string(),
+                        threshold: threshold.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
     };
 
-    let client = blocking::Client::builder()
-        .timeout(Duration::from_secs(360)) // Generous timeout for API
-        .build()?;
+    let client = utils::http_client().map_err(|e| ApiError::new(ErrorClass::Fatal, e))?;
 
     LOGGER.debug(
         format!(
@@ -219,12 +1496,24 @@ fn gemini_api(api_key: &str, prompt: &str) -> Result<String, Box<dyn std::error:
 
     let resp = client
         .post(&url)
+        .timeout(Duration::from_secs(360)) // Generous timeout for API
         .header("Content-Type", "application/json")
+        // Sent as a header so the key never appears in URLs, proxies or error messages.
+        .header("x-goog-api-key", api_key)
         .json(&body)
-        .send()?;
+        .send()
+        .map_err(|e| ApiError::transient(format!("Error communicating with API: {}", e)))?;
 
     let status = resp.status();
-    let text = resp.text()?;
+    let retry_after = resp
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    let text = resp
+        .text()
+        .map_err(|e| ApiError::transient(format!("Error reading API response: {}", e)))?;
     LOGGER.debug(
         format!(
             "API Response Status: {}. Response (partial): {}",
@@ -234,29 +1523,80 @@ fn gemini_api(api_key: &str, prompt: &str) -> Result<String, Box<dyn std::error:
         .as_str(),
     );
 
-    if !status.is_success() {
-        if let Ok(gemini_error) = serde_json::from_str::<GeminiErrorResponse>(&text) {
-            Err(format!(
+    if status.is_success() {
+        return Ok(text);
+    }
+    Err(error_from_response(status, retry_after, &text))
+}
+
+/// Builds the error for a failed response; a `Retry-After` header wins over a `RetryInfo` detail.
+fn error_from_response(status: reqwest::StatusCode, retry_after: Option<Duration>, text: &str) -> ApiError {
+    match serde_json::from_str::<GeminiErrorResponse>(text) {
+        Ok(gemini_error) => ApiError {
+            class: classify_error(Some(status.as_u16()), &gemini_error.error.status),
+            message: format!(
                 "Gemini API Error: (Code: {}) {} - Status: {}",
                 gemini_error.error.code, gemini_error.error.message, gemini_error.error.status
-            )
-            .into())
-        } else {
-            Err(format!("Request failed (Status {}): {}", status, text).into())
-        }
+            ),
+            retry_after: retry_after.or_else(|| retry_info_delay(&gemini_error.error.details)),
+        },
+        Err(_) => ApiError {
+            class: classify_error(Some(status.as_u16()), ""),
+            message: format!("Request failed (Status {}): {}", status, text),
+            retry_after,
+        },
+    }
+}
+
+/// Explains the blocks `TranslationContext` puts before a chunk.
+const CONTEXT_NOTE: &str = "A request may start with a block between CONTEXT and END OF CONTEXT holding a summary of the story so far and the previous lines with their accepted translations. Use it only to keep names, pronouns, forms of address and register consistent; never translate it or include it in your output. A block between GLOSSARY and END OF GLOSSARY lists terms as `source => required translation`; always render those terms exactly that way and never include the block in your output. A block between EXAMPLES and END OF EXAMPLES shows earlier translations of similar lines as `source => translation`; follow their wording and style where they fit, but translate only the lines you are given.";
+
+/// Wording shared by the line-prefix and the structured prompts.
+fn translation_guidelines(target: &str) -> String {
+    format!("Produce a fluent and technically accurate {target} translation. Prioritize translating technical software/programming terms into their common {target} equivalents. Retain in English only essential elements like specific code identifiers (e.g., user_id, calculateTotal, method, string, syntax, ...), programming language names (e.g., Rust, Kotlin, Python, ...), operators, or globally recognized acronyms (e.g., \"HTML\", ...) when their English form is standard in {target} and aids clarity. Text within punctuation should also be translated unless it's one of these essential English elements.
+Translate all other words into fluent {target}.")
+}
+
+/// The fixed instructions, sent as `systemInstruction` (or cached), and the chunk itself.
+pub struct TranslationPrompt {
+    pub system_instruction: String,
+    pub user_content: String,
+}
+
+pub fn build_system_instruction(languages: &LanguagePair, structured: bool) -> String {
+    if structured {
+        build_structured_system_instruction(languages)
     } else {
-        Ok(text)
+        build_prefix_system_instruction(languages)
     }
 }
 
-pub fn build_translation_prompt(input: &str) -> String {
-    format!("You are a translation assistant. When given a text enclosed in triple backticks:
+pub fn build_user_content(chunk_text: &str, structured: bool) -> String {
+    if structured {
+        serde_json::to_string_pretty(&structured_lines(chunk_text)).unwrap_or_default()
+    } else {
+        format!("Now translate the text provided.\n\n```{}```", chunk_text)
+    }
+}
+
+/// Instructions and examples for the line-prefix format; the same for every chunk of a run.
+fn build_prefix_system_instruction(languages: &LanguagePair) -> String {
+    let source = utils::language_name(&languages.source);
+    let target = utils::language_name(&languages.target);
+    let guidelines = translation_guidelines(&target);
+    let context_note = CONTEXT_NOTE;
+    let example_note = if target == "Persian" {
+        String::new()
+    } else {
+        format!(" The example shows a Persian translation; your output must be in {}.", target)
+    };
+    format!("You are a translation assistant. When given {source} text enclosed in triple backticks:
 For each line, output exactly one translated line, in the same order and with the same floating-point line number prefix.
-Produce a fluent and technically accurate Persian translation. Prioritize translating technical software/programming terms into their common Persian equivalents. Retain in English only essential elements like specific code identifiers (e.g., user_id, calculateTotal, method, string, syntax, ...), programming language names (e.g., Rust, Kotlin, Python, ...), operators, or globally recognized acronyms (e.g., \"HTML\", ...) when their English form is standard in Persian and aids clarity. Text within punctuation should also be translated unless it's one of these essential English elements.
-Translate all other words into fluent Persian.
+{guidelines}
 Do not merge, split, add, or remove any lines or line numbers; even if a line contains only one word or is empty, you must reproduce its line number and provide its translation or an empty line as appropriate.
 Ensure that no line is left completely untranslated—every line must include at least one translated word where applicable (excluding purely technical identifiers).
 Do not output anything before or after the translated lines, and do not wrap the translations in a code block this is important, dont use code block, only the translated lines themselves.
+{context_note}
 
 Example input (for illustration only, do not translate this example):
 0.0_Hello World  
@@ -271,7 +1611,7 @@ Example input (for illustration only, do not translate this example):
 5.1_The variable `count` should be an `i32`.
 5.2_Alright, let's get this show on the road!
 
-Expected output format (illustration only):
+Expected output format (illustration only):{example_note}
 0.0_سلام دنیا
 0.1_نام variable: x
 1.0_این یک ویژگی واقعا باحال است، مگه نه؟
@@ -282,11 +1622,86 @@ Expected output format (illustration only):
 4.0_متد [Array.prototype.map()] را در نظر بگیرید.
 5.0_این «راه‌حل سریع»، انگار، کاملاً پایدار نیست.
 5.1_متغیر `count` باید یک `i32` باشد.
-5.2_خیلی خب، بزن بریم!
+5.2_خیلی خب، بزن بریم!")
+}
+
+/// A subtitle line sent to or returned by the model in structured output mode.
+#[derive(Serialize, Deserialize, Debug)]
+struct StructuredLine {
+    id: String,
+    text: String,
+}
+
+/// Splits `<id>_<text>` chunk lines into `{id, text}` items.
+fn structured_lines(chunk_text: &str) -> Vec<StructuredLine> {
+    chunk_text
+        .lines()
+        .map(|line| {
+            let (id, text) = line.split_once('_').unwrap_or((line, ""));
+            StructuredLine {
+                id: id.to_string(),
+                text: text.to_string(),
+            }
+        })
+        .collect()
+}
+
+fn build_structured_system_instruction(languages: &LanguagePair) -> String {
+    let source = utils::language_name(&languages.source);
+    let target = utils::language_name(&languages.target);
+    let guidelines = translation_guidelines(&target);
+    let context_note = CONTEXT_NOTE;
+    format!("You are a translation assistant. You will be given a JSON array of {source} subtitle lines, each with an id and its text.
+Return a JSON array with exactly one item per input item, in the same order and with the same id, whose text is the {target} translation.
+{guidelines}
+Never merge, split, add, or drop items; an empty text stays empty.
+{context_note}")
+}
 
-Now translate the text provided.
+/// JSON schema of the structured response: an array of `{id, text}` items.
+fn structured_response_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "ARRAY",
+        "items": {
+            "type": "OBJECT",
+            "properties": {
+                "id": { "type": "STRING" },
+                "text": { "type": "STRING" }
+            },
+            "required": ["id", "text"],
+            "propertyOrdering": ["id", "text"]
+        }
+    })
+}
 
-```{}```", input)
+/// Parses a structured response and checks that it has exactly the chunk's ids, in order.
+/// Returns the translation in the `<id>_<text>` line format used by the rest of the pipeline.
+fn parse_structured_response(response: &str, chunk_text: &str) -> Result<String, String> {
+    let translated: Vec<StructuredLine> = serde_json::from_str(response.trim())
+        .map_err(|e| format!("Structured response is not valid JSON: {}", e))?;
+    let expected = structured_lines(chunk_text);
+    if translated.len() != expected.len() {
+        return Err(format!(
+            "Structured response has {} items for {} lines.",
+            translated.len(),
+            expected.len()
+        ));
+    }
+    if let Some((sent, received)) = expected
+        .iter()
+        .zip(&translated)
+        .find(|(sent, received)| sent.id != received.id.trim())
+    {
+        return Err(format!(
+            "Structured response has id '{}' where '{}' was expected.",
+            received.id, sent.id
+        ));
+    }
+    Ok(translated
+        .iter()
+        .map(|line| format!("{}_{}", line.id.trim(), line.text.replace('\n', " ")))
+        .collect::<Vec<_>>()
+        .join("\n"))
 }
 
 pub fn extract_prefixed_lines(input: &str) -> String {
@@ -313,27 +1728,13 @@ pub fn extract_prefixed_lines(input: &str) -> String {
     result_lines.join("\n")
 }
 
-fn split_into_chunks(text: &str, chunk_size: usize) -> Vec<String> {
-    if chunk_size == 0 {
-        LOGGER.warning("Chunk size is 0, returning text as a single chunk.");
-        return vec![text.to_string()];
-    }
-    text.lines()
-        .map(|s| s.trim_end()) // Trim trailing whitespace from lines before chunking
-        .filter(|s| !s.is_empty()) // Remove empty lines before chunking
-        .collect::<Vec<&str>>()
-        .chunks(chunk_size)
-        .map(|chunk_lines| chunk_lines.join("\n"))
-        .collect()
-}
-
 fn check_translated_and_orginal_lines(
-    ai_response: &String,
-    orginal_chunk: &String,
+    ai_response: &str,
+    orginal_chunk: &str,
     chunk_index: usize,
     total_chunks: usize,
 ) -> Result<String, String> {
-    let extracted_lines = extract_prefixed_lines(&ai_response);
+    let extracted_lines = extract_prefixed_lines(ai_response);
     if extracted_lines.is_empty() && !ai_response.trim().is_empty() {
         LOGGER.warning(format!("Chunk {}/{} translated, but no lines with numeric prefix were extracted. API response (partial): '{}'", chunk_index, total_chunks, ai_response.chars().take(100).collect::<String>()).as_str());
         // Depending on strictness, this could be an error.
@@ -362,16 +1763,38 @@ fn check_translated_and_orginal_lines(
     }
 
     let translated_lines = count_non_empty_lines(&extracted_lines);
-    let orginal_lines = count_non_empty_lines(&orginal_chunk);
+    let orginal_lines = count_non_empty_lines(orginal_chunk);
 
-    if translated_lines == orginal_lines {
-        Ok(extracted_lines)
-    } else {
-        Err("NotEqual".to_owned())
+    if translated_lines != orginal_lines {
+        return Err("NotEqual".to_owned());
+    }
+    // Ids have gaps once memory hits and repeated lines are left out, so a reply numbered
+    // afresh would still have the right count but put translations on the wrong lines.
+    let (sent_ids, received_ids) = (line_ids(orginal_chunk), line_ids(&extracted_lines));
+    if let Some(missing) = sent_ids.iter().find(|id| !received_ids.contains(id)) {
+        let unexpected = received_ids
+            .iter()
+            .find(|id| !sent_ids.contains(id))
+            .map_or_else(String::new, |id| format!(" (got '{}' instead)", id));
+        return Err(format!(
+            "Chunk {}/{}: the translation has no line '{}'{}.",
+            chunk_index, total_chunks, missing, unexpected
+        ));
     }
+    Ok(extracted_lines)
+}
+
+/// The `<cue>.<line>` ids of `<id>_<text>` lines, sorted.
+fn line_ids(text: &str) -> Vec<&str> {
+    let mut ids: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.trim().split_once('_').map(|(id, _)| id))
+        .collect();
+    ids.sort_unstable();
+    ids
 }
 
-pub fn count_non_empty_lines(text: &String) -> usize {
+pub fn count_non_empty_lines(text: &str) -> usize {
     text.lines().filter(|line| !line.trim().is_empty()).count()
 }
 
@@ -393,16 +1816,29 @@ struct GenerationConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    max_output_tokens: Option<i32>,
-    // candidate_count, top_p, top_k can be added here if needed
-    // #[serde(skip_serializing_if = "Option::is_none")]
-    // candidate_count: Option<i32>,
-    // #[serde(skip_serializing_if = "Option::is_none")]
-    // stop_sequences: Option<Vec<String>>,
-    // #[serde(skip_serializing_if = "Option::is_none")]
-    // top_p: Option<f32>,
-    // #[serde(skip_serializing_if = "Option::is_none")]
-    // top_k: Option<i32>,
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_schema: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ThinkingConfig {
+    thinking_budget: i32,
+}
+
+#[derive(Serialize, Debug)]
+struct SafetySetting {
+    category: String,
+    threshold: String,
 }
 
 #[derive(Serialize, Debug)]
@@ -410,7 +1846,13 @@ struct GenerationConfig {
 struct GeminiRequestBody {
     contents: Vec<Content>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cached_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     generation_config: Option<GenerationConfig>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    safety_settings: Vec<SafetySetting>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -420,18 +1862,28 @@ pub struct GeminiResponse {
     pub candidates: Vec<Candidate>,
     #[serde(default)]
     pub usage_metadata: Option<UsageMetadata>,
+    #[serde(default)]
+    pub prompt_feedback: Option<PromptFeedback>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptFeedback {
+    #[serde(default)]
+    pub block_reason: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Candidate {
+    #[serde(default)] // Missing when the candidate was blocked
     pub content: ContentContainer,
     #[serde(default)]
     pub finish_reason: Option<String>,
     // Add other fields like 'safetyRatings', 'citationMetadata' if needed and available
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 pub struct ContentContainer {
     #[serde(default = "Vec::new")] // Handle cases where parts might be missing
     pub parts: Vec<TextPart>,
@@ -465,7 +1917,102 @@ struct GeminiErrorResponse {
 struct GeminiErrorDetail {
     code: i32,
     message: String,
+    #[serde(default)]
     status: String,
-    // details: Option<Vec<serde_json::Value>>, // For more detailed error info if provided by API
+    #[serde(default)]
+    details: Vec<serde_json::Value>,
 }
 // endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENT: &str = "3.0_Hello\n5.0_Again\n9.0_Bye";
+
+    #[test]
+    fn accepts_reply_with_the_sent_ids() {
+        let reply = "5.0_Encore\n3.0_Bonjour\n9.0_Salut";
+        assert_eq!(check_translated_and_orginal_lines(reply, SENT, 1, 1).unwrap(), reply);
+    }
+
+    #[test]
+    fn rejects_renumbered_reply() {
+        let reply = "0.0_Bonjour\n1.0_Encore\n2.0_Salut";
+        let error = check_translated_and_orginal_lines(reply, SENT, 1, 1).unwrap_err();
+        assert!(error.contains("no line '3.0'"), "{}", error);
+    }
+
+    #[test]
+    fn classifies_errors_by_status() {
+        let cases = [
+            (Some(400), "INVALID_ARGUMENT", ErrorClass::Fatal),
+            (Some(403), "PERMISSION_DENIED", ErrorClass::Fatal),
+            (Some(401), "UNAUTHENTICATED", ErrorClass::Fatal),
+            (Some(404), "", ErrorClass::Fatal),
+            (Some(429), "RESOURCE_EXHAUSTED", ErrorClass::RateLimited),
+            (Some(429), "", ErrorClass::RateLimited),
+            (Some(400), "RESOURCE_EXHAUSTED", ErrorClass::RateLimited),
+            (Some(503), "UNAVAILABLE", ErrorClass::Transient),
+            (Some(504), "DEADLINE_EXCEEDED", ErrorClass::Transient),
+            (Some(500), "", ErrorClass::Transient),
+            (Some(408), "", ErrorClass::Transient),
+            (None, "", ErrorClass::Transient),
+            (Some(500), "INVALID_ARGUMENT", ErrorClass::Fatal),
+        ];
+        for (http_status, gemini_status, expected) in cases {
+            assert_eq!(classify_error(http_status, gemini_status), expected, "{:?} {}", http_status, gemini_status);
+        }
+    }
+
+    #[test]
+    fn reads_retry_info_delays() {
+        let retry_info = |delay: &str| {
+            serde_json::json!({ "@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": delay })
+        };
+        let other = serde_json::json!({ "@type": "type.googleapis.com/google.rpc.QuotaFailure" });
+        assert_eq!(retry_info_delay(&[other.clone(), retry_info("37s")]), Some(Duration::from_secs(37)));
+        assert_eq!(retry_info_delay(&[retry_info("1.5s")]), Some(Duration::from_millis(1500)));
+        assert_eq!(retry_info_delay(&[retry_info("soon")]), None);
+        assert_eq!(retry_info_delay(&[other]), None);
+    }
+
+    #[test]
+    fn retry_after_header_wins_over_retry_info() {
+        let body = r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED",
+            "details": [{"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "37s"}]}}"#;
+        let status = reqwest::StatusCode::TOO_MANY_REQUESTS;
+
+        let error = error_from_response(status, Some(Duration::from_secs(5)), body);
+        assert_eq!(error.class, ErrorClass::RateLimited);
+        assert_eq!(error.retry_after, Some(Duration::from_secs(5)));
+        assert_eq!(retry_delay(&error, 1, 1000, 120_000), Duration::from_secs(5));
+
+        let error = error_from_response(status, None, body);
+        assert_eq!(error.retry_after, Some(Duration::from_secs(37)));
+
+        let error = error_from_response(reqwest::StatusCode::BAD_GATEWAY, None, "<html>Bad gateway</html>");
+        assert_eq!(error.class, ErrorClass::Transient);
+    }
+
+    #[test]
+    fn backoff_doubles_within_jitter_and_stays_bounded() {
+        let error = ApiError::transient("timeout".to_string());
+        for (attempt, full_ms) in [(1, 1000), (2, 2000), (3, 4000), (4, 8000), (5, 10_000), (60, 10_000)] {
+            for _ in 0..50 {
+                let delay = retry_delay(&error, attempt, 1000, 10_000).as_millis() as u64;
+                assert!((full_ms / 2..=full_ms).contains(&delay), "attempt {}: {}ms", attempt, delay);
+            }
+        }
+
+        // A rate-limited error without a requested delay backs off like any other.
+        let error = ApiError::new(ErrorClass::RateLimited, "quota".to_string());
+        assert!(retry_delay(&error, 1, 1000, 10_000) <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rejects_reply_with_a_repeated_id() {
+        let reply = "3.0_Bonjour\n3.0_Encore\n9.0_Salut";
+        assert!(check_translated_and_orginal_lines(reply, SENT, 1, 1).is_err());
+    }
+}
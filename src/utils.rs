@@ -1,11 +1,165 @@
+use std::sync::LazyLock;
+
 use regex::Regex;
+use reqwest::blocking;
+
+/// One HTTP client for the whole run so connections to the API are pooled and reused.
+/// Timeouts are set per request.
+static HTTP_CLIENT: LazyLock<Result<blocking::Client, String>> = LazyLock::new(|| {
+    blocking::Client::builder()
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+});
+
+pub fn http_client() -> Result<&'static blocking::Client, String> {
+    HTTP_CLIENT.as_ref().map_err(String::clone)
+}
+
+/// Source and target language codes (e.g. `en` and `fa`) for a translation run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguagePair {
+    pub source: String,
+    pub target: String,
+}
+
+impl Default for LanguagePair {
+    fn default() -> Self {
+        LanguagePair {
+            source: "en".to_string(),
+            target: "fa".to_string(),
+        }
+    }
+}
+
+const LANGUAGE_NAMES: [(&str, &str); 20] = [
+    ("ar", "Arabic"),
+    ("de", "German"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("id", "Indonesian"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("ur", "Urdu"),
+    ("zh", "Chinese"),
+];
+
+/// Returns the English name of a language code, or the code itself when it is not known.
+pub fn language_name(code: &str) -> String {
+    LANGUAGE_NAMES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map_or_else(|| code.to_string(), |(_, name)| name.to_string())
+}
+
+/// Accepts short codes such as `fa`, `pt-br` or `zh_hant`.
+pub fn is_valid_language_code(code: &str) -> bool {
+    let mut parts = code.split(['-', '_']);
+    parts.next().is_some_and(|primary| {
+        (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_alphabetic())
+    }) && parts.all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Right-to-left scripts get bidi embedding markers around every output line.
+pub fn is_rtl_language(code: &str) -> bool {
+    let primary = code.split(['-', '_']).next().unwrap_or_default();
+    ["ar", "fa", "he", "ur", "ps", "ckb", "yi"]
+        .iter()
+        .any(|rtl| rtl.eq_ignore_ascii_case(primary))
+}
 
-pub fn formated_to_fa_srt_name(name: &str) -> String {
-    let re = Regex::new(r"\.(?:en\.)?srt$").unwrap();
-    re.replace(name, ".fa.srt").to_string()
+fn replace_srt_suffix(name: &str, source_language: &str, suffix: &str) -> String {
+    let re = Regex::new(&format!(r"\.(?:{}\.)?srt$", regex::escape(source_language))).unwrap();
+    re.replace(name, format!(".{}.srt", suffix)).to_string()
 }
 
-pub fn formated_en_srt_name(name: &str) -> String {
-    let re = Regex::new(r"\.(?:en\.)?srt$").unwrap();
-    re.replace(name, ".en.srt").to_string()
+pub fn formated_to_translated_srt_name(name: &str, languages: &LanguagePair) -> String {
+    replace_srt_suffix(name, &languages.source, &languages.target)
+}
+
+/// Whether `name` is a translation or bilingual file into any language, e.g. `Show.fa.srt`
+/// or `Show.fa-en.srt`. A `.<source>.srt` suffix marks an input, not an output.
+pub fn is_translated_srt_name(name: &str, languages: &LanguagePair) -> bool {
+    let Some(stem) = name.to_lowercase().strip_suffix(".srt").map(str::to_string) else {
+        return false;
+    };
+    let Some((_, suffix)) = stem.rsplit_once('.') else {
+        return false;
+    };
+    match suffix.split_once('-') {
+        Some((target, source)) => is_valid_language_code(target) && is_valid_language_code(source),
+        None => is_valid_language_code(suffix) && !suffix.eq_ignore_ascii_case(&languages.source),
+    }
+}
+
+/// Whether `name` is an output folder of translations into some language (`subtitle_fa`).
+pub fn is_translation_dir_name(name: &str) -> bool {
+    name.strip_prefix("subtitle_").is_some_and(is_valid_language_code)
+}
+
+pub fn formated_source_srt_name(name: &str, languages: &LanguagePair) -> String {
+    replace_srt_suffix(name, &languages.source, &languages.source)
+}
+
+pub fn formated_orig_srt_name(name: &str, languages: &LanguagePair) -> String {
+    replace_srt_suffix(name, &languages.source, "orig")
+}
+
+pub fn formated_bilingual_srt_name(name: &str, languages: &LanguagePair) -> String {
+    replace_srt_suffix(
+        name,
+        &languages.source,
+        &format!("{}-{}", languages.target, languages.source),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translated_names_follow_the_target_language() {
+        let languages = LanguagePair {
+            source: "en".to_string(),
+            target: "de".to_string(),
+        };
+        let translated = formated_to_translated_srt_name("Show.en.srt", &languages);
+        assert!(is_translated_srt_name(&translated, &languages));
+        assert!(is_translated_srt_name("Show.de-en.srt", &languages));
+        assert!(is_translated_srt_name("Show.fa.srt", &languages));
+        assert!(is_translated_srt_name("Show.pt-br.srt", &languages));
+        assert!(is_translated_srt_name("Show.fa-en.srt", &languages));
+        assert!(!is_translated_srt_name("Show.en.srt", &languages));
+        assert!(!is_translated_srt_name("Show_fall.srt", &languages));
+        assert!(!is_translated_srt_name("Show.2019.srt", &languages));
+    }
+
+    #[test]
+    fn translation_folders_for_any_language_are_recognised() {
+        assert!(is_translation_dir_name("subtitle_fa"));
+        assert!(is_translation_dir_name("subtitle_zh_hant"));
+        assert!(!is_translation_dir_name("subtitle_converted"));
+        assert!(!is_translation_dir_name("Season 1"));
+    }
+
+    #[test]
+    fn backup_name_replaces_the_source_language_suffix() {
+        let languages = LanguagePair {
+            source: "de".to_string(),
+            target: "fa".to_string(),
+        };
+        assert_eq!(formated_orig_srt_name("Show.de.srt", &languages), "Show.orig.srt");
+        assert_eq!(formated_orig_srt_name("Show.srt", &languages), "Show.orig.srt");
+        assert_eq!(formated_orig_srt_name("Show.en.srt", &languages), "Show.en.orig.srt");
+    }
 }
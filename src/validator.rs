@@ -0,0 +1,349 @@
+use std::{fs, path::Path, sync::LazyLock};
+
+use serde::Serialize;
+
+use crate::logger::Logger;
+use crate::parser;
+use crate::subtitle::{self, Cue};
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Validator"));
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cue: Option<usize>,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FileReport {
+    pub path: String,
+    pub cue_count: usize,
+    pub errors: usize,
+    pub warnings: usize,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ValidationReport {
+    pub files: Vec<FileReport>,
+    pub total_errors: usize,
+    pub total_warnings: usize,
+}
+
+pub struct ValidationOptions {
+    pub max_line_length: usize,
+    pub max_cps: f64,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        ValidationOptions {
+            max_line_length: 55,
+            max_cps: 20.0,
+        }
+    }
+}
+
+pub fn validate_file(path: &Path, options: &ValidationOptions) -> FileReport {
+    let mut issues = Vec::new();
+    let mut cues = Vec::new();
+
+    match fs::read(path) {
+        Ok(bytes) => {
+            let content = check_encoding(&bytes, &mut issues);
+            issues.extend(check_numbering(&parser::srt_block_numbers(&content)));
+            for block in parser::parse_srt_blocks(&content) {
+                match block {
+                    Ok(cue) => cues.push(cue),
+                    Err(e) => issues.push(Issue {
+                        severity: Severity::Error,
+                        kind: "malformed_cue",
+                        cue: None,
+                        message: e,
+                    }),
+                }
+            }
+            issues.extend(validate_cues(&cues, options));
+        }
+        Err(e) => issues.push(Issue {
+            severity: Severity::Error,
+            kind: "unreadable_file",
+            cue: None,
+            message: format!("Failed to read file: {}", e),
+        }),
+    }
+
+    let errors = issues.iter().filter(|i| i.severity == Severity::Error).count();
+    FileReport {
+        path: path.display().to_string(),
+        cue_count: cues.len(),
+        errors,
+        warnings: issues.len() - errors,
+        issues,
+    }
+}
+
+/// Checks the raw bytes and returns the best-effort decoded text for further checks.
+fn check_encoding(bytes: &[u8], issues: &mut Vec<Issue>) -> String {
+    let mut decoded_lossy = false;
+    let content = match String::from_utf8(bytes.to_vec()) {
+        Ok(content) => content,
+        Err(e) => {
+            decoded_lossy = true;
+            issues.push(Issue {
+                severity: Severity::Error,
+                kind: "invalid_utf8",
+                cue: None,
+                message: format!(
+                    "File is not valid UTF-8 (first invalid byte at offset {}); it is probably in a legacy code page such as Windows-1252 or Windows-1256.",
+                    e.utf8_error().valid_up_to()
+                ),
+            });
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    };
+
+    if content.contains('\u{fffd}') && !decoded_lossy {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            kind: "replacement_characters",
+            cue: None,
+            message: "File contains U+FFFD replacement characters from an earlier failed conversion.".to_string(),
+        });
+    }
+    if ["Ã©", "Ã¨", "â€", "Ø§Ù"]
+        .iter()
+        .any(|pattern| content.contains(pattern))
+    {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            kind: "double_encoded",
+            cue: None,
+            message: "Text looks double-encoded (UTF-8 read as a legacy code page).".to_string(),
+        });
+    }
+
+    let crlf_count = content.matches("\r\n").count();
+    let lf_count = content.matches('\n').count();
+    if crlf_count > 0 && crlf_count != lf_count {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            kind: "mixed_line_endings",
+            cue: None,
+            message: format!(
+                "File mixes CRLF ({}) and LF ({}) line endings.",
+                crlf_count,
+                lf_count - crlf_count
+            ),
+        });
+    }
+
+    content
+}
+
+/// Checks the counters of the raw blocks, so a malformed block that is left out of the
+/// cues does not make the next cue look out of sequence.
+fn check_numbering(numbers: &[Option<usize>]) -> Vec<Issue> {
+    numbers
+        .iter()
+        .enumerate()
+        .filter_map(|(position, number)| {
+            let expected = position + 1;
+            match number {
+                Some(number) if *number == expected => None,
+                Some(number) => Some(Issue {
+                    severity: Severity::Warning,
+                    kind: "non_sequential_number",
+                    cue: Some(*number),
+                    message: format!("Cue number {} found where {} was expected.", number, expected),
+                }),
+                None => Some(Issue {
+                    severity: Severity::Warning,
+                    kind: "missing_number",
+                    cue: None,
+                    message: format!("Block {} has no cue number.", expected),
+                }),
+            }
+        })
+        .collect()
+}
+
+pub fn validate_cues(cues: &[Cue], options: &ValidationOptions) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for cue in cues {
+        let duration = cue.duration_ms();
+        if duration <= 0 {
+            issues.push(Issue {
+                severity: Severity::Error,
+                kind: if duration == 0 { "zero_duration" } else { "negative_duration" },
+                cue: Some(cue.index),
+                message: format!("Cue has a duration of {} ms ({}).", duration, cue.timing_line()),
+            });
+        }
+
+        let visible_text = subtitle::strip_markup(&cue.text);
+        if visible_text.trim().is_empty() {
+            issues.push(Issue {
+                severity: Severity::Warning,
+                kind: "empty_cue",
+                cue: Some(cue.index),
+                message: "Cue has no visible text.".to_string(),
+            });
+            continue;
+        }
+
+        for line in visible_text.lines() {
+            let length = line.trim().chars().count();
+            if length > options.max_line_length {
+                issues.push(Issue {
+                    severity: Severity::Warning,
+                    kind: "line_too_long",
+                    cue: Some(cue.index),
+                    message: format!(
+                        "Line has {} characters (limit {}): '{}'",
+                        length,
+                        options.max_line_length,
+                        line.trim()
+                    ),
+                });
+            }
+        }
+
+        if duration > 0 {
+            let characters = visible_text.lines().map(|l| l.trim().chars().count()).sum::<usize>();
+            let cps = characters as f64 / (duration as f64 / 1000.0);
+            if cps > options.max_cps {
+                issues.push(Issue {
+                    severity: Severity::Warning,
+                    kind: "reading_speed",
+                    cue: Some(cue.index),
+                    message: format!(
+                        "Reading speed is {:.1} characters per second (limit {:.1}).",
+                        cps, options.max_cps
+                    ),
+                });
+            }
+        }
+    }
+
+    for pair in cues.windows(2) {
+        let (previous, current) = (&pair[0], &pair[1]);
+        if current.start < previous.start {
+            issues.push(Issue {
+                severity: Severity::Error,
+                kind: "out_of_order",
+                cue: Some(current.index),
+                message: format!(
+                    "Cue starts at {}, before the previous cue {} at {}.",
+                    current.start, previous.index, previous.start
+                ),
+            });
+        } else if current.start < previous.end {
+            issues.push(Issue {
+                severity: Severity::Error,
+                kind: "overlap",
+                cue: Some(current.index),
+                message: format!(
+                    "Cue starts at {}, before the previous cue {} ends at {}.",
+                    current.start, previous.index, previous.end
+                ),
+            });
+        }
+    }
+
+    issues
+}
+
+pub fn build_report(files: Vec<FileReport>) -> ValidationReport {
+    ValidationReport {
+        total_errors: files.iter().map(|f| f.errors).sum(),
+        total_warnings: files.iter().map(|f| f.warnings).sum(),
+        files,
+    }
+}
+
+pub fn print_summary(report: &ValidationReport) {
+    for file in &report.files {
+        if file.issues.is_empty() {
+            LOGGER.success(format!("{}: {} cues, no problems found.", file.path, file.cue_count).as_str());
+            continue;
+        }
+
+        LOGGER.bold(
+            format!(
+                "{}: {} cues, {} error(s), {} warning(s)",
+                file.path, file.cue_count, file.errors, file.warnings
+            )
+            .as_str(),
+        );
+        for issue in &file.issues {
+            let location = issue
+                .cue
+                .map(|cue| format!("cue {}: ", cue))
+                .unwrap_or_default();
+            let message = format!("[{}] {}{}", issue.kind, location, issue.message);
+            match issue.severity {
+                Severity::Error => LOGGER.error(&message),
+                Severity::Warning => LOGGER.warning(&message),
+            }
+        }
+        LOGGER.log("\n");
+    }
+
+    let summary = format!(
+        "Validated {} file(s): {} error(s), {} warning(s).",
+        report.files.len(),
+        report.total_errors,
+        report.total_warnings
+    );
+    if report.total_errors > 0 {
+        LOGGER.bold_message_error(&summary);
+    } else if report.total_warnings > 0 {
+        LOGGER.bold_message_warning(&summary);
+    } else {
+        LOGGER.bold_message_success(&summary);
+    }
+}
+
+pub fn write_report(report: &ValidationReport, report_path: &Path) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(report)
+        .map_err(|e| format!("Failed to serialize validation report: {}", e))?;
+    fs::write(report_path, json).map_err(|e| {
+        format!(
+            "Failed to write validation report to '{}': {}",
+            report_path.display(),
+            e
+        )
+    })?;
+    LOGGER.info(format!("Machine-readable report written to '{}'.", report_path.display()).as_str());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_block_does_not_shift_numbering() {
+        let content = "1\n00:00:01,000 --> 00:00:02,000\nA\n\n2\nbroken timing\nB\n\n3\n00:00:05,000 --> 00:00:06,000\nC\n";
+        assert!(check_numbering(&parser::srt_block_numbers(content)).is_empty());
+    }
+
+    #[test]
+    fn reports_wrong_and_missing_numbers() {
+        let issues = check_numbering(&[Some(1), Some(3), None]);
+        let kinds: Vec<_> = issues.iter().map(|issue| (issue.kind, issue.cue)).collect();
+        assert_eq!(kinds, vec![("non_sequential_number", Some(3)), ("missing_number", None)]);
+    }
+}
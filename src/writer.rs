@@ -1,13 +1,21 @@
-use std::{env, fs, path::PathBuf, sync::LazyLock};
-use crate::utils;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+use crate::config;
+use crate::utils::{self, LanguagePair};
 
 use crate::logger::Logger;
 
 static LOGGER: LazyLock<Logger> = LazyLock::new(|| Logger::new("Writer"));
 
+const REMAINING_LIST_FILE_NAME: &str = "remaining_subtitles.txt";
+
 pub fn write_translated_and_copy_original(
-    original_path: &PathBuf,
+    original_path: &Path,
     srt_content: String,
+    languages: &LanguagePair,
 ) {
     let parent_dir = match original_path.parent() {
         Some(p) => p,
@@ -24,20 +32,12 @@ pub fn write_translated_and_copy_original(
         }
     };
 
-    let target_dir_name = env!("TRANSLATE_TARGET_DIR").parse()
-        .unwrap_or_else(|_| {
-            LOGGER.info(format!("Environment variable '{}' not set. Using default 'Translated_Subtitles'.", "TRANSLATE_TARGET_DIR").as_str());
-            "Translated_Subtitles".to_string()
-        });
-    let original_sub_dir_name = env!("ORIGINAL_SUB_TARGET_DIR").parse()
-        .unwrap_or_else(|_| {
-            LOGGER.info(format!("Environment variable '{}' not set. Using default 'Original_Subtitles_Backup'.", "ORIGINAL_SUB_TARGET_DIR").as_str());
-            "Original_Subtitles_Backup".to_string()
-        });
-
+    let config = config::get();
+    let target_dir_name = config.translation_dir(languages);
+    let original_sub_dir_name = &config.original_sub_target_dir;
 
-    let target_dir_path = parent_dir.join(&target_dir_name);
-    let original_sub_target_dir_path = parent_dir.join(&original_sub_dir_name);
+    let target_dir_path = parent_dir.join(target_dir_name);
+    let original_sub_target_dir_path = parent_dir.join(original_sub_dir_name);
 
     // Create target directory for translated files
     if let Err(e) = fs::create_dir_all(&target_dir_path) {
@@ -52,8 +52,9 @@ pub fn write_translated_and_copy_original(
     }
 
     // Write the translated SRT file
-    let fa_file_name = utils::formated_to_fa_srt_name(file_name.to_string_lossy().as_ref());
-    let target_file_path = target_dir_path.join(fa_file_name);
+    let translated_file_name =
+        utils::formated_to_translated_srt_name(file_name.to_string_lossy().as_ref(), languages);
+    let target_file_path = target_dir_path.join(translated_file_name);
     match fs::write(&target_file_path, srt_content) {
         Ok(_) => {
             LOGGER.success(
@@ -78,9 +79,10 @@ pub fn write_translated_and_copy_original(
     }
 
     // Copy the original subtitle file to the backup directory, only if its creation was successful or already existed.
-    let en_file_name = utils::formated_en_srt_name(file_name.to_string_lossy().as_ref());
+    let source_file_name =
+        utils::formated_source_srt_name(file_name.to_string_lossy().as_ref(), languages);
     if original_sub_target_dir_path.exists() || fs::create_dir_all(&original_sub_target_dir_path).is_ok() {
-        let original_backup_file_path = original_sub_target_dir_path.join(en_file_name);
+        let original_backup_file_path = original_sub_target_dir_path.join(source_file_name);
         match fs::copy(original_path, &original_backup_file_path) {
             Ok(_) => {
                 LOGGER.success(
@@ -105,4 +107,135 @@ pub fn write_translated_and_copy_original(
     } else {
          LOGGER.warning(format!("Skipped copying original subtitle to backup because directory '{}' could not be ensured.", original_sub_target_dir_path.display()).as_str());
     }
-}
\ No newline at end of file
+}
+/// Overwrites a source subtitle with corrected content, keeping the first untouched version
+/// in the backup directory so repeated runs never lose the original.
+pub fn write_adjusted_source(
+    original_path: &Path,
+    srt_content: &str,
+    languages: &LanguagePair,
+) -> Result<(), String> {
+    let parent_dir = original_path
+        .parent()
+        .ok_or_else(|| format!("Cannot access parent directory of '{}'.", original_path.display()))?;
+    let file_name = original_path
+        .file_name()
+        .ok_or_else(|| format!("Cannot access file name of '{}'.", original_path.display()))?;
+
+    let backup_dir_path = parent_dir.join(&config::get().original_sub_target_dir);
+    fs::create_dir_all(&backup_dir_path).map_err(|e| {
+        format!(
+            "Error creating backup directory '{}': {}",
+            backup_dir_path.display(),
+            e
+        )
+    })?;
+
+    let backup_file_path =
+        backup_dir_path.join(utils::formated_orig_srt_name(file_name.to_string_lossy().as_ref(), languages));
+    if backup_file_path.exists() {
+        LOGGER.debug(format!("Keeping existing backup '{}'.", backup_file_path.display()).as_str());
+    } else {
+        fs::copy(original_path, &backup_file_path).map_err(|e| {
+            format!(
+                "Error backing up '{}' to '{}': {}",
+                original_path.display(),
+                backup_file_path.display(),
+                e
+            )
+        })?;
+    }
+
+    fs::write(original_path, srt_content)
+        .map_err(|e| format!("Error writing '{}': {}", original_path.display(), e))?;
+    LOGGER.success(format!("Adjusted subtitle saved to: '{}'", original_path.display()).as_str());
+    Ok(())
+}
+
+/// Writes the dual-language subtitle into the same target directory as the translation.
+pub fn write_bilingual(
+    original_path: &Path,
+    srt_content: &str,
+    languages: &LanguagePair,
+) -> Result<(), String> {
+    let parent_dir = original_path
+        .parent()
+        .ok_or_else(|| format!("Cannot access parent directory of '{}'.", original_path.display()))?;
+    let file_name = original_path
+        .file_name()
+        .ok_or_else(|| format!("Cannot access file name of '{}'.", original_path.display()))?;
+
+    let target_dir_path = parent_dir.join(config::get().translation_dir(languages));
+    fs::create_dir_all(&target_dir_path).map_err(|e| {
+        format!(
+            "Error creating target directory '{}': {}",
+            target_dir_path.display(),
+            e
+        )
+    })?;
+
+    let bilingual_file_path = target_dir_path
+        .join(utils::formated_bilingual_srt_name(file_name.to_string_lossy().as_ref(), languages));
+    fs::write(&bilingual_file_path, srt_content)
+        .map_err(|e| format!("Error writing '{}': {}", bilingual_file_path.display(), e))?;
+    LOGGER.success(
+        format!(
+            "Bilingual subtitle file saved successfully to: '{}'",
+            bilingual_file_path.display()
+        )
+        .as_str(),
+    );
+    Ok(())
+}
+
+/// Writes a format-converted copy of a subtitle into the conversion output directory.
+pub fn write_converted(original_path: &Path, content: &str, extension: &str) -> Result<(), String> {
+    let parent_dir = original_path
+        .parent()
+        .ok_or_else(|| format!("Cannot access parent directory of '{}'.", original_path.display()))?;
+    let file_name = original_path
+        .file_name()
+        .ok_or_else(|| format!("Cannot access file name of '{}'.", original_path.display()))?;
+
+    let target_dir_path = parent_dir.join(&config::get().convert_target_dir);
+    fs::create_dir_all(&target_dir_path).map_err(|e| {
+        format!(
+            "Error creating conversion directory '{}': {}",
+            target_dir_path.display(),
+            e
+        )
+    })?;
+
+    let converted_file_path = target_dir_path.join(Path::new(file_name).with_extension(extension));
+    fs::write(&converted_file_path, content)
+        .map_err(|e| format!("Error writing '{}': {}", converted_file_path.display(), e))?;
+    LOGGER.success(
+        format!(
+            "Converted subtitle file saved successfully to: '{}'",
+            converted_file_path.display()
+        )
+        .as_str(),
+    );
+    Ok(())
+}
+
+/// The folder run artifacts are written to: the input itself, or the folder of an input file.
+pub fn input_folder(input_path: &Path) -> &Path {
+    if input_path.is_dir() {
+        input_path
+    } else {
+        input_path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+/// Lists files a stopped run did not translate, one path per line, next to the scanned input.
+pub fn write_remaining_list(input_path: &Path, remaining: &[PathBuf]) -> Result<PathBuf, String> {
+    let list_path = input_folder(input_path).join(REMAINING_LIST_FILE_NAME);
+    let content: String = remaining
+        .iter()
+        .map(|path| format!("{}\n", path.display()))
+        .collect();
+    fs::write(&list_path, content)
+        .map_err(|e| format!("Error writing '{}': {}", list_path.display(), e))?;
+    Ok(list_path)
+}